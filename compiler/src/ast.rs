@@ -20,6 +20,11 @@ impl File {
             primary_class,
         }
     }
+
+    /// The fully qualified name the primary class is linked under, such as `cocoa.lang.Object`
+    pub fn qualified_name(&self) -> String {
+        self.package_declaration.qualify(&self.primary_class.name)
+    }
 }
 
 pub type PackagePath = Vec<String>;
@@ -34,6 +39,15 @@ impl PackageDeclaration {
     pub fn new(path: PackagePath) -> Self {
         Self { path }
     }
+
+    /// Prefixes a class declared in this package with the package path
+    pub fn qualify(&self, name: &str) -> String {
+        if self.path.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{}.{}", self.path.join("."), name)
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
@@ -258,6 +272,9 @@ pub enum PrimitiveType {
     Unit,
 }
 
+/// The body of a method declared with `@`, the native method bound to its qualified name when linked
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct Native;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub struct MethodDeclaration {
@@ -267,7 +284,7 @@ pub struct MethodDeclaration {
     type_parameters: Vec<TypeParameter>,
     parameters: Vec<Parameter>,
     return_type: Type,
-    body: Either<Option<Statement>, usize>,
+    body: Either<Option<Statement>, Native>,
 }
 
 impl MethodDeclaration {
@@ -278,7 +295,7 @@ impl MethodDeclaration {
         type_parameters: Vec<TypeParameter>,
         parameters: Vec<Parameter>,
         return_type: Type,
-        body: Either<Option<Statement>, usize>,
+        body: Either<Option<Statement>, Native>,
     ) -> Self {
         Self {
             visibility,
@@ -299,7 +316,7 @@ pub struct MethodDeclarationBuilder {
     type_parameters: Vec<TypeParameter>,
     parameters: Vec<Parameter>,
     return_type: Type,
-    body: Either<Option<Statement>, usize>,
+    body: Either<Option<Statement>, Native>,
 }
 
 impl MethodDeclarationBuilder {
@@ -345,7 +362,7 @@ impl MethodDeclarationBuilder {
         self
     }
 
-    pub fn body(mut self, body: Either<Option<Statement>, usize>) -> Self {
+    pub fn body(mut self, body: Either<Option<Statement>, Native>) -> Self {
         self.body = body;
         self
     }
@@ -0,0 +1,985 @@
+//! A textual form of `ClassHeader`s
+//!
+//! ```text
+//! .class this parent Public
+//!
+//! .const this class name
+//! .const parent class parent_name
+//! .const name string "Main"
+//! .const parent_name string "Object"
+//! .const forty_two i32 42
+//! .const main_type type fn() -> i32
+//! .const main_code bytecode
+//!     LoadConstant forty_two
+//!     Goto done
+//! done:
+//!     Return
+//! .end
+//!
+//! .method name main_type main_code Static
+//! ```
+//!
+//! Constants are placed in the pool in the order they are declared and may be referred to by
+//! name or by raw index (`#3`). Jump offsets inside a `bytecode` block may use labels.
+//! `.field name type [= location] flags...`, `.interface info vtable...`, `.string const` and
+//! `.handler method start end handler [class]` describe the remaining tables. Everything after a `;` is a comment.
+
+use std::collections::HashMap;
+
+use crate::{bytecode::{Bytecode, Offset, Type}, class::{ClassFlags, ClassHeader, ClassInfo, ExceptionHandler, FieldFlags, FieldInfo, InterfaceInfo, Method, MethodFlags, MethodInfo, PoolEntry, PoolIndex, TypeInfo, UNBOUND_NATIVE}, CocoaResult, ErrorInfo};
+
+/// Parses the textual form of a class
+pub fn assemble(source: &str) -> CocoaResult<ClassHeader> {
+    Assembler::new(source)?.assemble()
+}
+
+/// Prints a class, linked or unlinked, in the form accepted by `assemble`
+pub fn disassemble(class: &ClassHeader) -> String {
+    let mut out = String::new();
+    let pool_len = class.constants().len();
+    let pool = |index: PoolIndex| pool_name(index, pool_len);
+
+    out.push_str(&format!(".class {} {}", pool(class.get_this_info()), pool(class.get_parent_info())));
+    for (name, _) in class.get_class_flags().iter_names() {
+        out.push(' ');
+        out.push_str(name);
+    }
+    out.push_str("\n\n");
+
+    for (index, entry) in class.constants().iter().enumerate() {
+        out.push_str(&format!(".const {} ", pool(index)));
+        match entry {
+            PoolEntry::U8(value) => out.push_str(&format!("u8 {}", value)),
+            PoolEntry::U16(value) => out.push_str(&format!("u16 {}", value)),
+            PoolEntry::U32(value) => out.push_str(&format!("u32 {}", value)),
+            PoolEntry::U64(value) => out.push_str(&format!("u64 {}", value)),
+            PoolEntry::I8(value) => out.push_str(&format!("i8 {}", value)),
+            PoolEntry::I16(value) => out.push_str(&format!("i16 {}", value)),
+            PoolEntry::I32(value) => out.push_str(&format!("i32 {}", value)),
+            PoolEntry::I64(value) => out.push_str(&format!("i64 {}", value)),
+            PoolEntry::F32(value) => out.push_str(&format!("f32 {:?}", value)),
+            PoolEntry::F64(value) => out.push_str(&format!("f64 {:?}", value)),
+            PoolEntry::Char(value) => out.push_str(&format!("char {:?}", value)),
+            PoolEntry::String(value) => out.push_str(&format!("string {:?}", value)),
+            PoolEntry::ClassInfo(info) => {
+                out.push_str(&format!("class {}", pool(info.name)));
+                if let Some(class_ref) = info.class_ref {
+                    out.push_str(&format!(" ref {}", class_ref));
+                }
+            }
+            PoolEntry::Method(Method::Native(_)) => out.push_str("method native"),
+            PoolEntry::Method(Method::Foreign(index)) => out.push_str(&format!("method foreign {}", index)),
+            PoolEntry::Method(Method::Bytecode(code)) => {
+                out.push_str("bytecode\n");
+                disassemble_code(&mut out, code, &pool);
+                out.push_str(".end");
+            }
+            PoolEntry::TypeInfo(info) => {
+                out.push_str("type ");
+                write_type_info(&mut out, info, &pool);
+            }
+            PoolEntry::Redirect(index) => out.push_str(&format!("redirect {}", pool(*index))),
+            PoolEntry::Reference(reference) => out.push_str(&format!("reference {}", reference)),
+        }
+        out.push('\n');
+    }
+
+    if !class.interfaces().is_empty() {
+        out.push('\n');
+    }
+    for interface in class.interfaces() {
+        out.push_str(&format!(".interface {}", pool(interface.info)));
+        for method in interface.vtable.iter() {
+            out.push_str(&format!(" {}", method));
+        }
+        out.push('\n');
+    }
+
+    if !class.fields().is_empty() {
+        out.push('\n');
+    }
+    for field in class.fields() {
+        out.push_str(&format!(".field {} {}", pool(field.name), pool(field.type_info)));
+        if let Some(location) = field.location {
+            out.push_str(&format!(" = {}", pool(location)));
+        }
+        for (name, _) in field.flags.iter_names() {
+            out.push(' ');
+            out.push_str(name);
+        }
+        out.push('\n');
+    }
+
+    if !class.methods().is_empty() {
+        out.push('\n');
+    }
+    for method in class.methods() {
+        out.push_str(&format!(".method {} {} {}", pool(method.name), pool(method.type_info), pool(method.location)));
+        for (name, _) in method.flags.iter_names() {
+            out.push(' ');
+            out.push_str(name);
+        }
+        out.push('\n');
+    }
+
+    if !class.strings().is_empty() {
+        out.push('\n');
+    }
+    for string in class.strings() {
+        out.push_str(&format!(".string {}\n", pool(*string)));
+    }
+
+    if !class.exception_handlers().is_empty() {
+        out.push('\n');
+    }
+    for handler in class.exception_handlers() {
+        out.push_str(&format!(".handler {} {} {} {}", handler.method, handler.start, handler.end, handler.handler));
+        if let Some(catch) = handler.catch {
+            out.push_str(&format!(" {}", pool(catch)));
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Linked classes point into the global constant pool, so indices outside of the class's own
+/// pool are printed raw
+fn pool_name(index: PoolIndex, pool_len: usize) -> String {
+    if index < pool_len {
+        format!("c{}", index)
+    } else {
+        format!("#{}", index)
+    }
+}
+
+fn disassemble_code(out: &mut String, code: &[Bytecode], pool: &dyn Fn(PoolIndex) -> String) {
+    let mut targets = Vec::new();
+    for (pc, instruction) in code.iter().enumerate() {
+        if let Some(target) = jump_target(pc, instruction, code.len()) {
+            targets.push(target);
+        }
+    }
+
+    for (pc, instruction) in code.iter().enumerate() {
+        if targets.contains(&pc) {
+            out.push_str(&format!("L{}:\n", pc));
+        }
+        let (mnemonic, operands) = split_instruction(instruction);
+        out.push_str("    ");
+        out.push_str(mnemonic);
+        for operand in operands {
+            out.push(' ');
+            match operand {
+                Operand::Pool(index) => out.push_str(&pool(index)),
+                Operand::Index(index) => out.push_str(&index.to_string()),
+                Operand::Local(index) => out.push_str(&index.to_string()),
+                Operand::Type(ty) => out.push_str(&format!("{:?}", ty)),
+                Operand::Offset(offset) => match jump_target(pc, instruction, code.len()) {
+                    Some(target) => out.push_str(&format!("L{}", target)),
+                    None => out.push_str(&format!("{:+}", offset)),
+                },
+            }
+        }
+        out.push('\n');
+    }
+}
+
+fn jump_target(pc: usize, instruction: &Bytecode, len: usize) -> Option<usize> {
+    let (_, operands) = split_instruction(instruction);
+    operands.into_iter().find_map(|operand| match operand {
+        Operand::Offset(offset) => {
+            let target = pc as isize + offset;
+            if target >= 0 && (target as usize) < len {
+                Some(target as usize)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    })
+}
+
+fn write_type_info(out: &mut String, info: &TypeInfo, pool: &dyn Fn(PoolIndex) -> String) {
+    match info {
+        TypeInfo::Unit => out.push_str("unit"),
+        TypeInfo::U8 => out.push_str("u8"),
+        TypeInfo::U16 => out.push_str("u16"),
+        TypeInfo::U32 => out.push_str("u32"),
+        TypeInfo::U64 => out.push_str("u64"),
+        TypeInfo::I8 => out.push_str("i8"),
+        TypeInfo::I16 => out.push_str("i16"),
+        TypeInfo::I32 => out.push_str("i32"),
+        TypeInfo::I64 => out.push_str("i64"),
+        TypeInfo::F32 => out.push_str("f32"),
+        TypeInfo::F64 => out.push_str("f64"),
+        TypeInfo::Char => out.push_str("char"),
+        TypeInfo::Bool => out.push_str("bool"),
+        TypeInfo::String => out.push_str("string"),
+        TypeInfo::Array(inner) => {
+            out.push('[');
+            write_type_info(out, inner, pool);
+            out.push(']');
+        }
+        TypeInfo::Object(index) => out.push_str(&format!("object({})", pool(*index))),
+        TypeInfo::Method { args, ret } => {
+            out.push_str("fn(");
+            for (i, arg) in args.iter().enumerate() {
+                if i != 0 {
+                    out.push_str(", ");
+                }
+                write_type_info(out, arg, pool);
+            }
+            out.push_str(") -> ");
+            write_type_info(out, ret, pool);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    Pool(PoolIndex),
+    Index(usize),
+    Local(u8),
+    Offset(Offset),
+    Type(Type),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OperandKind {
+    Pool,
+    Index,
+    Local,
+    Offset,
+    Type,
+}
+
+fn split_instruction(instruction: &Bytecode) -> (&'static str, Vec<Operand>) {
+    use Bytecode as B;
+    use Operand as O;
+    match *instruction {
+        B::Pop => ("Pop", vec![]),
+        B::PushNull => ("PushNull", vec![]),
+        B::LoadConstant(index) => ("LoadConstant", vec![O::Pool(index)]),
+        B::Dup => ("Dup", vec![]),
+        B::Swap => ("Swap", vec![]),
+        B::StoreLocal(index) => ("StoreLocal", vec![O::Local(index)]),
+        B::LoadLocal(index) => ("LoadLocal", vec![O::Local(index)]),
+        B::Add => ("Add", vec![]),
+        B::Subtract => ("Subtract", vec![]),
+        B::Multiply => ("Multiply", vec![]),
+        B::Divide => ("Divide", vec![]),
+        B::Modulo => ("Modulo", vec![]),
+        B::Negate => ("Negate", vec![]),
+        B::And => ("And", vec![]),
+        B::Or => ("Or", vec![]),
+        B::Xor => ("Xor", vec![]),
+        B::Not => ("Not", vec![]),
+        B::ShiftLeft => ("ShiftLeft", vec![]),
+        B::ShiftRight => ("ShiftRight", vec![]),
+        B::Equal => ("Equal", vec![]),
+        B::Greater => ("Greater", vec![]),
+        B::Less => ("Less", vec![]),
+        B::Convert(ty) => ("Convert", vec![O::Type(ty)]),
+        B::BinaryConvert(ty) => ("BinaryConvert", vec![O::Type(ty)]),
+        B::Goto(offset) => ("Goto", vec![O::Offset(offset)]),
+        B::If(offset) => ("If", vec![O::Offset(offset)]),
+        B::IfNot(offset) => ("IfNot", vec![O::Offset(offset)]),
+        B::IfGreater(offset) => ("IfGreater", vec![O::Offset(offset)]),
+        B::IfGreaterEqual(offset) => ("IfGreaterEqual", vec![O::Offset(offset)]),
+        B::IfLess(offset) => ("IfLess", vec![O::Offset(offset)]),
+        B::IfLessEqual(offset) => ("IfLessEqual", vec![O::Offset(offset)]),
+        B::IfNull(offset) => ("IfNull", vec![O::Offset(offset)]),
+        B::IfNotNull(offset) => ("IfNotNull", vec![O::Offset(offset)]),
+        B::InvokeVirtual(method) => ("InvokeVirtual", vec![O::Index(method)]),
+        B::InvokeVirtualTail(method) => ("InvokeVirtualTail", vec![O::Index(method)]),
+        B::InvokeStatic(class, method) => ("InvokeStatic", vec![O::Pool(class), O::Index(method)]),
+        B::InvokeStaticTail(class, method) => ("InvokeStaticTail", vec![O::Pool(class), O::Index(method)]),
+        B::InvokeInterface(interface, method) => ("InvokeInterface", vec![O::Pool(interface), O::Index(method)]),
+        B::InvokeInterfaceTail(interface, method) => ("InvokeInterfaceTail", vec![O::Pool(interface), O::Index(method)]),
+        B::InvokeInterfaceStatic(class, interface, method) => ("InvokeInterfaceStatic", vec![O::Pool(class), O::Pool(interface), O::Index(method)]),
+        B::InvokeInterfaceStaticTail(class, interface, method) => ("InvokeInterfaceStaticTail", vec![O::Pool(class), O::Pool(interface), O::Index(method)]),
+        B::Return => ("Return", vec![]),
+        B::ReturnUnit => ("ReturnUnit", vec![]),
+        B::Throw => ("Throw", vec![]),
+        B::New(class) => ("New", vec![O::Pool(class)]),
+        B::SetField(class, field) => ("SetField", vec![O::Pool(class), O::Index(field)]),
+        B::GetField(class, field) => ("GetField", vec![O::Pool(class), O::Index(field)]),
+        B::StoreStatic(field) => ("StoreStatic", vec![O::Index(field)]),
+        B::LoadStatic(field) => ("LoadStatic", vec![O::Index(field)]),
+        B::InstanceOf(class) => ("InstanceOf", vec![O::Pool(class)]),
+        B::GetParent => ("GetParent", vec![]),
+        B::NewArray(ty) => ("NewArray", vec![O::Type(ty)]),
+        B::NewTypedArray(type_info) => ("NewTypedArray", vec![O::Pool(type_info)]),
+        B::ArrayGet(ty) => ("ArrayGet", vec![O::Type(ty)]),
+        B::ArraySet(ty) => ("ArraySet", vec![O::Type(ty)]),
+        B::NewString(string) => ("NewString", vec![O::Index(string)]),
+        B::Breakpoint => ("Breakpoint", vec![]),
+        B::Nop => ("Nop", vec![]),
+    }
+}
+
+fn operand_kinds(mnemonic: &str) -> Option<&'static [OperandKind]> {
+    use OperandKind as K;
+    let kinds: &'static [OperandKind] = match mnemonic {
+        "Pop" | "PushNull" | "Dup" | "Swap" | "Add" | "Subtract" | "Multiply" | "Divide" | "Modulo" | "Negate"
+            | "And" | "Or" | "Xor" | "Not" | "ShiftLeft" | "ShiftRight" | "Equal" | "Greater" | "Less"
+            | "Return" | "ReturnUnit" | "Throw" | "GetParent" | "Breakpoint" | "Nop" => &[],
+        "LoadConstant" | "New" | "InstanceOf" | "NewTypedArray" => &[K::Pool],
+        "StoreLocal" | "LoadLocal" => &[K::Local],
+        "Convert" | "BinaryConvert" | "NewArray" | "ArrayGet" | "ArraySet" => &[K::Type],
+        "Goto" | "If" | "IfNot" | "IfGreater" | "IfGreaterEqual" | "IfLess" | "IfLessEqual" | "IfNull" | "IfNotNull" => &[K::Offset],
+        "InvokeVirtual" | "InvokeVirtualTail" | "StoreStatic" | "LoadStatic" | "NewString" => &[K::Index],
+        "InvokeStatic" | "InvokeStaticTail" | "InvokeInterface" | "InvokeInterfaceTail" | "SetField" | "GetField" => &[K::Pool, K::Index],
+        "InvokeInterfaceStatic" | "InvokeInterfaceStaticTail" => &[K::Pool, K::Pool, K::Index],
+        _ => return None,
+    };
+    Some(kinds)
+}
+
+fn build_instruction(mnemonic: &str, operands: &[Operand]) -> Option<Bytecode> {
+    use Bytecode as B;
+    use Operand as O;
+    let code = match (mnemonic, operands) {
+        ("Pop", []) => B::Pop,
+        ("PushNull", []) => B::PushNull,
+        ("LoadConstant", [O::Pool(index)]) => B::LoadConstant(*index),
+        ("Dup", []) => B::Dup,
+        ("Swap", []) => B::Swap,
+        ("StoreLocal", [O::Local(index)]) => B::StoreLocal(*index),
+        ("LoadLocal", [O::Local(index)]) => B::LoadLocal(*index),
+        ("Add", []) => B::Add,
+        ("Subtract", []) => B::Subtract,
+        ("Multiply", []) => B::Multiply,
+        ("Divide", []) => B::Divide,
+        ("Modulo", []) => B::Modulo,
+        ("Negate", []) => B::Negate,
+        ("And", []) => B::And,
+        ("Or", []) => B::Or,
+        ("Xor", []) => B::Xor,
+        ("Not", []) => B::Not,
+        ("ShiftLeft", []) => B::ShiftLeft,
+        ("ShiftRight", []) => B::ShiftRight,
+        ("Equal", []) => B::Equal,
+        ("Greater", []) => B::Greater,
+        ("Less", []) => B::Less,
+        ("Convert", [O::Type(ty)]) => B::Convert(*ty),
+        ("BinaryConvert", [O::Type(ty)]) => B::BinaryConvert(*ty),
+        ("Goto", [O::Offset(offset)]) => B::Goto(*offset),
+        ("If", [O::Offset(offset)]) => B::If(*offset),
+        ("IfNot", [O::Offset(offset)]) => B::IfNot(*offset),
+        ("IfGreater", [O::Offset(offset)]) => B::IfGreater(*offset),
+        ("IfGreaterEqual", [O::Offset(offset)]) => B::IfGreaterEqual(*offset),
+        ("IfLess", [O::Offset(offset)]) => B::IfLess(*offset),
+        ("IfLessEqual", [O::Offset(offset)]) => B::IfLessEqual(*offset),
+        ("IfNull", [O::Offset(offset)]) => B::IfNull(*offset),
+        ("IfNotNull", [O::Offset(offset)]) => B::IfNotNull(*offset),
+        ("InvokeVirtual", [O::Index(method)]) => B::InvokeVirtual(*method),
+        ("InvokeVirtualTail", [O::Index(method)]) => B::InvokeVirtualTail(*method),
+        ("InvokeStatic", [O::Pool(class), O::Index(method)]) => B::InvokeStatic(*class, *method),
+        ("InvokeStaticTail", [O::Pool(class), O::Index(method)]) => B::InvokeStaticTail(*class, *method),
+        ("InvokeInterface", [O::Pool(interface), O::Index(method)]) => B::InvokeInterface(*interface, *method),
+        ("InvokeInterfaceTail", [O::Pool(interface), O::Index(method)]) => B::InvokeInterfaceTail(*interface, *method),
+        ("InvokeInterfaceStatic", [O::Pool(class), O::Pool(interface), O::Index(method)]) => B::InvokeInterfaceStatic(*class, *interface, *method),
+        ("InvokeInterfaceStaticTail", [O::Pool(class), O::Pool(interface), O::Index(method)]) => B::InvokeInterfaceStaticTail(*class, *interface, *method),
+        ("Return", []) => B::Return,
+        ("ReturnUnit", []) => B::ReturnUnit,
+        ("Throw", []) => B::Throw,
+        ("New", [O::Pool(class)]) => B::New(*class),
+        ("SetField", [O::Pool(class), O::Index(field)]) => B::SetField(*class, *field),
+        ("GetField", [O::Pool(class), O::Index(field)]) => B::GetField(*class, *field),
+        ("StoreStatic", [O::Index(field)]) => B::StoreStatic(*field),
+        ("LoadStatic", [O::Index(field)]) => B::LoadStatic(*field),
+        ("InstanceOf", [O::Pool(class)]) => B::InstanceOf(*class),
+        ("GetParent", []) => B::GetParent,
+        ("NewArray", [O::Type(ty)]) => B::NewArray(*ty),
+        ("NewTypedArray", [O::Pool(type_info)]) => B::NewTypedArray(*type_info),
+        ("ArrayGet", [O::Type(ty)]) => B::ArrayGet(*ty),
+        ("ArraySet", [O::Type(ty)]) => B::ArraySet(*ty),
+        ("NewString", [O::Index(string)]) => B::NewString(*string),
+        ("Breakpoint", []) => B::Breakpoint,
+        ("Nop", []) => B::Nop,
+        _ => return None,
+    };
+    Some(code)
+}
+
+fn parse_type(token: &str) -> Option<Type> {
+    let ty = match token {
+        "I8" => Type::I8,
+        "I16" => Type::I16,
+        "I32" => Type::I32,
+        "I64" => Type::I64,
+        "U8" => Type::U8,
+        "U16" => Type::U16,
+        "U32" => Type::U32,
+        "U64" => Type::U64,
+        "F32" => Type::F32,
+        "F64" => Type::F64,
+        "Reference" => Type::Reference,
+        _ => {
+            let size = token.strip_prefix("Char(")?.strip_suffix(')')?;
+            Type::Char(size.parse().ok()?)
+        }
+    };
+    Some(ty)
+}
+
+struct Line<'a> {
+    number: usize,
+    text: &'a str,
+}
+
+struct Assembler<'a> {
+    lines: Vec<Line<'a>>,
+    constants: HashMap<&'a str, PoolIndex>,
+}
+
+fn error(line: &Line, message: impl std::fmt::Display) -> ErrorInfo {
+    ErrorInfo::new(format!("line {}: {}", line.number, message))
+}
+
+/// Removes a trailing `;` comment, ignoring semicolons inside string and char literals
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(q) => {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == q {
+                    quote = None;
+                }
+            }
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                ';' => return &line[..i],
+                _ => {}
+            },
+        }
+    }
+    line
+}
+
+/// Undoes the escaping done by `{:?}` on a string or char, without the surrounding quotes
+fn unescape(text: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => out.push('\n'),
+            'r' => out.push('\r'),
+            't' => out.push('\t'),
+            '0' => out.push('\0'),
+            '\\' => out.push('\\'),
+            '"' => out.push('"'),
+            '\'' => out.push('\''),
+            'u' => {
+                if chars.next()? != '{' {
+                    return None;
+                }
+                let mut hex = String::new();
+                loop {
+                    match chars.next()? {
+                        '}' => break,
+                        c => hex.push(c),
+                    }
+                }
+                out.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+            }
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    match text.find(char::is_whitespace) {
+        Some(i) => (&text[..i], text[i..].trim_start()),
+        None => (text, ""),
+    }
+}
+
+impl<'a> Assembler<'a> {
+    fn new(source: &'a str) -> CocoaResult<Self> {
+        let lines: Vec<Line> = source.lines().enumerate().filter_map(|(i, text)| {
+            let text = strip_comment(text).trim();
+            if text.is_empty() {
+                None
+            } else {
+                Some(Line { number: i + 1, text })
+            }
+        }).collect();
+
+        let mut constants = HashMap::new();
+        let mut in_code = false;
+        for line in lines.iter() {
+            let (directive, rest) = split_word(line.text);
+            if in_code {
+                in_code = directive != ".end";
+                continue;
+            }
+            if directive == ".const" {
+                let (name, rest) = split_word(rest);
+                if name.is_empty() || name.starts_with('#') {
+                    return Err(error(line, format!("invalid constant name '{}'", name)));
+                }
+                if constants.insert(name, constants.len()).is_some() {
+                    return Err(error(line, format!("constant '{}' is declared twice", name)));
+                }
+                in_code = rest == "bytecode";
+            }
+        }
+        if in_code {
+            return Err(ErrorInfo::new("bytecode block is missing its .end"));
+        }
+
+        Ok(Self {
+            lines,
+            constants,
+        })
+    }
+
+    fn pool_ref(&self, line: &Line, token: &str) -> CocoaResult<PoolIndex> {
+        if let Some(index) = token.strip_prefix('#') {
+            return index.parse().map_err(|_| error(line, format!("invalid pool index '{}'", token)));
+        }
+        self.constants.get(token).copied().ok_or_else(|| error(line, format!("unknown constant '{}'", token)))
+    }
+
+    fn number<T: std::str::FromStr>(&self, line: &Line, token: &str) -> CocoaResult<T> {
+        token.parse().map_err(|_| error(line, format!("invalid number '{}'", token)))
+    }
+
+    fn assemble(&self) -> CocoaResult<ClassHeader> {
+        let mut header = None;
+        let mut constants = Vec::new();
+        let mut interfaces = Vec::new();
+        let mut fields = Vec::new();
+        let mut methods = Vec::new();
+        let mut strings = Vec::new();
+        let mut exception_handlers = Vec::new();
+
+        let mut i = 0;
+        while i < self.lines.len() {
+            let line = &self.lines[i];
+            let (directive, rest) = split_word(line.text);
+            let words: Vec<&str> = rest.split_whitespace().collect();
+            match directive {
+                ".class" => {
+                    let [this, parent, flags @ ..] = words.as_slice() else {
+                        return Err(error(line, ".class needs this and parent class infos"));
+                    };
+                    let mut class_flags = ClassFlags::empty();
+                    for flag in flags {
+                        class_flags |= ClassFlags::from_name(flag).ok_or_else(|| error(line, format!("unknown class flag '{}'", flag)))?;
+                    }
+                    header = Some((self.pool_ref(line, this)?, self.pool_ref(line, parent)?, class_flags));
+                }
+                ".const" => {
+                    let (_, rest) = split_word(rest);
+                    if rest == "bytecode" {
+                        let start = i + 1;
+                        let mut end = start;
+                        while split_word(self.lines[end].text).0 != ".end" {
+                            end += 1;
+                        }
+                        constants.push(PoolEntry::Method(Method::Bytecode(self.assemble_code(&self.lines[start..end])?)));
+                        i = end;
+                    } else {
+                        constants.push(self.assemble_constant(line, rest)?);
+                    }
+                }
+                ".interface" => {
+                    let [info, vtable @ ..] = words.as_slice() else {
+                        return Err(error(line, ".interface needs a class info"));
+                    };
+                    let info = self.pool_ref(line, info)?;
+                    let vtable = vtable.iter().map(|method| self.number(line, method)).collect::<CocoaResult<_>>()?;
+                    interfaces.push(InterfaceInfo { info, vtable });
+                }
+                ".field" => {
+                    let [name, type_info, rest @ ..] = words.as_slice() else {
+                        return Err(error(line, ".field needs a name and a type"));
+                    };
+                    let (location, flags) = match rest {
+                        ["=", location, flags @ ..] => (Some(self.pool_ref(line, location)?), flags),
+                        flags => (None, flags),
+                    };
+                    let mut field_flags = FieldFlags::empty();
+                    for flag in flags {
+                        field_flags |= FieldFlags::from_name(flag).ok_or_else(|| error(line, format!("unknown field flag '{}'", flag)))?;
+                    }
+                    fields.push(FieldInfo {
+                        name: self.pool_ref(line, name)?,
+                        flags: field_flags,
+                        type_info: self.pool_ref(line, type_info)?,
+                        location,
+                    });
+                }
+                ".method" => {
+                    let [name, type_info, location, flags @ ..] = words.as_slice() else {
+                        return Err(error(line, ".method needs a name, a type and a location"));
+                    };
+                    let mut method_flags = MethodFlags::empty();
+                    for flag in flags {
+                        method_flags |= MethodFlags::from_name(flag).ok_or_else(|| error(line, format!("unknown method flag '{}'", flag)))?;
+                    }
+                    methods.push(MethodInfo {
+                        flags: method_flags,
+                        name: self.pool_ref(line, name)?,
+                        type_info: self.pool_ref(line, type_info)?,
+                        location: self.pool_ref(line, location)?,
+                    });
+                }
+                ".string" => {
+                    let [string] = words.as_slice() else {
+                        return Err(error(line, ".string needs exactly one constant"));
+                    };
+                    strings.push(self.pool_ref(line, string)?);
+                }
+                ".handler" => {
+                    let (method, start, end, handler, catch) = match words.as_slice() {
+                        [method, start, end, handler] => (method, start, end, handler, None),
+                        [method, start, end, handler, catch] => (method, start, end, handler, Some(self.pool_ref(line, catch)?)),
+                        _ => return Err(error(line, ".handler needs a method, a start and end pc, a handler pc and an optional class info")),
+                    };
+                    exception_handlers.push(ExceptionHandler {
+                        method: self.number(line, method)?,
+                        start: self.number(line, start)?,
+                        end: self.number(line, end)?,
+                        handler: self.number(line, handler)?,
+                        catch,
+                    });
+                }
+                x => return Err(error(line, format!("unknown directive '{}'", x))),
+            }
+            i += 1;
+        }
+
+        let Some((this_info, parent_info, class_flags)) = header else {
+            return Err(ErrorInfo::new("missing .class directive"));
+        };
+
+        let mut class = ClassHeader::new(constants.len(), interfaces.len(), fields.len(), methods.len(), strings.len());
+        class.set_this_info(this_info);
+        class.set_parent_info(parent_info);
+        class.set_class_flags(class_flags);
+        for (i, entry) in constants.into_iter().enumerate() {
+            class.set_constant_pool_entry(i, entry);
+        }
+        for (i, interface) in interfaces.into_iter().enumerate() {
+            class.set_interface(i, interface);
+        }
+        for (i, field) in fields.into_iter().enumerate() {
+            class.set_field(i, field);
+        }
+        for (i, method) in methods.into_iter().enumerate() {
+            class.set_method(i, method);
+        }
+        for (i, string) in strings.into_iter().enumerate() {
+            class.set_string(i, string);
+        }
+        class.set_exception_handlers(exception_handlers);
+        Ok(class)
+    }
+
+    fn assemble_constant(&self, line: &Line, text: &str) -> CocoaResult<PoolEntry> {
+        let (kind, value) = split_word(text);
+        let entry = match kind {
+            "u8" => PoolEntry::U8(self.number(line, value)?),
+            "u16" => PoolEntry::U16(self.number(line, value)?),
+            "u32" => PoolEntry::U32(self.number(line, value)?),
+            "u64" => PoolEntry::U64(self.number(line, value)?),
+            "i8" => PoolEntry::I8(self.number(line, value)?),
+            "i16" => PoolEntry::I16(self.number(line, value)?),
+            "i32" => PoolEntry::I32(self.number(line, value)?),
+            "i64" => PoolEntry::I64(self.number(line, value)?),
+            "f32" => PoolEntry::F32(self.number(line, value)?),
+            "f64" => PoolEntry::F64(self.number(line, value)?),
+            "char" => {
+                let inner = value.strip_prefix('\'').and_then(|value| value.strip_suffix('\''));
+                let text = inner.and_then(unescape).ok_or_else(|| error(line, format!("invalid char literal {}", value)))?;
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => PoolEntry::Char(c),
+                    _ => return Err(error(line, format!("invalid char literal {}", value))),
+                }
+            }
+            "string" => {
+                let inner = value.strip_prefix('"').and_then(|value| value.strip_suffix('"'));
+                let text = inner.and_then(unescape).ok_or_else(|| error(line, format!("invalid string literal {}", value)))?;
+                PoolEntry::String(text)
+            }
+            "class" => {
+                let words: Vec<&str> = value.split_whitespace().collect();
+                match words.as_slice() {
+                    [name] => PoolEntry::ClassInfo(ClassInfo {
+                        name: self.pool_ref(line, name)?,
+                        class_ref: None,
+                    }),
+                    [name, "ref", class_ref] => PoolEntry::ClassInfo(ClassInfo {
+                        name: self.pool_ref(line, name)?,
+                        class_ref: Some(self.number(line, class_ref)?),
+                    }),
+                    _ => return Err(error(line, "expected 'class <name> [ref <reference>]'")),
+                }
+            }
+            "method" => {
+                let (kind, index) = split_word(value);
+                match kind {
+                    "native" if index.is_empty() => PoolEntry::Method(Method::Native(UNBOUND_NATIVE)),
+                    "native" => return Err(error(line, "native methods are bound by their qualified name and take no index")),
+                    "foreign" => PoolEntry::Method(Method::Foreign(self.number(line, index)?)),
+                    x => return Err(error(line, format!("unknown method kind '{}'", x))),
+                }
+            }
+            "type" => {
+                let mut parser = TypeParser {
+                    assembler: self,
+                    line,
+                    text: value,
+                };
+                let info = parser.parse()?;
+                if !parser.text.trim().is_empty() {
+                    return Err(error(line, format!("unexpected '{}' after type", parser.text.trim())));
+                }
+                PoolEntry::TypeInfo(info)
+            }
+            "redirect" => PoolEntry::Redirect(self.pool_ref(line, value)?),
+            "reference" => PoolEntry::Reference(self.number(line, value)?),
+            x => return Err(error(line, format!("unknown constant kind '{}'", x))),
+        };
+        Ok(entry)
+    }
+
+    fn assemble_code(&self, lines: &[Line]) -> CocoaResult<Box<[Bytecode]>> {
+        let mut labels = HashMap::new();
+        let mut instructions = Vec::new();
+        for line in lines {
+            let mut text = line.text;
+            if let Some((label, rest)) = text.split_once(':') {
+                let label = label.trim();
+                if labels.insert(label, instructions.len()).is_some() {
+                    return Err(error(line, format!("label '{}' is declared twice", label)));
+                }
+                text = rest.trim();
+            }
+            if !text.is_empty() {
+                instructions.push((line, text));
+            }
+        }
+
+        let mut code = Vec::new();
+        for (pc, (line, text)) in instructions.into_iter().enumerate() {
+            let mut words = text.split_whitespace();
+            let mnemonic = words.next().expect("Instruction lines are not empty");
+            let kinds = operand_kinds(mnemonic).ok_or_else(|| error(line, format!("unknown instruction '{}'", mnemonic)))?;
+            let words: Vec<&str> = words.collect();
+            if words.len() != kinds.len() {
+                return Err(error(line, format!("{} takes {} operands", mnemonic, kinds.len())));
+            }
+            let mut operands = Vec::new();
+            for (word, kind) in words.into_iter().zip(kinds.iter()) {
+                let operand = match kind {
+                    OperandKind::Pool => Operand::Pool(self.pool_ref(line, word)?),
+                    OperandKind::Index => Operand::Index(self.number(line, word)?),
+                    OperandKind::Local => Operand::Local(self.number(line, word)?),
+                    OperandKind::Type => Operand::Type(parse_type(word).ok_or_else(|| error(line, format!("unknown type '{}'", word)))?),
+                    OperandKind::Offset => match labels.get(word) {
+                        Some(target) => Operand::Offset(*target as Offset - pc as Offset),
+                        None => Operand::Offset(self.number(line, word.trim_start_matches('+'))?),
+                    },
+                };
+                operands.push(operand);
+            }
+            code.push(build_instruction(mnemonic, &operands).expect("Operands match the instruction's kinds"));
+        }
+        Ok(code.into())
+    }
+}
+
+struct TypeParser<'a, 'b> {
+    assembler: &'b Assembler<'a>,
+    line: &'b Line<'a>,
+    text: &'b str,
+}
+
+impl<'b> TypeParser<'_, 'b> {
+    fn eat(&mut self, token: &str) -> bool {
+        let text = self.text.trim_start();
+        match text.strip_prefix(token) {
+            Some(rest) => {
+                self.text = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> CocoaResult<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(error(self.line, format!("expected '{}' in type", token)))
+        }
+    }
+
+    fn word(&mut self) -> &'b str {
+        let text = self.text.trim_start();
+        let end = text.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '#')).unwrap_or(text.len());
+        self.text = &text[end..];
+        &text[..end]
+    }
+
+    fn parse(&mut self) -> CocoaResult<TypeInfo> {
+        if self.eat("[") {
+            let inner = self.parse()?;
+            self.expect("]")?;
+            return Ok(TypeInfo::Array(Box::new(inner)));
+        }
+        let info = match self.word() {
+            "unit" => TypeInfo::Unit,
+            "u8" => TypeInfo::U8,
+            "u16" => TypeInfo::U16,
+            "u32" => TypeInfo::U32,
+            "u64" => TypeInfo::U64,
+            "i8" => TypeInfo::I8,
+            "i16" => TypeInfo::I16,
+            "i32" => TypeInfo::I32,
+            "i64" => TypeInfo::I64,
+            "f32" => TypeInfo::F32,
+            "f64" => TypeInfo::F64,
+            "char" => TypeInfo::Char,
+            "bool" => TypeInfo::Bool,
+            "string" => TypeInfo::String,
+            "object" => {
+                self.expect("(")?;
+                let name = self.word().to_owned();
+                self.expect(")")?;
+                TypeInfo::Object(self.assembler.pool_ref(self.line, &name)?)
+            }
+            "fn" => {
+                self.expect("(")?;
+                let mut args = Vec::new();
+                if !self.eat(")") {
+                    loop {
+                        args.push(self.parse()?);
+                        if self.eat(")") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                self.expect("->")?;
+                let ret = Box::new(self.parse()?);
+                TypeInfo::Method { args, ret }
+            }
+            x => return Err(error(self.line, format!("unknown type '{}'", x))),
+        };
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+        .class this parent Public
+
+        .const this class name
+        .const parent class parent_name ref 3
+        .const name string "Main \"quoted\"; not a comment"
+        .const parent_name string "Object"
+        .const forty_two i32 -42 ; a comment
+        .const letter char 'λ'
+        .const main_type type fn([object(parent)], char) -> unit
+        .const main_code bytecode
+            LoadConstant forty_two
+        top:
+            Convert I64
+            IfNot done
+            InvokeStatic parent 1
+            Goto top
+        done: ReturnUnit
+        .end
+        .const native method native
+        .const far redirect #40
+
+        .interface parent 0 2
+        .field name main_type = forty_two Static Const
+        .method name main_type main_code Public Static
+        .string name
+        .handler 0 1 4 5 parent
+        .handler 0 0 5 5
+    "#;
+
+    #[test]
+    fn test_assemble() {
+        let class = assemble(SOURCE).unwrap();
+        assert_eq!(class.get_this_info(), 0);
+        assert_eq!(class.get_parent_info(), 1);
+        assert_eq!(class.get_class_flags(), ClassFlags::Public);
+        assert_eq!(*class.get_constant_pool_entry(1), PoolEntry::ClassInfo(ClassInfo { name: 3, class_ref: Some(3) }));
+        assert_eq!(*class.get_constant_pool_entry(2), PoolEntry::String("Main \"quoted\"; not a comment".to_owned()));
+        assert_eq!(*class.get_constant_pool_entry(5), PoolEntry::Char('λ'));
+        assert_eq!(*class.get_constant_pool_entry(6), PoolEntry::TypeInfo(TypeInfo::Method {
+            args: vec![TypeInfo::Array(Box::new(TypeInfo::Object(1))), TypeInfo::Char],
+            ret: Box::new(TypeInfo::Unit),
+        }));
+        assert_eq!(*class.get_constant_pool_entry(7), PoolEntry::Method(Method::Bytecode(vec![
+            Bytecode::LoadConstant(4),
+            Bytecode::Convert(Type::I64),
+            Bytecode::IfNot(3),
+            Bytecode::InvokeStatic(1, 1),
+            Bytecode::Goto(-3),
+            Bytecode::ReturnUnit,
+        ].into())));
+        assert_eq!(*class.get_constant_pool_entry(9), PoolEntry::Redirect(40));
+        assert_eq!(*class.get_interface(0), InterfaceInfo { info: 1, vtable: vec![0, 2] });
+        assert_eq!(class.get_field(0).flags, FieldFlags::Static | FieldFlags::Const);
+        assert_eq!(class.get_field(0).location, Some(4));
+        assert_eq!(*class.get_method(0), MethodInfo { flags: MethodFlags::Public | MethodFlags::Static, name: 2, type_info: 6, location: 7 });
+        assert_eq!(class.strings(), &[2]);
+        assert_eq!(class.exception_handlers(), &[
+            ExceptionHandler { method: 0, start: 1, end: 4, handler: 5, catch: Some(1) },
+            ExceptionHandler { method: 0, start: 0, end: 5, handler: 5, catch: None },
+        ]);
+    }
+
+    #[test]
+    fn test_disassemble_round_trip() {
+        let class = assemble(SOURCE).unwrap();
+        let text = disassemble(&class);
+        let again = assemble(&text).unwrap();
+
+        assert_eq!(again.get_this_info(), class.get_this_info());
+        assert_eq!(again.get_parent_info(), class.get_parent_info());
+        assert_eq!(again.get_class_flags(), class.get_class_flags());
+        assert_eq!(again.constants(), class.constants());
+        assert_eq!(again.interfaces(), class.interfaces());
+        assert_eq!(again.fields(), class.fields());
+        assert_eq!(again.methods(), class.methods());
+        assert_eq!(again.strings(), class.strings());
+        assert_eq!(again.exception_handlers(), class.exception_handlers());
+        assert_eq!(disassemble(&again), text);
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        assert!(assemble(".const a i32 1").is_err());
+        assert!(assemble(".class a a\n.const a i32 x").is_err());
+        assert!(assemble(".class a b\n.const a i32 1").is_err());
+        assert!(assemble(".class a a\n.const a bytecode\nGoto nowhere\n.end").is_err());
+        assert!(assemble(".class a a\n.const a bytecode\nReturn").is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::class::PoolIndex;
+use crate::{class::PoolIndex, CocoaResult, ErrorInfo};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Type {
@@ -178,16 +178,22 @@ pub enum Bytecode {
     Return,
     /// Returns Nothing
     ReturnUnit,
+    /// Pops an object and throws it
+    /// This unwinds the stack to the nearest exception handler that catches the object's class
+    /// Anything that is not a `cocoa.lang.Exception` throws a `cocoa.lang.ClassCastException` instead
+    Throw,
     // Object Related
     /// Create a new object
     /// The PoolIndex is the class info of the object to be created
     New(PoolIndex),
     /// Set a field in the current object
-    /// The FieldIndex is the index of the field in the class's field table
-    SetField(FieldIndex),
+    /// The PoolIndex is the class info of the class that declares the field
+    /// The FieldIndex is the index of the field in that class's field table
+    SetField(PoolIndex, FieldIndex),
     /// Get a field from the current object
-    /// The FieldIndex is the index of the field in the class's field table
-    GetField(FieldIndex),
+    /// The PoolIndex is the class info of the class that declares the field
+    /// The FieldIndex is the index of the field in that class's field table
+    GetField(PoolIndex, FieldIndex),
     /// Load a static field
     /// The FieldIndex is the index of the field in the class's field table
     StoreStatic(FieldIndex),
@@ -195,15 +201,19 @@ pub enum Bytecode {
     /// The FieldIndex is the index of the field in the class's field table
     LoadStatic(FieldIndex),
     /// Check if the top value on the stack is an instance of the class
-    /// The PoolIndex is the class info of the class to check against
+    /// The PoolIndex is the class info of the class to check against, or an array type info
+    /// Arrays are covariant, so an array of a class is an instance of an array of its parent
     InstanceOf(PoolIndex),
     /// Get the parent of the current object
-    /// The parent's reference is pushed onto the stack
+    /// The same object viewed as its parent class is pushed onto the stack, virtual calls on it use the parent's methods
     /// This returns null if the object has no parent
     GetParent,
     // Array Related
     /// Create a new array with a specified type
     NewArray(Type),
+    /// Create a new array of references that knows its element type
+    /// The PoolIndex is the array type info, `ArraySet` checks stored values against its element type
+    NewTypedArray(PoolIndex),
     /// Get an element from an array
     /// The index is popped off the stack and the element is pushed back on
     ArrayGet(Type),
@@ -220,3 +230,475 @@ pub enum Bytecode {
     /// No operation
     Nop,
 }
+
+
+/// Encodes a method body as one opcode byte per instruction followed by its operands
+/// Pool, method, field and string indices are unsigned LEB128 and jump offsets are signed LEB128
+/// Offsets are kept in instructions rather than bytes so jumps mean the same thing after decoding
+pub fn encode(code: &[Bytecode]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for instruction in code {
+        instruction.encode_into(&mut bytes);
+    }
+    bytes
+}
+
+/// Decodes a method body produced by `encode`
+pub fn decode(bytes: &[u8]) -> CocoaResult<Box<[Bytecode]>> {
+    let mut code = Vec::new();
+    let mut position = 0;
+    while position < bytes.len() {
+        code.push(Bytecode::decode_from(bytes, &mut position)?);
+    }
+    Ok(code.into())
+}
+
+/// A stable 64-bit FNV-1a hash of the encoded method body, suitable for caching
+pub fn fingerprint(code: &[Bytecode]) -> u64 {
+    encode(code).iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn write_unsigned(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn write_signed(bytes: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn read_byte(bytes: &[u8], position: &mut usize) -> CocoaResult<u8> {
+    let Some(byte) = bytes.get(*position) else {
+        return Err(ErrorInfo::new("Unexpected end of bytecode"));
+    };
+    *position += 1;
+    Ok(*byte)
+}
+
+fn read_unsigned(bytes: &[u8], position: &mut usize) -> CocoaResult<u64> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = read_byte(bytes, position)?;
+        if shift >= 64 || (shift == 63 && byte & 0x7e != 0) {
+            return Err(ErrorInfo::new("LEB128 value overflows 64 bits"));
+        }
+        value |= ((byte & 0x7f) as u64) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+fn read_signed(bytes: &[u8], position: &mut usize) -> CocoaResult<i64> {
+    let mut value = 0i64;
+    let mut shift = 0;
+    loop {
+        let byte = read_byte(bytes, position)?;
+        // The tenth byte only holds the sign bit, so its other bits have to repeat it and it has to be the last
+        if shift >= 64 || (shift == 63 && byte != 0x00 && byte != 0x7f) {
+            return Err(ErrorInfo::new("LEB128 value overflows 64 bits"));
+        }
+        value |= ((byte & 0x7f) as i64) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            if shift < 64 && byte & 0x40 != 0 {
+                value |= -1 << shift;
+            }
+            return Ok(value);
+        }
+    }
+}
+
+fn read_index(bytes: &[u8], position: &mut usize) -> CocoaResult<usize> {
+    let value = read_unsigned(bytes, position)?;
+    usize::try_from(value).map_err(|_| ErrorInfo::new(format!("Index {} does not fit in a usize", value)))
+}
+
+fn read_offset(bytes: &[u8], position: &mut usize) -> CocoaResult<Offset> {
+    let value = read_signed(bytes, position)?;
+    Offset::try_from(value).map_err(|_| ErrorInfo::new(format!("Offset {} does not fit in an isize", value)))
+}
+
+impl Type {
+    fn encode_into(&self, bytes: &mut Vec<u8>) {
+        match self {
+            Type::I8 => bytes.push(0),
+            Type::I16 => bytes.push(1),
+            Type::I32 => bytes.push(2),
+            Type::I64 => bytes.push(3),
+            Type::U8 => bytes.push(4),
+            Type::U16 => bytes.push(5),
+            Type::U32 => bytes.push(6),
+            Type::U64 => bytes.push(7),
+            Type::F32 => bytes.push(8),
+            Type::F64 => bytes.push(9),
+            Type::Char(size) => {
+                bytes.push(10);
+                bytes.push(*size);
+            }
+            Type::Reference => bytes.push(11),
+        }
+    }
+
+    fn decode_from(bytes: &[u8], position: &mut usize) -> CocoaResult<Self> {
+        let ty = match read_byte(bytes, position)? {
+            0 => Type::I8,
+            1 => Type::I16,
+            2 => Type::I32,
+            3 => Type::I64,
+            4 => Type::U8,
+            5 => Type::U16,
+            6 => Type::U32,
+            7 => Type::U64,
+            8 => Type::F32,
+            9 => Type::F64,
+            10 => Type::Char(read_byte(bytes, position)?),
+            11 => Type::Reference,
+            x => return Err(ErrorInfo::new(format!("Invalid type tag {}", x))),
+        };
+        Ok(ty)
+    }
+}
+
+impl Bytecode {
+    /// Appends the encoded form of this instruction to `bytes`
+    pub fn encode_into(&self, bytes: &mut Vec<u8>) {
+        use Bytecode as B;
+        match *self {
+            B::Pop => bytes.push(0),
+            B::PushNull => bytes.push(1),
+            B::LoadConstant(index) => {
+                bytes.push(2);
+                write_unsigned(bytes, index as u64);
+            }
+            B::Dup => bytes.push(3),
+            B::Swap => bytes.push(4),
+            B::StoreLocal(index) => {
+                bytes.push(5);
+                bytes.push(index);
+            }
+            B::LoadLocal(index) => {
+                bytes.push(6);
+                bytes.push(index);
+            }
+            B::Add => bytes.push(7),
+            B::Subtract => bytes.push(8),
+            B::Multiply => bytes.push(9),
+            B::Divide => bytes.push(10),
+            B::Modulo => bytes.push(11),
+            B::Negate => bytes.push(12),
+            B::And => bytes.push(13),
+            B::Or => bytes.push(14),
+            B::Xor => bytes.push(15),
+            B::Not => bytes.push(16),
+            B::ShiftLeft => bytes.push(17),
+            B::ShiftRight => bytes.push(18),
+            B::Equal => bytes.push(19),
+            B::Greater => bytes.push(20),
+            B::Less => bytes.push(21),
+            B::Convert(ty) => {
+                bytes.push(22);
+                ty.encode_into(bytes);
+            }
+            B::BinaryConvert(ty) => {
+                bytes.push(23);
+                ty.encode_into(bytes);
+            }
+            B::Goto(offset) => {
+                bytes.push(24);
+                write_signed(bytes, offset as i64);
+            }
+            B::If(offset) => {
+                bytes.push(25);
+                write_signed(bytes, offset as i64);
+            }
+            B::IfNot(offset) => {
+                bytes.push(26);
+                write_signed(bytes, offset as i64);
+            }
+            B::IfGreater(offset) => {
+                bytes.push(27);
+                write_signed(bytes, offset as i64);
+            }
+            B::IfGreaterEqual(offset) => {
+                bytes.push(28);
+                write_signed(bytes, offset as i64);
+            }
+            B::IfLess(offset) => {
+                bytes.push(29);
+                write_signed(bytes, offset as i64);
+            }
+            B::IfLessEqual(offset) => {
+                bytes.push(30);
+                write_signed(bytes, offset as i64);
+            }
+            B::IfNull(offset) => {
+                bytes.push(31);
+                write_signed(bytes, offset as i64);
+            }
+            B::IfNotNull(offset) => {
+                bytes.push(32);
+                write_signed(bytes, offset as i64);
+            }
+            B::InvokeVirtual(method) => {
+                bytes.push(33);
+                write_unsigned(bytes, method as u64);
+            }
+            B::InvokeVirtualTail(method) => {
+                bytes.push(34);
+                write_unsigned(bytes, method as u64);
+            }
+            B::InvokeStatic(class, method) => {
+                bytes.push(35);
+                write_unsigned(bytes, class as u64);
+                write_unsigned(bytes, method as u64);
+            }
+            B::InvokeStaticTail(class, method) => {
+                bytes.push(36);
+                write_unsigned(bytes, class as u64);
+                write_unsigned(bytes, method as u64);
+            }
+            B::InvokeInterface(interface, method) => {
+                bytes.push(37);
+                write_unsigned(bytes, interface as u64);
+                write_unsigned(bytes, method as u64);
+            }
+            B::InvokeInterfaceTail(interface, method) => {
+                bytes.push(38);
+                write_unsigned(bytes, interface as u64);
+                write_unsigned(bytes, method as u64);
+            }
+            B::InvokeInterfaceStatic(class, interface, method) => {
+                bytes.push(39);
+                write_unsigned(bytes, class as u64);
+                write_unsigned(bytes, interface as u64);
+                write_unsigned(bytes, method as u64);
+            }
+            B::InvokeInterfaceStaticTail(class, interface, method) => {
+                bytes.push(40);
+                write_unsigned(bytes, class as u64);
+                write_unsigned(bytes, interface as u64);
+                write_unsigned(bytes, method as u64);
+            }
+            B::Return => bytes.push(41),
+            B::ReturnUnit => bytes.push(42),
+            B::New(class) => {
+                bytes.push(43);
+                write_unsigned(bytes, class as u64);
+            }
+            B::SetField(class, field) => {
+                bytes.push(44);
+                write_unsigned(bytes, class as u64);
+                write_unsigned(bytes, field as u64);
+            }
+            B::GetField(class, field) => {
+                bytes.push(45);
+                write_unsigned(bytes, class as u64);
+                write_unsigned(bytes, field as u64);
+            }
+            B::StoreStatic(field) => {
+                bytes.push(46);
+                write_unsigned(bytes, field as u64);
+            }
+            B::LoadStatic(field) => {
+                bytes.push(47);
+                write_unsigned(bytes, field as u64);
+            }
+            B::InstanceOf(class) => {
+                bytes.push(48);
+                write_unsigned(bytes, class as u64);
+            }
+            B::GetParent => bytes.push(49),
+            B::NewArray(ty) => {
+                bytes.push(50);
+                ty.encode_into(bytes);
+            }
+            B::ArrayGet(ty) => {
+                bytes.push(51);
+                ty.encode_into(bytes);
+            }
+            B::ArraySet(ty) => {
+                bytes.push(52);
+                ty.encode_into(bytes);
+            }
+            B::NewString(string) => {
+                bytes.push(53);
+                write_unsigned(bytes, string as u64);
+            }
+            B::Breakpoint => bytes.push(54),
+            B::Nop => bytes.push(55),
+            B::Throw => bytes.push(56),
+            B::NewTypedArray(type_info) => {
+                bytes.push(57);
+                write_unsigned(bytes, type_info as u64);
+            }
+        }
+    }
+
+    /// Decodes one instruction starting at `position` and advances `position` past it
+    pub fn decode_from(bytes: &[u8], position: &mut usize) -> CocoaResult<Self> {
+        use Bytecode as B;
+        let code = match read_byte(bytes, position)? {
+            0 => B::Pop,
+            1 => B::PushNull,
+            2 => B::LoadConstant(read_index(bytes, position)?),
+            3 => B::Dup,
+            4 => B::Swap,
+            5 => B::StoreLocal(read_byte(bytes, position)?),
+            6 => B::LoadLocal(read_byte(bytes, position)?),
+            7 => B::Add,
+            8 => B::Subtract,
+            9 => B::Multiply,
+            10 => B::Divide,
+            11 => B::Modulo,
+            12 => B::Negate,
+            13 => B::And,
+            14 => B::Or,
+            15 => B::Xor,
+            16 => B::Not,
+            17 => B::ShiftLeft,
+            18 => B::ShiftRight,
+            19 => B::Equal,
+            20 => B::Greater,
+            21 => B::Less,
+            22 => B::Convert(Type::decode_from(bytes, position)?),
+            23 => B::BinaryConvert(Type::decode_from(bytes, position)?),
+            24 => B::Goto(read_offset(bytes, position)?),
+            25 => B::If(read_offset(bytes, position)?),
+            26 => B::IfNot(read_offset(bytes, position)?),
+            27 => B::IfGreater(read_offset(bytes, position)?),
+            28 => B::IfGreaterEqual(read_offset(bytes, position)?),
+            29 => B::IfLess(read_offset(bytes, position)?),
+            30 => B::IfLessEqual(read_offset(bytes, position)?),
+            31 => B::IfNull(read_offset(bytes, position)?),
+            32 => B::IfNotNull(read_offset(bytes, position)?),
+            33 => B::InvokeVirtual(read_index(bytes, position)?),
+            34 => B::InvokeVirtualTail(read_index(bytes, position)?),
+            35 => B::InvokeStatic(read_index(bytes, position)?, read_index(bytes, position)?),
+            36 => B::InvokeStaticTail(read_index(bytes, position)?, read_index(bytes, position)?),
+            37 => B::InvokeInterface(read_index(bytes, position)?, read_index(bytes, position)?),
+            38 => B::InvokeInterfaceTail(read_index(bytes, position)?, read_index(bytes, position)?),
+            39 => B::InvokeInterfaceStatic(read_index(bytes, position)?, read_index(bytes, position)?, read_index(bytes, position)?),
+            40 => B::InvokeInterfaceStaticTail(read_index(bytes, position)?, read_index(bytes, position)?, read_index(bytes, position)?),
+            41 => B::Return,
+            42 => B::ReturnUnit,
+            43 => B::New(read_index(bytes, position)?),
+            44 => B::SetField(read_index(bytes, position)?, read_index(bytes, position)?),
+            45 => B::GetField(read_index(bytes, position)?, read_index(bytes, position)?),
+            46 => B::StoreStatic(read_index(bytes, position)?),
+            47 => B::LoadStatic(read_index(bytes, position)?),
+            48 => B::InstanceOf(read_index(bytes, position)?),
+            49 => B::GetParent,
+            50 => B::NewArray(Type::decode_from(bytes, position)?),
+            51 => B::ArrayGet(Type::decode_from(bytes, position)?),
+            52 => B::ArraySet(Type::decode_from(bytes, position)?),
+            53 => B::NewString(read_index(bytes, position)?),
+            54 => B::Breakpoint,
+            55 => B::Nop,
+            56 => B::Throw,
+            57 => B::NewTypedArray(read_index(bytes, position)?),
+            x => return Err(ErrorInfo::new(format!("Invalid opcode {}", x))),
+        };
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leb128_round_trip() {
+        for value in [0u64, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let mut bytes = Vec::new();
+            write_unsigned(&mut bytes, value);
+            let mut position = 0;
+            assert_eq!(read_unsigned(&bytes, &mut position).unwrap(), value);
+            assert_eq!(position, bytes.len());
+        }
+        for value in [0i64, 1, -1, 63, 64, -64, -65, i64::MIN, i64::MAX] {
+            let mut bytes = Vec::new();
+            write_signed(&mut bytes, value);
+            let mut position = 0;
+            assert_eq!(read_signed(&bytes, &mut position).unwrap(), value);
+            assert_eq!(position, bytes.len());
+        }
+    }
+
+    #[test]
+    fn test_leb128_overflow() {
+        let mut too_long = vec![0x80u8; 10];
+        too_long.push(0);
+        assert!(read_unsigned(&too_long, &mut 0).is_err());
+        assert!(read_signed(&too_long, &mut 0).is_err());
+
+        let mut past_sign = vec![0xffu8; 9];
+        past_sign.push(0x01);
+        assert!(read_signed(&past_sign, &mut 0).is_err());
+        past_sign[9] = 0x7f;
+        assert_eq!(read_signed(&past_sign, &mut 0).unwrap(), -1);
+    }
+
+    #[test]
+    fn test_bytecode_round_trip() {
+        let code = vec![
+            Bytecode::LoadConstant(300),
+            Bytecode::StoreLocal(255),
+            Bytecode::LoadLocal(0),
+            Bytecode::Convert(Type::Char(4)),
+            Bytecode::BinaryConvert(Type::F64),
+            Bytecode::Goto(-3),
+            Bytecode::IfNotNull(1 << 20),
+            Bytecode::InvokeStatic(1, 2),
+            Bytecode::InvokeInterfaceStaticTail(3, 4, 5),
+            Bytecode::NewArray(Type::Reference),
+            Bytecode::NewTypedArray(6),
+            Bytecode::NewString(7),
+            Bytecode::Throw,
+            Bytecode::ReturnUnit,
+        ];
+        let bytes = encode(&code);
+        assert_eq!(&*decode(&bytes).unwrap(), code.as_slice());
+    }
+
+    #[test]
+    fn test_bytecode_compact() {
+        let bytes = encode(&[Bytecode::LoadConstant(5), Bytecode::Goto(-1), Bytecode::Return]);
+        assert_eq!(bytes, vec![2, 5, 24, 0x7f, 41]);
+    }
+
+    #[test]
+    fn test_bytecode_truncated() {
+        let bytes = encode(&[Bytecode::InvokeStatic(1000, 1000)]);
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(decode(&[0xff]).is_err());
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let a = [Bytecode::LoadConstant(1), Bytecode::Return];
+        let b = [Bytecode::LoadConstant(2), Bytecode::Return];
+        assert_eq!(fingerprint(&a), fingerprint(&a));
+        assert_ne!(fingerprint(&a), fingerprint(&b));
+    }
+}
@@ -6,6 +6,12 @@ use crate::{bytecode::{Bytecode, MethodIndex}, object::Reference};
 pub type PoolIndex = usize;
 pub type NativeMethodIndex = usize;
 
+/// The index a native method has until the linker binds it to the method registered under its qualified name
+pub const UNBOUND_NATIVE: NativeMethodIndex = NativeMethodIndex::MAX;
+
+/// Name of the static `fn() -> unit` method run once before a class is first used
+pub const CLASS_INITIALIZER: &str = "<clinit>";
+
 bitflags::bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
     pub struct ClassFlags: u8 {
@@ -39,16 +45,89 @@ pub enum PoolEntry {
     Reference(Reference),
 }
 
+impl PoolEntry {
+    /// The name of this entry's variant, for use in error messages
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PoolEntry::U8(_) => "U8",
+            PoolEntry::U16(_) => "U16",
+            PoolEntry::U32(_) => "U32",
+            PoolEntry::U64(_) => "U64",
+            PoolEntry::I8(_) => "I8",
+            PoolEntry::I16(_) => "I16",
+            PoolEntry::I32(_) => "I32",
+            PoolEntry::I64(_) => "I64",
+            PoolEntry::F32(_) => "F32",
+            PoolEntry::F64(_) => "F64",
+            PoolEntry::Char(_) => "Char",
+            PoolEntry::String(_) => "String",
+            PoolEntry::ClassInfo(_) => "ClassInfo",
+            PoolEntry::Method(_) => "Method",
+            PoolEntry::TypeInfo(_) => "TypeInfo",
+            PoolEntry::Redirect(_) => "Redirect",
+            PoolEntry::Reference(_) => "Reference",
+        }
+    }
+}
+
+/// `name` points at a `PoolEntry::String` holding the class's fully qualified name, such as `cocoa.lang.Object`
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ClassInfo {
     pub name: PoolIndex,
     pub class_ref: Option<Reference>
 }
 
+/// A class name together with the package it was declared in
+///
+/// Classes without a `package` declaration live in the unnamed package.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct QualifiedName {
+    package: String,
+    name: String,
+}
+
+impl QualifiedName {
+    pub fn new(package: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            package: package.into(),
+            name: name.into(),
+        }
+    }
+
+    /// Splits a dotted name like `cocoa.lang.Object` at its last dot
+    pub fn parse(qualified_name: &str) -> Self {
+        match qualified_name.rsplit_once('.') {
+            Some((package, name)) => Self::new(package, name),
+            None => Self::new("", qualified_name),
+        }
+    }
+
+    pub fn package(&self) -> &str {
+        &self.package
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl std::fmt::Display for QualifiedName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.package.is_empty() {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "{}.{}", self.package, self.name)
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Method {
     Native(NativeMethodIndex),
     Bytecode(Box<[Bytecode]>),
+    /// Forwards to a method of the parent class
+    ///
+    /// Only kept so older class files still load, the linker copies inherited methods into the vtable instead.
     Foreign(MethodIndex),
 }
 
@@ -76,12 +155,57 @@ pub enum TypeInfo {
     },
 }
 
+impl TypeInfo {
+    /// Renders the type with every object type replaced by its class name
+    ///
+    /// `class_name` maps the pool index of an object type to a name, so that signatures from different classes can be compared.
+    pub fn describe(&self, class_name: &dyn Fn(PoolIndex) -> String) -> String {
+        match self {
+            TypeInfo::Unit => "unit".to_owned(),
+            TypeInfo::U8 => "u8".to_owned(),
+            TypeInfo::U16 => "u16".to_owned(),
+            TypeInfo::U32 => "u32".to_owned(),
+            TypeInfo::U64 => "u64".to_owned(),
+            TypeInfo::I8 => "i8".to_owned(),
+            TypeInfo::I16 => "i16".to_owned(),
+            TypeInfo::I32 => "i32".to_owned(),
+            TypeInfo::I64 => "i64".to_owned(),
+            TypeInfo::F32 => "f32".to_owned(),
+            TypeInfo::F64 => "f64".to_owned(),
+            TypeInfo::Char => "char".to_owned(),
+            TypeInfo::Bool => "bool".to_owned(),
+            TypeInfo::String => "string".to_owned(),
+            TypeInfo::Array(element) => format!("[{}]", element.describe(class_name)),
+            TypeInfo::Object(index) => format!("object({})", class_name(*index)),
+            TypeInfo::Method { args, ret } => {
+                let args = args.iter().map(|arg| arg.describe(class_name)).collect::<Vec<_>>();
+                format!("fn({}) -> {}", args.join(", "), ret.describe(class_name))
+            }
+        }
+    }
+
+    /// The number of bytes a value of the type takes up in an object, which is also its alignment
+    pub fn size(&self) -> usize {
+        match self {
+            TypeInfo::Unit => 0,
+            TypeInfo::U8 | TypeInfo::I8 | TypeInfo::Bool => 1,
+            TypeInfo::U16 | TypeInfo::I16 => 2,
+            TypeInfo::U32 | TypeInfo::I32 | TypeInfo::F32 | TypeInfo::Char => 4,
+            TypeInfo::U64 | TypeInfo::I64 | TypeInfo::F64 => 8,
+            TypeInfo::String | TypeInfo::Array(_) | TypeInfo::Object(_) | TypeInfo::Method { .. } => std::mem::size_of::<Reference>(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct FieldInfo {
     pub name: PoolIndex,
     pub flags: FieldFlags,
     pub type_info: PoolIndex,
     /// The location of the field's value in the object
+    ///
+    /// For static fields this is the default value in the constant pool, which the linker
+    /// replaces with the field's slot in the class's static storage.
     pub location: Option<PoolIndex>,
 }
 
@@ -133,6 +257,28 @@ impl Default for InterfaceInfo {
     }
 }
 
+/// A try range of a method and where execution continues when something in it throws
+///
+/// `start..end` and `handler` are pcs in the method's bytecode. `catch` is the class info of the
+/// class to catch, `None` catches everything. Handlers are tried in order, so inner ranges come first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ExceptionHandler {
+    pub method: MethodIndex,
+    pub start: usize,
+    pub end: usize,
+    pub handler: usize,
+    pub catch: Option<PoolIndex>,
+}
+
+/// A slot of a linked class's vtable
+///
+/// `method` indexes the methods of the class at `class_ref`, which is the class that declared the implementation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VTableEntry {
+    pub class_ref: Reference,
+    pub method: MethodIndex,
+}
+
 pub struct ClassHeaderBody {
     this_info: PoolIndex,
     parent_info: PoolIndex,
@@ -142,6 +288,17 @@ pub struct ClassHeaderBody {
     fields: Vec<FieldInfo>,
     methods: Vec<MethodInfo>,
     strings: Vec<PoolIndex>,
+    exception_handlers: Vec<ExceptionHandler>,
+    /// Built by the linker, never written to class files
+    vtable: Vec<VTableEntry>,
+    /// Values of the static fields, allocated by the linker
+    statics: Vec<PoolEntry>,
+    /// Byte offsets of the instance fields in an object, `None` for static fields, computed by the linker
+    field_offsets: Vec<Option<usize>>,
+    /// The number of bytes the fields of an object take up, including those of its parents
+    instance_size: usize,
+    initializer: Option<MethodIndex>,
+    initialized: bool,
 }
 
 impl ClassHeaderBody {
@@ -177,6 +334,13 @@ impl ClassHeaderBody {
             fields,
             methods,
             strings,
+            exception_handlers: Vec::new(),
+            vtable: Vec::new(),
+            statics: Vec::new(),
+            field_offsets: Vec::new(),
+            instance_size: 0,
+            initializer: None,
+            initialized: false,
         }
     }
 
@@ -221,6 +385,16 @@ impl ClassHeader {
         }
     }
 
+    /// The linked parent class, `None` for the root of the hierarchy and for parents that aren't linked yet
+    pub fn get_parent_ref(&self) -> Option<Reference> {
+        let class_ref = |index: PoolIndex| match self.constants().get(index) {
+            Some(PoolEntry::ClassInfo(ClassInfo { class_ref, .. })) => *class_ref,
+            _ => None,
+        };
+        // The root class names itself as its parent
+        class_ref(self.get_parent_info()).filter(|parent_ref| Some(*parent_ref) != class_ref(self.get_this_info()))
+    }
+
     pub fn set_class_flags(&mut self, class_flags: ClassFlags) {
         unsafe {
             (*self.0).class_flags = class_flags;
@@ -387,6 +561,103 @@ impl ClassHeader {
         }
     }
 
+    pub fn exception_handlers(&self) -> &[ExceptionHandler] {
+        unsafe {
+            &(*self.0).exception_handlers
+        }
+    }
+
+    pub fn set_exception_handlers(&mut self, exception_handlers: Vec<ExceptionHandler>) {
+        unsafe {
+            (*self.0).exception_handlers = exception_handlers;
+        }
+    }
+
+    pub fn vtable(&self) -> &[VTableEntry] {
+        unsafe {
+            &(*self.0).vtable
+        }
+    }
+
+    pub fn set_vtable(&mut self, vtable: Vec<VTableEntry>) {
+        unsafe {
+            (*self.0).vtable = vtable;
+        }
+    }
+
+    pub fn statics(&self) -> &[PoolEntry] {
+        unsafe {
+            &(*self.0).statics
+        }
+    }
+
+    pub fn set_statics(&mut self, statics: Vec<PoolEntry>) {
+        unsafe {
+            (*self.0).statics = statics;
+        }
+    }
+
+    pub fn get_static(&self, slot: usize) -> &PoolEntry {
+        unsafe {
+            &(&(*self.0).statics)[slot]
+        }
+    }
+
+    pub fn set_static(&mut self, slot: usize, value: PoolEntry) {
+        unsafe {
+            (&mut (*self.0).statics)[slot] = value;
+        }
+    }
+
+    pub fn field_offsets(&self) -> &[Option<usize>] {
+        unsafe {
+            &(*self.0).field_offsets
+        }
+    }
+
+    /// The byte offset of an instance field, `None` for static fields and unlinked classes
+    pub fn get_field_offset(&self, index: usize) -> Option<usize> {
+        self.field_offsets().get(index).copied().flatten()
+    }
+
+    pub fn instance_size(&self) -> usize {
+        unsafe {
+            (*self.0).instance_size
+        }
+    }
+
+    pub fn set_field_layout(&mut self, field_offsets: Vec<Option<usize>>, instance_size: usize) {
+        unsafe {
+            (*self.0).field_offsets = field_offsets;
+            (*self.0).instance_size = instance_size;
+        }
+    }
+
+    /// The index of the class's `CLASS_INITIALIZER` method, set by the linker
+    pub fn get_initializer(&self) -> Option<MethodIndex> {
+        unsafe {
+            (*self.0).initializer
+        }
+    }
+
+    pub fn set_initializer(&mut self, initializer: Option<MethodIndex>) {
+        unsafe {
+            (*self.0).initializer = initializer;
+        }
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        unsafe {
+            (*self.0).initialized
+        }
+    }
+
+    pub fn set_initialized(&mut self, initialized: bool) {
+        unsafe {
+            (*self.0).initialized = initialized;
+        }
+    }
+
 }
 
 #[cfg(test)]
@@ -395,6 +666,18 @@ mod tests {
     use crate::class::{FieldInfo, MethodInfo, PoolEntry, MethodFlags};
     use super::*;
 
+    #[test]
+    fn test_qualified_name() {
+        let name = QualifiedName::parse("cocoa.lang.Object");
+        assert_eq!(name.package(), "cocoa.lang");
+        assert_eq!(name.name(), "Object");
+        assert_eq!(name.to_string(), "cocoa.lang.Object");
+
+        let name = QualifiedName::parse("Main");
+        assert_eq!(name, QualifiedName::new("", "Main"));
+        assert_eq!(name.to_string(), "Main");
+    }
+
     #[test]
     fn test_class_header_creation() {
         let _ = ClassHeader::new(10, 5, 3, 4, 0);
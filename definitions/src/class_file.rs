@@ -0,0 +1,686 @@
+use std::{collections::HashMap, io::{Read, Write}};
+
+use crate::{bytecode, class::{ClassFlags, ClassHeader, ClassInfo, ExceptionHandler, FieldFlags, FieldInfo, InterfaceInfo, Method, MethodFlags, MethodInfo, PoolEntry, TypeInfo}, CocoaResult, ErrorInfo};
+
+/// The first four bytes of every `.cocoac` file
+pub const MAGIC: [u8; 4] = [0xC0, 0xC0, 0xA0, 0xCF];
+/// The version of the class file format that this module reads and writes
+pub const VERSION: u16 = 4;
+/// The file extension used for compiled classes
+pub const EXTENSION: &str = "cocoac";
+/// The first four bytes of every `.cocoaa` archive
+pub const ARCHIVE_MAGIC: [u8; 4] = [0xC0, 0xC0, 0xA0, 0xAA];
+/// The file extension used for archives of compiled classes
+pub const ARCHIVE_EXTENSION: &str = "cocoaa";
+
+const POOL_U8: u8 = 0;
+const POOL_U16: u8 = 1;
+const POOL_U32: u8 = 2;
+const POOL_U64: u8 = 3;
+const POOL_I8: u8 = 4;
+const POOL_I16: u8 = 5;
+const POOL_I32: u8 = 6;
+const POOL_I64: u8 = 7;
+const POOL_F32: u8 = 8;
+const POOL_F64: u8 = 9;
+const POOL_CHAR: u8 = 10;
+const POOL_STRING: u8 = 11;
+const POOL_CLASS_INFO: u8 = 12;
+const POOL_METHOD: u8 = 13;
+const POOL_TYPE_INFO: u8 = 14;
+const POOL_REDIRECT: u8 = 15;
+const POOL_REFERENCE: u8 = 16;
+
+const METHOD_NATIVE: u8 = 0;
+const METHOD_BYTECODE: u8 = 1;
+const METHOD_FOREIGN: u8 = 2;
+
+const TYPE_UNIT: u8 = 0;
+const TYPE_U8: u8 = 1;
+const TYPE_U16: u8 = 2;
+const TYPE_U32: u8 = 3;
+const TYPE_U64: u8 = 4;
+const TYPE_I8: u8 = 5;
+const TYPE_I16: u8 = 6;
+const TYPE_I32: u8 = 7;
+const TYPE_I64: u8 = 8;
+const TYPE_F32: u8 = 9;
+const TYPE_F64: u8 = 10;
+const TYPE_CHAR: u8 = 11;
+const TYPE_BOOL: u8 = 12;
+const TYPE_STRING: u8 = 13;
+const TYPE_ARRAY: u8 = 14;
+const TYPE_OBJECT: u8 = 15;
+const TYPE_METHOD: u8 = 16;
+
+/// Writes a class header in the `.cocoac` format
+pub fn write_class<W: Write>(writer: &mut W, class: &ClassHeader) -> CocoaResult<()> {
+    let mut writer = ClassWriter { writer };
+    writer.write_class(class)
+}
+
+/// Reads a class header from the `.cocoac` format
+pub fn read_class<R: Read>(reader: &mut R) -> CocoaResult<ClassHeader> {
+    let mut reader = ClassReader { reader };
+    reader.read_class()
+}
+
+pub fn class_to_bytes(class: &ClassHeader) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    write_class(&mut bytes, class).expect("Writing to a Vec can't fail");
+    bytes
+}
+
+pub fn class_from_bytes(mut bytes: &[u8]) -> CocoaResult<ClassHeader> {
+    read_class(&mut bytes)
+}
+
+/// Writes classes into a `.cocoaa` archive under their fully qualified names
+pub fn write_archive<W: Write>(writer: &mut W, classes: &[(&str, ClassHeader)]) -> CocoaResult<()> {
+    let mut writer = ClassWriter { writer };
+    writer.write_archive(classes)
+}
+
+/// Reads a `.cocoaa` archive, returning the encoded bytes of each class by name
+///
+/// Classes are left encoded so that only the ones that are used need to be decoded with `class_from_bytes`.
+pub fn read_archive<R: Read>(reader: &mut R) -> CocoaResult<HashMap<String, Vec<u8>>> {
+    let mut reader = ClassReader { reader };
+    reader.read_archive()
+}
+
+struct ClassWriter<'a, W: Write> {
+    writer: &'a mut W,
+}
+
+impl<W: Write> ClassWriter<'_, W> {
+    fn write_bytes(&mut self, bytes: &[u8]) -> CocoaResult<()> {
+        self.writer.write_all(bytes).map_err(|error| ErrorInfo::new(format!("Failed to write class: {}", error)))
+    }
+
+    fn write_u8(&mut self, value: u8) -> CocoaResult<()> {
+        self.write_bytes(&[value])
+    }
+
+    fn write_u16(&mut self, value: u16) -> CocoaResult<()> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    fn write_u64(&mut self, value: u64) -> CocoaResult<()> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    fn write_index(&mut self, value: usize) -> CocoaResult<()> {
+        self.write_u64(value as u64)
+    }
+
+    fn write_option(&mut self, value: Option<usize>) -> CocoaResult<()> {
+        match value {
+            Some(value) => {
+                self.write_u8(1)?;
+                self.write_index(value)
+            }
+            None => self.write_u8(0),
+        }
+    }
+
+    fn write_string(&mut self, value: &str) -> CocoaResult<()> {
+        self.write_index(value.len())?;
+        self.write_bytes(value.as_bytes())
+    }
+
+    fn write_archive(&mut self, classes: &[(&str, ClassHeader)]) -> CocoaResult<()> {
+        self.write_bytes(&ARCHIVE_MAGIC)?;
+        self.write_u16(VERSION)?;
+        self.write_index(classes.len())?;
+        for (name, class) in classes {
+            self.write_string(name)?;
+            let bytes = class_to_bytes(class);
+            self.write_index(bytes.len())?;
+            self.write_bytes(&bytes)?;
+        }
+        Ok(())
+    }
+
+    fn write_class(&mut self, class: &ClassHeader) -> CocoaResult<()> {
+        self.write_bytes(&MAGIC)?;
+        self.write_u16(VERSION)?;
+        self.write_index(class.get_this_info())?;
+        self.write_index(class.get_parent_info())?;
+        self.write_u8(class.get_class_flags().bits())?;
+
+        self.write_index(class.constants().len())?;
+        for entry in class.constants() {
+            self.write_pool_entry(entry)?;
+        }
+
+        self.write_index(class.interfaces().len())?;
+        for interface in class.interfaces() {
+            self.write_index(interface.info)?;
+            self.write_index(interface.vtable.len())?;
+            for method in interface.vtable.iter() {
+                self.write_index(*method)?;
+            }
+        }
+
+        self.write_index(class.fields().len())?;
+        for field in class.fields() {
+            self.write_index(field.name)?;
+            self.write_u8(field.flags.bits())?;
+            self.write_index(field.type_info)?;
+            self.write_option(field.location)?;
+        }
+
+        self.write_index(class.methods().len())?;
+        for method in class.methods() {
+            self.write_u8(method.flags.bits())?;
+            self.write_index(method.name)?;
+            self.write_index(method.type_info)?;
+            self.write_index(method.location)?;
+        }
+
+        self.write_index(class.strings().len())?;
+        for string in class.strings() {
+            self.write_index(*string)?;
+        }
+
+        self.write_index(class.exception_handlers().len())?;
+        for handler in class.exception_handlers() {
+            self.write_index(handler.method)?;
+            self.write_index(handler.start)?;
+            self.write_index(handler.end)?;
+            self.write_index(handler.handler)?;
+            self.write_option(handler.catch)?;
+        }
+        Ok(())
+    }
+
+    fn write_pool_entry(&mut self, entry: &PoolEntry) -> CocoaResult<()> {
+        match entry {
+            PoolEntry::U8(value) => {
+                self.write_u8(POOL_U8)?;
+                self.write_bytes(&value.to_le_bytes())
+            }
+            PoolEntry::U16(value) => {
+                self.write_u8(POOL_U16)?;
+                self.write_bytes(&value.to_le_bytes())
+            }
+            PoolEntry::U32(value) => {
+                self.write_u8(POOL_U32)?;
+                self.write_bytes(&value.to_le_bytes())
+            }
+            PoolEntry::U64(value) => {
+                self.write_u8(POOL_U64)?;
+                self.write_bytes(&value.to_le_bytes())
+            }
+            PoolEntry::I8(value) => {
+                self.write_u8(POOL_I8)?;
+                self.write_bytes(&value.to_le_bytes())
+            }
+            PoolEntry::I16(value) => {
+                self.write_u8(POOL_I16)?;
+                self.write_bytes(&value.to_le_bytes())
+            }
+            PoolEntry::I32(value) => {
+                self.write_u8(POOL_I32)?;
+                self.write_bytes(&value.to_le_bytes())
+            }
+            PoolEntry::I64(value) => {
+                self.write_u8(POOL_I64)?;
+                self.write_bytes(&value.to_le_bytes())
+            }
+            PoolEntry::F32(value) => {
+                self.write_u8(POOL_F32)?;
+                self.write_bytes(&value.to_le_bytes())
+            }
+            PoolEntry::F64(value) => {
+                self.write_u8(POOL_F64)?;
+                self.write_bytes(&value.to_le_bytes())
+            }
+            PoolEntry::Char(value) => {
+                self.write_u8(POOL_CHAR)?;
+                self.write_bytes(&(*value as u32).to_le_bytes())
+            }
+            PoolEntry::String(value) => {
+                self.write_u8(POOL_STRING)?;
+                self.write_string(value)
+            }
+            PoolEntry::ClassInfo(info) => {
+                self.write_u8(POOL_CLASS_INFO)?;
+                self.write_index(info.name)?;
+                self.write_option(info.class_ref)
+            }
+            PoolEntry::Method(method) => {
+                self.write_u8(POOL_METHOD)?;
+                self.write_method(method)
+            }
+            PoolEntry::TypeInfo(info) => {
+                self.write_u8(POOL_TYPE_INFO)?;
+                self.write_type_info(info)
+            }
+            PoolEntry::Redirect(index) => {
+                self.write_u8(POOL_REDIRECT)?;
+                self.write_index(*index)
+            }
+            PoolEntry::Reference(reference) => {
+                self.write_u8(POOL_REFERENCE)?;
+                self.write_index(*reference)
+            }
+        }
+    }
+
+    fn write_method(&mut self, method: &Method) -> CocoaResult<()> {
+        match method {
+            Method::Native(index) => {
+                self.write_u8(METHOD_NATIVE)?;
+                self.write_index(*index)
+            }
+            Method::Bytecode(code) => {
+                self.write_u8(METHOD_BYTECODE)?;
+                let bytes = bytecode::encode(code);
+                self.write_index(bytes.len())?;
+                self.write_bytes(&bytes)
+            }
+            Method::Foreign(index) => {
+                self.write_u8(METHOD_FOREIGN)?;
+                self.write_index(*index)
+            }
+        }
+    }
+
+    fn write_type_info(&mut self, info: &TypeInfo) -> CocoaResult<()> {
+        match info {
+            TypeInfo::Unit => self.write_u8(TYPE_UNIT),
+            TypeInfo::U8 => self.write_u8(TYPE_U8),
+            TypeInfo::U16 => self.write_u8(TYPE_U16),
+            TypeInfo::U32 => self.write_u8(TYPE_U32),
+            TypeInfo::U64 => self.write_u8(TYPE_U64),
+            TypeInfo::I8 => self.write_u8(TYPE_I8),
+            TypeInfo::I16 => self.write_u8(TYPE_I16),
+            TypeInfo::I32 => self.write_u8(TYPE_I32),
+            TypeInfo::I64 => self.write_u8(TYPE_I64),
+            TypeInfo::F32 => self.write_u8(TYPE_F32),
+            TypeInfo::F64 => self.write_u8(TYPE_F64),
+            TypeInfo::Char => self.write_u8(TYPE_CHAR),
+            TypeInfo::Bool => self.write_u8(TYPE_BOOL),
+            TypeInfo::String => self.write_u8(TYPE_STRING),
+            TypeInfo::Array(inner) => {
+                self.write_u8(TYPE_ARRAY)?;
+                self.write_type_info(inner)
+            }
+            TypeInfo::Object(index) => {
+                self.write_u8(TYPE_OBJECT)?;
+                self.write_index(*index)
+            }
+            TypeInfo::Method { args, ret } => {
+                self.write_u8(TYPE_METHOD)?;
+                self.write_index(args.len())?;
+                for arg in args {
+                    self.write_type_info(arg)?;
+                }
+                self.write_type_info(ret)
+            }
+        }
+    }
+}
+
+struct ClassReader<'a, R: Read> {
+    reader: &'a mut R,
+}
+
+impl<R: Read> ClassReader<'_, R> {
+    fn read_bytes<const N: usize>(&mut self) -> CocoaResult<[u8; N]> {
+        let mut bytes = [0; N];
+        self.reader.read_exact(&mut bytes).map_err(|error| ErrorInfo::new(format!("Failed to read class: {}", error)))?;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> CocoaResult<u8> {
+        Ok(self.read_bytes::<1>()?[0])
+    }
+
+    fn read_u16(&mut self) -> CocoaResult<u16> {
+        Ok(u16::from_le_bytes(self.read_bytes()?))
+    }
+
+    fn read_u64(&mut self) -> CocoaResult<u64> {
+        Ok(u64::from_le_bytes(self.read_bytes()?))
+    }
+
+    fn read_index(&mut self) -> CocoaResult<usize> {
+        let value = self.read_u64()?;
+        usize::try_from(value).map_err(|_| ErrorInfo::new(format!("Index {} does not fit in a usize", value)))
+    }
+
+    fn read_option(&mut self) -> CocoaResult<Option<usize>> {
+        match self.read_u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(self.read_index()?)),
+            x => Err(ErrorInfo::new(format!("Invalid option tag {}", x))),
+        }
+    }
+
+    fn read_byte_vec(&mut self) -> CocoaResult<Vec<u8>> {
+        let len = self.read_index()?;
+        let mut bytes = Vec::new();
+        self.reader.by_ref().take(len as u64).read_to_end(&mut bytes).map_err(|error| ErrorInfo::new(format!("Failed to read class: {}", error)))?;
+        if bytes.len() != len {
+            return Err(ErrorInfo::new("Unexpected end of class file"));
+        }
+        Ok(bytes)
+    }
+
+    fn read_string(&mut self) -> CocoaResult<String> {
+        let bytes = self.read_byte_vec()?;
+        String::from_utf8(bytes).map_err(|_| ErrorInfo::new("String is not valid UTF-8"))
+    }
+
+    fn read_archive(&mut self) -> CocoaResult<HashMap<String, Vec<u8>>> {
+        let magic = self.read_bytes::<4>()?;
+        if magic != ARCHIVE_MAGIC {
+            return Err(ErrorInfo::new("Not a Cocoa class archive"));
+        }
+        let version = self.read_u16()?;
+        if version != VERSION {
+            return Err(ErrorInfo::new(format!("Unsupported class archive version {}, expected {}", version, VERSION)));
+        }
+
+        let mut classes = HashMap::new();
+        for _ in 0..self.read_index()? {
+            let name = self.read_string()?;
+            let bytes = self.read_byte_vec()?;
+            if classes.insert(name.clone(), bytes).is_some() {
+                return Err(ErrorInfo::new(format!("Class {} appears twice in archive", name)));
+            }
+        }
+        Ok(classes)
+    }
+
+    fn read_class(&mut self) -> CocoaResult<ClassHeader> {
+        let magic = self.read_bytes::<4>()?;
+        if magic != MAGIC {
+            return Err(ErrorInfo::new("Not a Cocoa class file"));
+        }
+        let version = self.read_u16()?;
+        if version != VERSION {
+            return Err(ErrorInfo::new(format!("Unsupported class file version {}, expected {}", version, VERSION)));
+        }
+
+        let this_info = self.read_index()?;
+        let parent_info = self.read_index()?;
+        let class_flags = self.read_u8()?;
+        let class_flags = ClassFlags::from_bits(class_flags).ok_or_else(|| ErrorInfo::new(format!("Invalid class flags {:#x}", class_flags)))?;
+
+        let mut constant_pool = Vec::new();
+        for _ in 0..self.read_index()? {
+            constant_pool.push(self.read_pool_entry()?);
+        }
+
+        let mut interfaces = Vec::new();
+        for _ in 0..self.read_index()? {
+            let info = self.read_index()?;
+            let mut vtable = Vec::new();
+            for _ in 0..self.read_index()? {
+                vtable.push(self.read_index()?);
+            }
+            interfaces.push(InterfaceInfo { info, vtable });
+        }
+
+        let mut fields = Vec::new();
+        for _ in 0..self.read_index()? {
+            let name = self.read_index()?;
+            let flags = self.read_u8()?;
+            let flags = FieldFlags::from_bits(flags).ok_or_else(|| ErrorInfo::new(format!("Invalid field flags {:#x}", flags)))?;
+            let type_info = self.read_index()?;
+            let location = self.read_option()?;
+            fields.push(FieldInfo { name, flags, type_info, location });
+        }
+
+        let mut methods = Vec::new();
+        for _ in 0..self.read_index()? {
+            let flags = self.read_u8()?;
+            let flags = MethodFlags::from_bits(flags).ok_or_else(|| ErrorInfo::new(format!("Invalid method flags {:#x}", flags)))?;
+            let name = self.read_index()?;
+            let type_info = self.read_index()?;
+            let location = self.read_index()?;
+            methods.push(MethodInfo { flags, name, type_info, location });
+        }
+
+        let mut strings = Vec::new();
+        for _ in 0..self.read_index()? {
+            strings.push(self.read_index()?);
+        }
+
+        let mut exception_handlers = Vec::new();
+        for _ in 0..self.read_index()? {
+            let method = self.read_index()?;
+            let start = self.read_index()?;
+            let end = self.read_index()?;
+            let handler = self.read_index()?;
+            let catch = self.read_option()?;
+            exception_handlers.push(ExceptionHandler { method, start, end, handler, catch });
+        }
+
+        let mut class = ClassHeader::new(constant_pool.len(), interfaces.len(), fields.len(), methods.len(), strings.len());
+        class.set_this_info(this_info);
+        class.set_parent_info(parent_info);
+        class.set_class_flags(class_flags);
+        for (i, entry) in constant_pool.into_iter().enumerate() {
+            class.set_constant_pool_entry(i, entry);
+        }
+        for (i, interface) in interfaces.into_iter().enumerate() {
+            class.set_interface(i, interface);
+        }
+        for (i, field) in fields.into_iter().enumerate() {
+            class.set_field(i, field);
+        }
+        for (i, method) in methods.into_iter().enumerate() {
+            class.set_method(i, method);
+        }
+        for (i, string) in strings.into_iter().enumerate() {
+            class.set_string(i, string);
+        }
+        class.set_exception_handlers(exception_handlers);
+
+        Ok(class)
+    }
+
+    fn read_pool_entry(&mut self) -> CocoaResult<PoolEntry> {
+        let entry = match self.read_u8()? {
+            POOL_U8 => PoolEntry::U8(u8::from_le_bytes(self.read_bytes()?)),
+            POOL_U16 => PoolEntry::U16(u16::from_le_bytes(self.read_bytes()?)),
+            POOL_U32 => PoolEntry::U32(u32::from_le_bytes(self.read_bytes()?)),
+            POOL_U64 => PoolEntry::U64(u64::from_le_bytes(self.read_bytes()?)),
+            POOL_I8 => PoolEntry::I8(i8::from_le_bytes(self.read_bytes()?)),
+            POOL_I16 => PoolEntry::I16(i16::from_le_bytes(self.read_bytes()?)),
+            POOL_I32 => PoolEntry::I32(i32::from_le_bytes(self.read_bytes()?)),
+            POOL_I64 => PoolEntry::I64(i64::from_le_bytes(self.read_bytes()?)),
+            POOL_F32 => PoolEntry::F32(f32::from_le_bytes(self.read_bytes()?)),
+            POOL_F64 => PoolEntry::F64(f64::from_le_bytes(self.read_bytes()?)),
+            POOL_CHAR => {
+                let value = u32::from_le_bytes(self.read_bytes()?);
+                let value = char::from_u32(value).ok_or_else(|| ErrorInfo::new(format!("Invalid char {:#x}", value)))?;
+                PoolEntry::Char(value)
+            }
+            POOL_STRING => PoolEntry::String(self.read_string()?),
+            POOL_CLASS_INFO => {
+                let name = self.read_index()?;
+                let class_ref = self.read_option()?;
+                PoolEntry::ClassInfo(ClassInfo { name, class_ref })
+            }
+            POOL_METHOD => PoolEntry::Method(self.read_method()?),
+            POOL_TYPE_INFO => PoolEntry::TypeInfo(self.read_type_info()?),
+            POOL_REDIRECT => PoolEntry::Redirect(self.read_index()?),
+            POOL_REFERENCE => PoolEntry::Reference(self.read_index()?),
+            x => return Err(ErrorInfo::new(format!("Invalid pool entry tag {}", x))),
+        };
+        Ok(entry)
+    }
+
+    fn read_method(&mut self) -> CocoaResult<Method> {
+        let method = match self.read_u8()? {
+            METHOD_NATIVE => Method::Native(self.read_index()?),
+            METHOD_BYTECODE => {
+                let bytes = self.read_byte_vec()?;
+                Method::Bytecode(bytecode::decode(&bytes)?)
+            }
+            METHOD_FOREIGN => Method::Foreign(self.read_index()?),
+            x => return Err(ErrorInfo::new(format!("Invalid method tag {}", x))),
+        };
+        Ok(method)
+    }
+
+    fn read_type_info(&mut self) -> CocoaResult<TypeInfo> {
+        let info = match self.read_u8()? {
+            TYPE_UNIT => TypeInfo::Unit,
+            TYPE_U8 => TypeInfo::U8,
+            TYPE_U16 => TypeInfo::U16,
+            TYPE_U32 => TypeInfo::U32,
+            TYPE_U64 => TypeInfo::U64,
+            TYPE_I8 => TypeInfo::I8,
+            TYPE_I16 => TypeInfo::I16,
+            TYPE_I32 => TypeInfo::I32,
+            TYPE_I64 => TypeInfo::I64,
+            TYPE_F32 => TypeInfo::F32,
+            TYPE_F64 => TypeInfo::F64,
+            TYPE_CHAR => TypeInfo::Char,
+            TYPE_BOOL => TypeInfo::Bool,
+            TYPE_STRING => TypeInfo::String,
+            TYPE_ARRAY => TypeInfo::Array(Box::new(self.read_type_info()?)),
+            TYPE_OBJECT => TypeInfo::Object(self.read_index()?),
+            TYPE_METHOD => {
+                let mut args = Vec::new();
+                for _ in 0..self.read_index()? {
+                    args.push(self.read_type_info()?);
+                }
+                let ret = Box::new(self.read_type_info()?);
+                TypeInfo::Method { args, ret }
+            }
+            x => return Err(ErrorInfo::new(format!("Invalid type info tag {}", x))),
+        };
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bytecode::{Bytecode, Type};
+    use super::*;
+
+    fn sample_class() -> ClassHeader {
+        let mut class = ClassHeader::new(12, 1, 1, 1, 1);
+
+        class.set_parent_info(1);
+        class.set_this_info(0);
+        class.set_class_flags(ClassFlags::Public);
+
+        class.set_constant_pool_entry(0, PoolEntry::ClassInfo(ClassInfo {
+            name: 2,
+            class_ref: None,
+        }));
+        class.set_constant_pool_entry(1, PoolEntry::ClassInfo(ClassInfo {
+            name: 3,
+            class_ref: Some(7),
+        }));
+        class.set_constant_pool_entry(2, PoolEntry::String("Main".to_owned()));
+        class.set_constant_pool_entry(3, PoolEntry::String("Object".to_owned()));
+        class.set_constant_pool_entry(4, PoolEntry::Method(Method::Bytecode(vec![
+            Bytecode::LoadConstant(8),
+            Bytecode::Convert(Type::Char(4)),
+            Bytecode::IfNot(-2),
+            Bytecode::InvokeInterfaceStatic(0, 1, 2),
+            Bytecode::Return,
+        ].into())));
+        class.set_constant_pool_entry(5, PoolEntry::Method(Method::Native(3)));
+        class.set_constant_pool_entry(6, PoolEntry::TypeInfo(TypeInfo::Method {
+            args: vec![TypeInfo::Array(Box::new(TypeInfo::Object(3))), TypeInfo::Char],
+            ret: Box::new(TypeInfo::Unit),
+        }));
+        class.set_constant_pool_entry(7, PoolEntry::Redirect(1));
+        class.set_constant_pool_entry(8, PoolEntry::I32(-42));
+        class.set_constant_pool_entry(9, PoolEntry::F64(1.5));
+        class.set_constant_pool_entry(10, PoolEntry::Char('λ'));
+        class.set_constant_pool_entry(11, PoolEntry::Reference(99));
+
+        class.set_interface(0, InterfaceInfo {
+            info: 1,
+            vtable: vec![0, 3],
+        });
+        class.set_field(0, FieldInfo {
+            name: 3,
+            flags: FieldFlags::Static | FieldFlags::Const,
+            type_info: 6,
+            location: Some(8),
+        });
+        class.set_method(0, MethodInfo {
+            flags: MethodFlags::Public | MethodFlags::Static,
+            name: 2,
+            type_info: 6,
+            location: 4,
+        });
+        class.set_string(0, 2);
+        class.set_exception_handlers(vec![ExceptionHandler {
+            method: 0,
+            start: 0,
+            end: 3,
+            handler: 4,
+            catch: Some(1),
+        }]);
+
+        class
+    }
+
+    #[test]
+    fn test_class_file_round_trip() {
+        let class = sample_class();
+        let bytes = class_to_bytes(&class);
+        let read = class_from_bytes(&bytes).unwrap();
+
+        assert_eq!(read.get_this_info(), class.get_this_info());
+        assert_eq!(read.get_parent_info(), class.get_parent_info());
+        assert_eq!(read.get_class_flags(), class.get_class_flags());
+        assert_eq!(read.constants(), class.constants());
+        assert_eq!(read.interfaces(), class.interfaces());
+        assert_eq!(read.fields(), class.fields());
+        assert_eq!(read.methods(), class.methods());
+        assert_eq!(read.strings(), class.strings());
+        assert_eq!(read.exception_handlers(), class.exception_handlers());
+    }
+
+    #[test]
+    fn test_class_file_bad_magic() {
+        let mut bytes = class_to_bytes(&sample_class());
+        bytes[0] = 0;
+        assert!(class_from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_class_file_old_version() {
+        // Version 3 encoded GetField and SetField with a single operand
+        let mut bytes = class_to_bytes(&sample_class());
+        bytes[4..6].copy_from_slice(&3u16.to_le_bytes());
+        let error = class_from_bytes(&bytes).unwrap_err();
+        assert_eq!(error.get_message(), "Unsupported class file version 3, expected 4");
+    }
+
+    #[test]
+    fn test_class_file_truncated() {
+        let bytes = class_to_bytes(&sample_class());
+        assert!(class_from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_archive_round_trip() {
+        let class = sample_class();
+        let mut bytes = Vec::new();
+        write_archive(&mut bytes, &[("cocoa.lang.Main", class)]).unwrap();
+
+        let archive = read_archive(&mut bytes.as_slice()).unwrap();
+        assert_eq!(archive.len(), 1);
+        let read = class_from_bytes(&archive["cocoa.lang.Main"]).unwrap();
+        assert_eq!(read.constants(), class.constants());
+        assert_eq!(read.methods(), class.methods());
+
+        assert!(read_archive(&mut class_to_bytes(&class).as_slice()).is_err());
+    }
+}
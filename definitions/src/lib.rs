@@ -5,7 +5,10 @@ pub mod class;
 pub mod object;
 pub mod bytecode;
 pub mod stack;
+pub mod class_file;
+pub mod assembler;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ArgType {
     Unit,
     U8(u8),
@@ -26,7 +29,53 @@ pub enum ArgType {
 
 pub type CocoaResult<T> = Result<T, ErrorInfo>;
 
+/// A resource limit that stopped a machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Limit {
+    /// The instruction budget ran out
+    Fuel,
+    /// Too many frames were on the stack
+    CallDepth,
+    /// Too many entries were alive in the object table
+    LiveObjects,
+    /// Too many bytes were allocated for arrays
+    ArrayBytes,
+}
+
 #[derive(Debug)]
 pub struct ErrorInfo {
     message: String,
+    limit: Option<Limit>,
+}
+
+impl ErrorInfo {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            limit: None,
+        }
+    }
+
+    /// An error for a program that used more than a limit allows
+    pub fn limit_exceeded(limit: Limit, message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            limit: Some(limit),
+        }
+    }
+
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+
+    /// The limit that was exceeded, if that's what caused the error
+    pub fn get_limit(&self) -> Option<Limit> {
+        self.limit
+    }
+}
+
+impl std::fmt::Display for ErrorInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
 }
@@ -1,54 +1,53 @@
-use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::{atomic::{AtomicUsize, Ordering}, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
-use crate::class::ClassHeader;
+use crate::{bytecode::Type, class::{ClassHeader, PoolIndex}};
 
 
 struct ObjectBody {
-    parent: Reference,
     class_ref: Reference,
-    field_count: usize,
+    /// The number of bytes of field storage after the body
+    size: usize,
 }
 
 impl ObjectBody {
-    fn new(parent: Reference, class_ref: Reference, fields: usize) -> *mut Self {
+    fn layout(size: usize) -> std::alloc::Layout {
         let layout = std::alloc::Layout::new::<Self>();
-        let (layout, _) = layout.extend(std::alloc::Layout::array::<Reference>(fields).unwrap()).unwrap();
-        let object = unsafe {std::alloc::alloc(layout)};
+        let (layout, _) = layout.extend(std::alloc::Layout::array::<u8>(size).unwrap()).unwrap();
+        layout
+    }
+
+    fn new(class_ref: Reference, size: usize) -> *mut Self {
+        // Fields start out zeroed, which makes reference fields null
+        let object = unsafe {std::alloc::alloc_zeroed(Self::layout(size))};
         let object = object as *mut Self;
         unsafe {
             std::ptr::write(object, Self {
-                parent,
                 class_ref,
-                field_count: fields,
+                size,
             });
         }
         object
     }
 
-    fn get_parent(&self) -> Reference {
-        self.parent
-    }
-
     fn get_class(&self) -> Reference {
         self.class_ref
     }
 
-    fn get_field<T:Copy>(&self, index: usize) -> T {
+    /// A pointer to the field at a byte offset, the field storage is aligned like the body
+    fn field_ptr<T>(&self, offset: usize) -> *mut T {
+        assert!(offset + std::mem::size_of::<T>() <= self.size);
+        debug_assert_eq!(offset % std::mem::align_of::<T>(), 0);
         let ptr = self as *const Self;
-        let ptr = unsafe { ptr.add(1) };
-        let ptr = ptr as *const Reference;
-        assert!(index < self.field_count);
-        let ptr = unsafe { ptr.add(index) as *const T };
-        unsafe {*ptr}
+        let ptr = unsafe { ptr.add(1) } as *mut u8;
+        unsafe { ptr.add(offset) as *mut T }
     }
 
-    fn set_field<T:Copy>(&self, index: usize, value: T) {
-        let ptr = self as *const Self;
-        let ptr = unsafe { ptr.add(1) };
-        let ptr = ptr as *const Reference;
-        assert!(index < self.field_count);
-        let ptr = unsafe { ptr.add(index) as *mut T };
-        unsafe {*ptr = value};
+    fn get_field<T:Copy>(&self, offset: usize) -> T {
+        unsafe {*self.field_ptr::<T>(offset)}
+    }
+
+    fn set_field<T:Copy>(&self, offset: usize, value: T) {
+        unsafe {*self.field_ptr::<T>(offset) = value};
     }
 }
 
@@ -56,45 +55,40 @@ impl ObjectBody {
 pub struct Object(*mut ObjectBody);
 
 impl Object {
-    pub fn new(parent: Reference, class_ref: Reference, fields: usize) -> Self {
-        let body = ObjectBody::new(parent, class_ref, fields);
+    /// An object with `size` bytes of zeroed fields, laid out as the class's field offsets describe
+    ///
+    /// The fields of the class's parents are part of the same allocation.
+    pub fn new(class_ref: Reference, size: usize) -> Self {
+        let body = ObjectBody::new(class_ref, size);
 
         Object(body)
     }
 
-    pub fn get_parent(&self) -> Reference {
-        let body = unsafe {self.0.as_ref().unwrap()};
-        body.get_parent()
-    }
-
     pub fn get_class(&self) -> Reference {
         let body = unsafe {self.0.as_ref().unwrap()};
         body.get_class()
     }
 
+    /// The number of bytes the object's fields take up
     pub fn get_size(&self) -> usize {
         let body = unsafe {self.0.as_ref().unwrap()};
-        body.field_count
+        body.size
     }
 
-    pub fn get_field<T:Copy>(&self, index: usize) -> T {
+    pub fn get_field<T:Copy>(&self, offset: usize) -> T {
         let body = unsafe {self.0.as_ref().unwrap()};
-        body.get_field(index)
+        body.get_field(offset)
     }
 
-    pub fn set_field<T:Copy>(&self, index: usize, value: T) {
+    pub fn set_field<T:Copy>(&self, offset: usize, value: T) {
         let body = unsafe {self.0.as_ref().unwrap()};
-        body.set_field(index, value);
+        body.set_field(offset, value);
     }
 
     pub fn deallocate(&mut self) {
-        println!("Dropping Object");
-        let field_count = unsafe {self.0.as_ref().unwrap().field_count};
-        let layout = std::alloc::Layout::new::<Self>();
-        let (layout, _) = layout.extend(std::alloc::Layout::array::<Reference>(field_count).unwrap()).unwrap();
+        let size = unsafe {self.0.as_ref().unwrap().size};
         unsafe {
-            //std::ptr::drop_in_place(self);
-            std::alloc::dealloc(self.0 as *mut u8, layout);
+            std::alloc::dealloc(self.0 as *mut u8, ObjectBody::layout(size));
         }
     }
 }
@@ -110,11 +104,22 @@ struct ArrayBody {
     class_ref: Reference,
     elem_size: usize,
     size: usize,
+    /// The type of the elements, references are followed by the garbage collector
+    elem_type: Type,
+    /// The global pool index of the array's `TypeInfo::Array`
+    type_info: Option<PoolIndex>,
 }
 
 impl ArrayBody {
-    fn new(parent: Reference, class_ref: Reference, elem_size: usize, size: usize) -> *mut Self {
+    fn new(parent: Reference, class_ref: Reference, elem_type: Type, size: usize, type_info: Option<PoolIndex>) -> *mut Self {
         let layout = std::alloc::Layout::new::<Self>();
+        let elem_size = match elem_type {
+            Type::U8 | Type::I8 => 1,
+            Type::U16 | Type::I16 => 2,
+            Type::U32 | Type::I32 | Type::F32 => 4,
+            Type::U64 | Type::I64 | Type::F64 | Type::Reference => 8,
+            Type::Char(size) => size as usize,
+        };
         
         let (layout, _) = match elem_size {
             1 => layout.extend(std::alloc::Layout::array::<u8>(size).unwrap()).unwrap(),
@@ -123,7 +128,8 @@ impl ArrayBody {
             8 => layout.extend(std::alloc::Layout::array::<u64>(size).unwrap()).unwrap(),
             _ => panic!("Invalid element size"),
         };
-        let object = unsafe {std::alloc::alloc(layout)};
+        // Elements start out zeroed, which makes reference elements null
+        let object = unsafe {std::alloc::alloc_zeroed(layout)};
         let object = object as *mut Self;
         unsafe {
             std::ptr::write(object, Self {
@@ -131,6 +137,8 @@ impl ArrayBody {
                 class_ref,
                 elem_size,
                 size,
+                elem_type,
+                type_info,
             });
         }
         object
@@ -179,8 +187,18 @@ impl ArrayBody {
 pub struct Array(*mut ArrayBody);
 
 impl Array {
-    pub fn new(parent: Reference, class_ref: Reference, elem_size: usize, size: usize) -> Self {
-        let body = ArrayBody::new(parent, class_ref, elem_size, size);
+    pub fn new(parent: Reference, class_ref: Reference, elem_type: Type, size: usize) -> Self {
+        let body = ArrayBody::new(parent, class_ref, elem_type, size, None);
+
+        Array(body)
+    }
+
+    /// An array of `size` references, which start out null
+    ///
+    /// `type_info` is the global pool index of the array's `TypeInfo::Array`, arrays without one
+    /// don't know what their elements are.
+    pub fn new_references(parent: Reference, class_ref: Reference, type_info: Option<PoolIndex>, size: usize) -> Self {
+        let body = ArrayBody::new(parent, class_ref, Type::Reference, size, type_info);
 
         Array(body)
     }
@@ -205,6 +223,23 @@ impl Array {
         body.get_size()
     }
 
+    pub fn get_elem_type(&self) -> Type {
+        let body = unsafe {self.0.as_ref().unwrap()};
+        body.elem_type
+    }
+
+    /// Whether the elements are references
+    pub fn holds_references(&self) -> bool {
+        let body = unsafe {self.0.as_ref().unwrap()};
+        body.elem_type == Type::Reference
+    }
+
+    /// The global pool index of the array's `TypeInfo::Array`, if it was created with one
+    pub fn get_type_info(&self) -> Option<PoolIndex> {
+        let body = unsafe {self.0.as_ref().unwrap()};
+        body.type_info
+    }
+
     pub fn get_elem<T:Copy>(&self, index: usize) -> T {
         let body = unsafe {self.0.as_ref().unwrap()};
         body.get_elem(index)
@@ -216,10 +251,9 @@ impl Array {
     }
 
     pub fn deallocate(&mut self) {
-        println!("Dropping Array");
         let size = unsafe {self.0.as_ref().unwrap().size};
         let elem_size = unsafe {self.0.as_ref().unwrap().elem_size};
-        let layout = std::alloc::Layout::new::<Self>();
+        let layout = std::alloc::Layout::new::<ArrayBody>();
         let (layout, _) = match elem_size {
             1 => layout.extend(std::alloc::Layout::array::<u8>(size).unwrap()).unwrap(),
             2 => layout.extend(std::alloc::Layout::array::<u16>(size).unwrap()).unwrap(),
@@ -228,7 +262,6 @@ impl Array {
             _ => panic!("Invalid element size"),
         };
         unsafe {
-            //std::ptr::drop_in_place(self);
             std::alloc::dealloc(self.0 as *mut u8, layout);
         }
     }
@@ -280,10 +313,9 @@ impl StringObject {
     }
 
     pub fn deallocate(&mut self) {
-        println!("Dropping String");
         let layout = std::alloc::Layout::new::<StringBody>();
         unsafe {
-            //std::ptr::drop_in_place(self);
+            std::ptr::drop_in_place(self.0);
             std::alloc::dealloc(self.0 as *mut u8, layout);
         }
     }
@@ -291,6 +323,42 @@ impl StringObject {
 
 pub type Reference = usize;
 
+/// The number of low bits of a reference that hold its slot, the view and then the generation sit above them
+const SLOT_BITS: u32 = 40;
+const SLOT_MASK: usize = (1 << SLOT_BITS) - 1;
+const VIEW_BITS: u32 = 8;
+const GENERATION_SHIFT: u32 = SLOT_BITS + VIEW_BITS;
+/// The deepest view a reference can hold
+pub const MAX_VIEW: usize = (1 << VIEW_BITS) - 1;
+
+/// The slot of the object table a reference points to
+pub fn reference_slot(reference: Reference) -> usize {
+    reference & SLOT_MASK
+}
+
+/// The generation of its slot the reference was handed out in
+pub fn reference_generation(reference: Reference) -> u16 {
+    (reference >> GENERATION_SHIFT) as u16
+}
+
+/// How many classes up the hierarchy the reference looks at its object
+///
+/// An object holds the fields of all its parents, so its parent is the same object viewed as the
+/// parent class. Views don't change which entry a reference points to.
+pub fn reference_view(reference: Reference) -> usize {
+    (reference >> SLOT_BITS) & MAX_VIEW
+}
+
+/// The same entry seen through another view, a view of 0 is the object as its own class
+pub fn with_view(reference: Reference, view: usize) -> Reference {
+    assert!(view <= MAX_VIEW);
+    reference & !(MAX_VIEW << SLOT_BITS) | view << SLOT_BITS
+}
+
+fn make_reference(slot: usize, generation: u16) -> Reference {
+    slot | (generation as usize) << GENERATION_SHIFT
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum GcMark {
     White,
@@ -344,32 +412,36 @@ impl ObjectHeader {
     
     pub fn get_object_ptr(&self) -> Object {
         match &self.ptr {
-            HeaderPtr::Object(ptr) => ptr.clone(),
+            HeaderPtr::Object(ptr) => *ptr,
             _ => panic!("Invalid object type"),
         }
     }
 
     pub fn get_array_ptr(&self) -> Array {
         match &self.ptr {
-            HeaderPtr::Array(ptr) => ptr.clone(),
+            HeaderPtr::Array(ptr) => *ptr,
             _ => panic!("Invalid object type"),
         }
     }
 
     pub fn get_class_ptr(&self) -> ClassHeader {
         match &self.ptr {
-            HeaderPtr::Class(ptr) => ptr.clone(),
+            HeaderPtr::Class(ptr) => *ptr,
             _ => panic!("Invalid object type"),
         }
     }
 
     pub fn get_string_ptr(&self) -> StringObject {
         match &self.ptr {
-            HeaderPtr::String(ptr) => ptr.clone(),
+            HeaderPtr::String(ptr) => *ptr,
             _ => panic!("Invalid object type"),
         }
     }
 
+    pub fn get_ptr(&self) -> &HeaderPtr {
+        &self.ptr
+    }
+
     pub fn get_mark(&self) -> GcMark {
         self.mark
     }
@@ -418,62 +490,166 @@ impl ObjectHeader {
 
 
 
+/// The entries of an object table
+///
+/// A `Reference` holds the index of a slot in its low bits and the generation of the slot in its high
+/// bits, with the view in between. Deleting an entry bumps the generation of its slot and puts the slot on the free list, so
+/// references to the deleted entry are stale from then on and never alias whatever reuses the slot.
+pub struct Slots {
+    headers: Vec<Option<ObjectHeader>>,
+    generations: Vec<u16>,
+    free: Vec<usize>,
+}
+
+impl Slots {
+    fn new() -> Self {
+        // Reference 0 stands for null, so slot 0 is never handed out
+        Self {
+            headers: vec![None],
+            generations: vec![0],
+            free: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, header: ObjectHeader) -> Reference {
+        let slot = match self.free.pop() {
+            Some(slot) => slot,
+            None => {
+                self.headers.push(None);
+                self.generations.push(0);
+                self.headers.len() - 1
+            }
+        };
+        self.headers[slot] = Some(header);
+        make_reference(slot, self.generations[slot])
+    }
+
+    fn remove(&mut self, reference: Reference) -> Option<ObjectHeader> {
+        if self.is_stale(reference) {
+            return None;
+        }
+        let slot = reference_slot(reference);
+        let header = self.headers.get_mut(slot)?.take()?;
+        // A slot that ran out of generations is retired, reusing it could make an old reference valid again
+        if self.generations[slot] < u16::MAX {
+            self.generations[slot] += 1;
+            self.free.push(slot);
+        }
+        Some(header)
+    }
+
+    /// Whether the entry the reference pointed to has been deleted since it was handed out
+    pub fn is_stale(&self, reference: Reference) -> bool {
+        match self.generations.get(reference_slot(reference)) {
+            Some(generation) => *generation != reference_generation(reference),
+            None => false,
+        }
+    }
+
+    pub fn get(&self, reference: Reference) -> Option<&ObjectHeader> {
+        if self.is_stale(reference) {
+            return None;
+        }
+        self.headers.get(reference_slot(reference))?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, reference: Reference) -> Option<&mut ObjectHeader> {
+        if self.is_stale(reference) {
+            return None;
+        }
+        self.headers.get_mut(reference_slot(reference))?.as_mut()
+    }
+
+    /// The live entries and the references that point to them
+    pub fn iter(&self) -> impl Iterator<Item = (Reference, &ObjectHeader)> {
+        self.headers.iter().zip(&self.generations).enumerate()
+            .filter_map(|(slot, (header, generation))| Some((make_reference(slot, *generation), header.as_ref()?)))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Reference, &mut ObjectHeader)> {
+        self.headers.iter_mut().zip(&self.generations).enumerate()
+            .filter_map(|(slot, (header, generation))| Some((make_reference(slot, *generation), header.as_mut()?)))
+    }
+
+    /// The number of slots, live or free
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+}
+
 pub struct ObjectTable {
-    objects: RwLock<Vec<Option<ObjectHeader>>>,
+    objects: RwLock<Slots>,
+    /// The number of entries that haven't been deleted
+    live: AtomicUsize,
 }
 
 impl ObjectTable {
     pub fn new() -> Self {
         Self {
-            objects: RwLock::new(Vec::new()),
+            objects: RwLock::new(Slots::new()),
+            live: AtomicUsize::new(0),
         }
     }
 
-    pub fn add_object(&self, object: Object) -> Reference {
+    fn add(&self, header: ObjectHeader) -> Reference {
         let mut objects = self.objects.write().unwrap();
-        let ptr = objects.len();
-        objects.push(Some(ObjectHeader::new_object(object)));
-        ptr
+        self.live.fetch_add(1, Ordering::Relaxed);
+        objects.insert(header)
+    }
+
+    pub fn add_object(&self, object: Object) -> Reference {
+        self.add(ObjectHeader::new_object(object))
     }
 
     pub fn add_array(&self, array: Array) -> Reference {
-        let mut objects = self.objects.write().unwrap();
-        let ptr = objects.len();
-        objects.push(Some(ObjectHeader::new_array(array)));
-        ptr
+        self.add(ObjectHeader::new_array(array))
     }
 
     pub fn add_class(&self, class: ClassHeader) -> Reference {
-        let mut objects = self.objects.write().unwrap();
-        let ptr = objects.len();
-        objects.push(Some(ObjectHeader::new_class(class)));
-        ptr
+        self.add(ObjectHeader::new_class(class))
     }
 
     pub fn add_string(&self, string: StringObject) -> Reference {
-        let mut objects = self.objects.write().unwrap();
-        let ptr = objects.len();
-        objects.push(Some(ObjectHeader::new_string(string)));
-        ptr
+        self.add(ObjectHeader::new_string(string))
     }
 
     pub fn get_object(&self, reference: Reference) -> Option<ObjectHeader> {
-        self.objects.read().unwrap().get(reference).cloned().flatten()
+        self.objects.read().unwrap().get(reference).cloned()
     }
 
+    /// Deletes the entry, a stale reference leaves the entry that reused its slot alone
     pub fn delete_object(&self, reference: Reference) {
         let mut table = self.objects.write().unwrap();
-        table[reference].as_mut().map(|obj| {
-            obj.deallocate();
-        });
-        table[reference] = None;
+        if let Some(mut header) = table.remove(reference) {
+            header.deallocate();
+            self.live.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether the entry the reference pointed to has been deleted since it was handed out
+    pub fn is_stale(&self, reference: Reference) -> bool {
+        self.objects.read().unwrap().is_stale(reference)
     }
 
-    pub fn get_table(&self) -> RwLockReadGuard<Vec<Option<ObjectHeader>>> {
+    /// The number of objects, arrays, classes and strings in the table
+    pub fn live_count(&self) -> usize {
+        self.live.load(Ordering::Relaxed)
+    }
+
+    /// The number of slots in the table, including free ones
+    pub fn slot_count(&self) -> usize {
+        self.objects.read().unwrap().len()
+    }
+
+    pub fn get_table(&self) -> RwLockReadGuard<Slots> {
         self.objects.read().unwrap()
     }
 
-    pub fn get_table_mut(&self) -> RwLockWriteGuard<Vec<Option<ObjectHeader>>> {
+    pub fn get_table_mut(&self) -> RwLockWriteGuard<Slots> {
         self.objects.write().unwrap()
     }
 
@@ -504,24 +680,69 @@ mod tests {
     #[test]
     fn test_object_table() {
         let table = ObjectTable::new();
-        let object = Object::new(0, 0, 0);
-        let reference = table.add_object(object.clone());
+        let object = Object::new(0, 0);
+        let reference = table.add_object(object);
         assert_eq!(table.get_object(reference).unwrap().get_object_ptr(), object);
         table.delete_object(reference);
         assert_eq!(table.get_object(reference), None);
     }
 
+    #[test]
+    fn test_slot_reuse() {
+        let table = ObjectTable::new();
+        let first = table.add_object(Object::new(0, 0));
+        assert_ne!(first, 0);
+        table.delete_object(first);
+        assert!(table.is_stale(first));
+
+        // The freed slot is reused under a new generation
+        let object = Object::new(0, 1);
+        let second = table.add_object(object);
+        assert_eq!(reference_slot(second), reference_slot(first));
+        assert_eq!(reference_generation(second), reference_generation(first) + 1);
+        assert_eq!(table.slot_count(), 2);
+
+        // The stale reference neither sees nor deletes the entry that took its slot
+        assert_eq!(table.get_object(first), None);
+        table.delete_object(first);
+        assert_eq!(table.live_count(), 1);
+        assert_eq!(table.get_object(second).unwrap().get_object_ptr(), object);
+        assert!(!table.is_stale(second) && !table.is_stale(0));
+
+        let references: Vec<Reference> = table.get_table().iter().map(|(reference, _)| reference).collect();
+        assert_eq!(references, vec![second]);
+    }
+
     #[test]
     fn test_object() {
-        let object = Object::new(0, 0, 0);
-        assert_eq!(object.get_parent(), 0);
-        assert_eq!(object.get_class(), 0);
+        let object = Object::new(3, 8);
+        assert_eq!(object.get_class(), 3);
+        assert_eq!(object.get_size(), 8);
+    }
+
+    #[test]
+    fn test_reference_views() {
+        let table = ObjectTable::new();
+        let object = Object::new(0, 0);
+        let reference = table.add_object(object);
+        let parent = with_view(reference, 2);
+        assert_ne!(parent, reference);
+        assert_eq!(reference_view(parent), 2);
+        assert_eq!(reference_slot(parent), reference_slot(reference));
+        assert_eq!(with_view(parent, 0), reference);
+
+        // A view points to the same entry and goes stale along with it
+        assert_eq!(table.get_object(parent).unwrap().get_object_ptr(), object);
+        table.delete_object(reference);
+        assert!(table.is_stale(parent));
+        let reused = table.add_object(Object::new(0, 0));
+        assert!(table.is_stale(with_view(reference, 1)) && !table.is_stale(with_view(reused, 1)));
     }
 
     #[test]
     fn test_object_header() {
-        let object = Object::new(0, 0, 0);
-        let mut header = ObjectHeader::new_object(object.clone());
+        let object = Object::new(0, 0);
+        let mut header = ObjectHeader::new_object(object);
         assert_eq!(header.get_object_ptr(), object);
         assert_eq!(header.get_mark(), GcMark::White);
         header.set_mark(GcMark::Black);
@@ -530,29 +751,32 @@ mod tests {
 
     #[test]
     fn test_array() {
-        let array = Array::new(0, 0, 4, 4);
+        let array = Array::new(0, 0, Type::F32, 4);
         assert_eq!(array.get_parent(), 0);
         assert_eq!(array.get_class(), 0);
         assert_eq!(array.get_elem_size(), 4);
+        assert_eq!(array.get_elem_type(), Type::F32);
         assert_eq!(array.get_size(), 4);
     }
 
     #[test]
     fn test_object_body() {
-        let object = Object::new(0, 0, 4);
-        object.set_field(0, 1);
-        object.set_field(1, 2);
-        object.set_field(2, 3);
-        object.set_field(3, 4);
-        assert_eq!(object.get_field::<usize>(0), 1);
-        assert_eq!(object.get_field::<usize>(1), 2);
-        assert_eq!(object.get_field::<usize>(2), 3);
-        assert_eq!(object.get_field::<usize>(3), 4);
+        let object = Object::new(0, 16);
+        assert_eq!(object.get_size(), 16);
+        object.set_field::<u8>(0, 1);
+        object.set_field::<u16>(2, 2);
+        object.set_field::<i32>(4, -3);
+        object.set_field::<usize>(8, 4);
+        assert_eq!(object.get_field::<u8>(0), 1);
+        assert_eq!(object.get_field::<u16>(2), 2);
+        assert_eq!(object.get_field::<i32>(4), -3);
+        assert_eq!(object.get_field::<usize>(8), 4);
     }
 
+
     #[test]
     fn test_array_body() {
-        let mut array = Array::new(0, 0, 8, 4);
+        let mut array = Array::new(0, 0, Type::U64, 4);
         array.set_elem::<usize>(0, 1);
         array.set_elem::<usize>(1, 2);
         array.set_elem::<usize>(2, 3);
@@ -563,8 +787,30 @@ mod tests {
         assert_eq!(array.get_elem::<usize>(3), 4);
     }
 
+    #[test]
+    fn test_reference_array() {
+        let table = ObjectTable::new();
+        let mut array = Array::new_references(0, 0, Some(5), 3);
+        assert!(array.holds_references());
+        assert_eq!(array.get_type_info(), Some(5));
+        let bytes = Array::new(0, 0, Type::U64, 3);
+        assert!(!bytes.holds_references());
+        assert_eq!(bytes.get_type_info(), None);
+        assert_eq!(array.get_elem::<Reference>(2), 0);
+        array.set_elem::<Reference>(1, 7);
+        assert_eq!(array.get_elem::<Reference>(1), 7);
+
+        let reference = table.add_array(array);
+        let string = table.add_string(StringObject::new(0, 0, String::from("freed")));
+        assert_eq!(table.live_count(), 2);
+        table.delete_object(reference);
+        table.delete_object(string);
+        assert_eq!(table.live_count(), 0);
+    }
+
     #[test]
     fn test_object_drop() {
-        let object = Object::new(0, 0, 4);
+        let mut object = Object::new(0, 4);
+        object.deallocate();
     }
 }
@@ -9,9 +9,23 @@ pub trait StackUtils<T> {
     fn push(&mut self, value: T);
     fn pop(&mut self) -> T;
     fn set_argument(&mut self, index: u8);
+    /// Moves an argument from the current frame's operand stack into its own locals for a tail call
+    fn set_tail_argument(&mut self, index: u8);
     fn get_argument(&mut self, index: u8) -> T;
 }
 
+/// A copy of one frame's state, used by debuggers to inspect a running program
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameSnapshot {
+    pub class_reference: Reference,
+    pub method_index: usize,
+    pub pc: usize,
+    /// The locals written so far as their type and raw little endian bits
+    pub locals: Vec<(Type, u64)>,
+    /// The operand stack from bottom to top as their type and raw little endian bits
+    pub operands: Vec<(Type, u64)>,
+}
+
 #[derive(Debug)]
 pub struct Stack {
     stack: Vec<StackFrame>,
@@ -28,6 +42,26 @@ impl Stack {
         self.stack.is_empty()
     }
 
+    /// The number of frames on the stack
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Snapshots of every frame, outermost first
+    pub fn frames(&self) -> Vec<FrameSnapshot> {
+        self.stack.iter().map(StackFrame::snapshot).collect()
+    }
+
+    /// Every reference held in a local variable or on the operand stack of any frame
+    pub fn references(&self) -> Vec<Reference> {
+        self.stack.iter().flat_map(StackFrame::get_references).collect()
+    }
+
+    /// A snapshot of the frame at `index`, counting from the outermost frame
+    pub fn frame(&self, index: usize) -> Option<FrameSnapshot> {
+        self.stack.get(index).map(StackFrame::snapshot)
+    }
+
     pub fn push_frame(&mut self, class_index: Reference, method_index: usize) {
         self.stack.push(StackFrame::new(class_index, method_index));
     }
@@ -36,6 +70,17 @@ impl Stack {
         self.stack.pop();
     }
 
+    /// Reuses the current frame for a tail call to another method
+    ///
+    /// The locals are reset but the operand stack is kept so the arguments can be moved with `set_tail_argument`.
+    pub fn replace_frame(&mut self, class_index: Reference, method_index: usize) {
+        self.stack.last_mut().expect("Stack Underflow").reset(class_index, method_index);
+    }
+
+    pub fn clear_operands(&mut self) {
+        self.stack.last_mut().expect("Stack Underflow").clear_operands();
+    }
+
     pub fn generic_pop(&mut self) {
         self.stack.last_mut().expect("Stack Underflow").generic_pop();
     }
@@ -173,6 +218,12 @@ impl StackUtils<i8> for Stack {
         self.stack.last_mut().expect("Stack Underflow").store_argument(index, value);
     }
 
+    fn set_tail_argument(&mut self, index: u8) {
+        let frame = self.stack.last_mut().expect("Stack Underflow");
+        let value: i8 = frame.pop();
+        frame.store_argument(index, value);
+    }
+
     fn get_argument(&mut self, index: u8) -> i8 {
         self.stack.last_mut().expect("Stack Underflow").load_argument(index)
     }
@@ -193,6 +244,12 @@ impl StackUtils<i16> for Stack {
         self.stack.last_mut().expect("Stack Underflow").store_argument(index, value);
     }
 
+    fn set_tail_argument(&mut self, index: u8) {
+        let frame = self.stack.last_mut().expect("Stack Underflow");
+        let value: i16 = frame.pop();
+        frame.store_argument(index, value);
+    }
+
     fn get_argument(&mut self, index: u8) -> i16 {
         self.stack.last_mut().expect("Stack Underflow").load_argument(index)
     }
@@ -213,6 +270,12 @@ impl StackUtils<i32> for Stack {
         self.stack.last_mut().expect("Stack Underflow").store_argument(index, value);
     }
 
+    fn set_tail_argument(&mut self, index: u8) {
+        let frame = self.stack.last_mut().expect("Stack Underflow");
+        let value: i32 = frame.pop();
+        frame.store_argument(index, value);
+    }
+
     fn get_argument(&mut self, index: u8) -> i32 {
         self.stack.last_mut().expect("Stack Underflow").load_argument(index)
     }
@@ -233,6 +296,12 @@ impl StackUtils<i64> for Stack {
         self.stack.last_mut().expect("Stack Underflow").store_argument(index, value);
     }
 
+    fn set_tail_argument(&mut self, index: u8) {
+        let frame = self.stack.last_mut().expect("Stack Underflow");
+        let value: i64 = frame.pop();
+        frame.store_argument(index, value);
+    }
+
     fn get_argument(&mut self, index: u8) -> i64 {
         self.stack.last_mut().expect("Stack Underflow").load_argument(index)
     }
@@ -253,6 +322,12 @@ impl StackUtils<u8> for Stack {
         self.stack.last_mut().expect("Stack Underflow").store_argument(index, value);
     }
 
+    fn set_tail_argument(&mut self, index: u8) {
+        let frame = self.stack.last_mut().expect("Stack Underflow");
+        let value: u8 = frame.pop();
+        frame.store_argument(index, value);
+    }
+
     fn get_argument(&mut self, index: u8) -> u8 {
         self.stack.last_mut().expect("Stack Underflow").load_argument(index)
     }
@@ -273,6 +348,12 @@ impl StackUtils<u16> for Stack {
         self.stack.last_mut().expect("Stack Underflow").store_argument(index, value);
     }
 
+    fn set_tail_argument(&mut self, index: u8) {
+        let frame = self.stack.last_mut().expect("Stack Underflow");
+        let value: u16 = frame.pop();
+        frame.store_argument(index, value);
+    }
+
     fn get_argument(&mut self, index: u8) -> u16 {
         self.stack.last_mut().expect("Stack Underflow").load_argument(index)
     }
@@ -293,6 +374,12 @@ impl StackUtils<u32> for Stack {
         self.stack.last_mut().expect("Stack Underflow").store_argument(index, value);
     }
 
+    fn set_tail_argument(&mut self, index: u8) {
+        let frame = self.stack.last_mut().expect("Stack Underflow");
+        let value: u32 = frame.pop();
+        frame.store_argument(index, value);
+    }
+
     fn get_argument(&mut self, index: u8) -> u32 {
         self.stack.last_mut().expect("Stack Underflow").load_argument(index)
     }
@@ -313,6 +400,12 @@ impl StackUtils<u64> for Stack {
         self.stack.last_mut().expect("Stack Underflow").store_argument(index, value);
     }
 
+    fn set_tail_argument(&mut self, index: u8) {
+        let frame = self.stack.last_mut().expect("Stack Underflow");
+        let value: u64 = frame.pop();
+        frame.store_argument(index, value);
+    }
+
     fn get_argument(&mut self, index: u8) -> u64 {
         self.stack.last_mut().expect("Stack Underflow").load_argument(index)
     }
@@ -333,6 +426,12 @@ impl StackUtils<f32> for Stack {
         self.stack.last_mut().expect("Stack Underflow").store_argument(index, value);
     }
 
+    fn set_tail_argument(&mut self, index: u8) {
+        let frame = self.stack.last_mut().expect("Stack Underflow");
+        let value: f32 = frame.pop();
+        frame.store_argument(index, value);
+    }
+
     fn get_argument(&mut self, index: u8) -> f32 {
         self.stack.last_mut().expect("Stack Underflow").load_argument(index)
     }
@@ -353,6 +452,12 @@ impl StackUtils<f64> for Stack {
         self.stack.last_mut().expect("Stack Underflow").store_argument(index, value);
     }
 
+    fn set_tail_argument(&mut self, index: u8) {
+        let frame = self.stack.last_mut().expect("Stack Underflow");
+        let value: f64 = frame.pop();
+        frame.store_argument(index, value);
+    }
+
     fn get_argument(&mut self, index: u8) -> f64 {
         self.stack.last_mut().expect("Stack Underflow").load_argument(index)
     }
@@ -373,6 +478,12 @@ impl StackUtils<Reference> for Stack {
         self.stack.last_mut().expect("Stack Underflow").store_argument(index, value);
     }
 
+    fn set_tail_argument(&mut self, index: u8) {
+        let frame = self.stack.last_mut().expect("Stack Underflow");
+        let value: Reference = frame.pop();
+        frame.store_argument(index, value);
+    }
+
     fn get_argument(&mut self, index: u8) -> Reference {
         self.stack.last_mut().expect("Stack Underflow").load_argument(index)
     }
@@ -1,5 +1,7 @@
 
-use crate::{bytecode::Type, object::Reference, CocoaResult};
+use crate::{bytecode::Type, object::Reference, CocoaResult, ErrorInfo};
+
+use super::FrameSnapshot;
 
 macro_rules! promote {
     ($ty:ty, $ty2:expr, $stack:expr) => {
@@ -69,6 +71,8 @@ pub struct StackFrame {
     class_reference: Reference,
     method_index: usize,
     pc: usize,
+    /// One past the highest local that has been written
+    locals_used: usize,
 }
 
 
@@ -82,6 +86,7 @@ impl StackFrame {
             class_reference,
             method_index,
             pc: 0,
+            locals_used: 0,
         }
     }
 
@@ -101,6 +106,55 @@ impl StackFrame {
         self.method_index
     }
 
+    /// Points the frame at another method and clears its locals, keeping the operand stack
+    pub fn reset(&mut self, class_reference: Reference, method_index: usize) {
+        self.local_variables.fill(0);
+        self.local_variable_types.fill(Type::U8);
+        self.class_reference = class_reference;
+        self.method_index = method_index;
+        self.pc = 0;
+        self.locals_used = 0;
+    }
+
+    /// Copies the written locals and the operand stack out of the frame for inspection
+    pub fn snapshot(&self) -> FrameSnapshot {
+        let locals = (0..self.locals_used)
+            .map(|index| (self.local_variable_types[index], self.local_variables[index] as u64))
+            .collect();
+
+        let mut operands = Vec::new();
+        let mut offset = 0;
+        for ty in self.operand_stack_types.iter() {
+            let size = match ty {
+                Type::U8 | Type::I8 => 1,
+                Type::U16 | Type::I16 => 2,
+                Type::U32 | Type::I32 | Type::F32 => 4,
+                Type::U64 | Type::I64 | Type::F64 | Type::Reference => 8,
+                Type::Char(size) => *size as usize,
+            };
+            let mut bytes = [0; 8];
+            for (i, byte) in self.operand_stack[offset..offset + size].iter().take(8).enumerate() {
+                bytes[i] = *byte;
+            }
+            operands.push((*ty, u64::from_le_bytes(bytes)));
+            offset += size;
+        }
+
+        FrameSnapshot {
+            class_reference: self.class_reference,
+            method_index: self.method_index,
+            pc: self.pc,
+            locals,
+            operands,
+        }
+    }
+
+    /// Drops every value on the operand stack, keeping the locals
+    pub fn clear_operands(&mut self) {
+        self.operand_stack.clear();
+        self.operand_stack_types.clear();
+    }
+
     pub fn generic_pop(&mut self) {
         let ty = self.operand_stack_types.pop().expect("stack underflow");
         match ty {
@@ -461,6 +515,7 @@ impl StackFrame {
             },
         }
         self.local_variable_types[index as usize] = ty;
+        self.locals_used = self.locals_used.max(index as usize + 1);
     }
 
     pub fn load_local(&mut self, index: u8) {
@@ -1169,7 +1224,7 @@ impl StackFrame {
                 let val1 = self.operand_stack.pop().expect("Stack underflow");
                 let val2 = self.operand_stack.pop().expect("Stack underflow");
                 let Some(val) = val1.checked_div(val2) else {
-                    todo!("Overflow");
+                    return Err(ErrorInfo::new("Division by zero or overflow"));
                 };
                 self.operand_stack.push(val);
                 self.operand_stack_types.push(Type::U8);
@@ -1178,7 +1233,7 @@ impl StackFrame {
                 let val1 = i8::from_le_bytes([self.operand_stack.pop().expect("Stack underflow")]);
                 let val2 = i8::from_le_bytes([self.operand_stack.pop().expect("Stack underflow")]);
                 let Some(val) = val1.checked_div(val2) else {
-                    todo!("Overflow");
+                    return Err(ErrorInfo::new("Division by zero or overflow"));
                 };
                 self.operand_stack.push(val.to_le_bytes()[0]);
                 self.operand_stack_types.push(Type::I8);
@@ -1191,7 +1246,7 @@ impl StackFrame {
                 let val1 = u16::from_le_bytes([val1_b, val1_a]);
                 let val2 = u16::from_le_bytes([val2_b, val2_a]);
                 let Some(val) = val1.checked_div(val2) else {
-                    todo!("Overflow");
+                    return Err(ErrorInfo::new("Division by zero or overflow"));
                 };
                 self.operand_stack.push(val.to_le_bytes()[0]);
                 self.operand_stack.push(val.to_le_bytes()[1]);
@@ -1205,7 +1260,7 @@ impl StackFrame {
                 let val1 = i16::from_le_bytes([val1_b, val1_a]);
                 let val2 = i16::from_le_bytes([val2_b, val2_a]);
                 let Some(val) = val1.checked_div(val2) else {
-                    todo!("Overflow");
+                    return Err(ErrorInfo::new("Division by zero or overflow"));
                 };
                 self.operand_stack.push(val.to_le_bytes()[0]);
                 self.operand_stack.push(val.to_le_bytes()[1]);
@@ -1223,7 +1278,7 @@ impl StackFrame {
                 let val1 = u32::from_le_bytes([val1_d, val1_c, val1_b, val1_a]);
                 let val2 = u32::from_le_bytes([val2_d, val2_c, val2_b, val2_a]);
                 let Some(val) = val1.checked_div(val2) else {
-                    todo!("Overflow");
+                    return Err(ErrorInfo::new("Division by zero or overflow"));
                 };
                 self.operand_stack.push(val.to_le_bytes()[0]);
                 self.operand_stack.push(val.to_le_bytes()[1]);
@@ -1243,7 +1298,7 @@ impl StackFrame {
                 let val1 = i32::from_le_bytes([val1_d, val1_c, val1_b, val1_a]);
                 let val2 = i32::from_le_bytes([val2_d, val2_c, val2_b, val2_a]);
                 let Some(val) = val1.checked_div(val2) else {
-                    todo!("Overflow");
+                    return Err(ErrorInfo::new("Division by zero or overflow"));
                 };
                 self.operand_stack.push(val.to_le_bytes()[0]);
                 self.operand_stack.push(val.to_le_bytes()[1]);
@@ -1271,7 +1326,7 @@ impl StackFrame {
                 let val1 = u64::from_le_bytes([val1_h, val1_g, val1_f, val1_e, val1_d, val1_c, val1_b, val1_a]);
                 let val2 = u64::from_le_bytes([val2_h, val2_g, val2_f, val2_e, val2_d, val2_c, val2_b, val2_a]);
                 let Some(val) = val1.checked_div(val2) else {
-                    todo!("Overflow");
+                    return Err(ErrorInfo::new("Division by zero or overflow"));
                 };
                 self.operand_stack.push(val.to_le_bytes()[0]);
                 self.operand_stack.push(val.to_le_bytes()[1]);
@@ -1303,7 +1358,7 @@ impl StackFrame {
                 let val1 = i64::from_le_bytes([val1_h, val1_g, val1_f, val1_e, val1_d, val1_c, val1_b, val1_a]);
                 let val2 = i64::from_le_bytes([val2_h, val2_g, val2_f, val2_e, val2_d, val2_c, val2_b, val2_a]);
                 let Some(val) = val1.checked_div(val2) else {
-                    todo!("Overflow");
+                    return Err(ErrorInfo::new("Division by zero or overflow"));
                 };
                 self.operand_stack.push(val.to_le_bytes()[0]);
                 self.operand_stack.push(val.to_le_bytes()[1]);
@@ -1328,7 +1383,7 @@ impl StackFrame {
                 let val2 = f32::from_le_bytes([val2_d, val2_c, val2_b, val2_a]);
 
                 if val2 == 0.0 {
-                    todo!("Division by zero");
+                    return Err(ErrorInfo::new("Division by zero"));
                 }
 
                 let val = val1 / val2;
@@ -1360,7 +1415,7 @@ impl StackFrame {
                 let val2 = f64::from_le_bytes([val2_h, val2_g, val2_f, val2_e, val2_d, val2_c, val2_b, val2_a]);
 
                 if val2 == 0.0 {
-                    todo!("Division by zero");
+                    return Err(ErrorInfo::new("Division by zero"));
                 }
 
                 let val = val1 / val2;
@@ -1391,7 +1446,7 @@ impl StackFrame {
                 let val1 = self.operand_stack.pop().expect("Stack underflow");
                 let val2 = self.operand_stack.pop().expect("Stack underflow");
                 let Some(val) = val1.checked_rem(val2) else {
-                    todo!("Overflow");
+                    return Err(ErrorInfo::new("Division by zero or overflow"));
                 };
                 self.operand_stack.push(val);
                 self.operand_stack_types.push(Type::U8);
@@ -1400,7 +1455,7 @@ impl StackFrame {
                 let val1 = i8::from_le_bytes([self.operand_stack.pop().expect("Stack underflow")]);
                 let val2 = i8::from_le_bytes([self.operand_stack.pop().expect("Stack underflow")]);
                 let Some(val) = val1.checked_rem(val2) else {
-                    todo!("Overflow");
+                    return Err(ErrorInfo::new("Division by zero or overflow"));
                 };
                 self.operand_stack.push(val.to_le_bytes()[0]);
                 self.operand_stack_types.push(Type::I8);
@@ -1413,7 +1468,7 @@ impl StackFrame {
                 let val1 = u16::from_le_bytes([val1_b, val1_a]);
                 let val2 = u16::from_le_bytes([val2_b, val2_a]);
                 let Some(val) = val1.checked_rem(val2) else {
-                    todo!("Overflow");
+                    return Err(ErrorInfo::new("Division by zero or overflow"));
                 };
                 self.operand_stack.push(val.to_le_bytes()[0]);
                 self.operand_stack.push(val.to_le_bytes()[1]);
@@ -1427,7 +1482,7 @@ impl StackFrame {
                 let val1 = i16::from_le_bytes([val1_b, val1_a]);
                 let val2 = i16::from_le_bytes([val2_b, val2_a]);
                 let Some(val) = val1.checked_rem(val2) else {
-                    todo!("Overflow");
+                    return Err(ErrorInfo::new("Division by zero or overflow"));
                 };
                 self.operand_stack.push(val.to_le_bytes()[0]);
                 self.operand_stack.push(val.to_le_bytes()[1]);
@@ -1445,7 +1500,7 @@ impl StackFrame {
                 let val1 = u32::from_le_bytes([val1_d, val1_c, val1_b, val1_a]);
                 let val2 = u32::from_le_bytes([val2_d, val2_c, val2_b, val2_a]);
                 let Some(val) = val1.checked_rem(val2) else {
-                    todo!("Overflow");
+                    return Err(ErrorInfo::new("Division by zero or overflow"));
                 };
                 self.operand_stack.push(val.to_le_bytes()[0]);
                 self.operand_stack.push(val.to_le_bytes()[1]);
@@ -1465,7 +1520,7 @@ impl StackFrame {
                 let val1 = i32::from_le_bytes([val1_d, val1_c, val1_b, val1_a]);
                 let val2 = i32::from_le_bytes([val2_d, val2_c, val2_b, val2_a]);
                 let Some(val) = val1.checked_rem(val2) else {
-                    todo!("Overflow");
+                    return Err(ErrorInfo::new("Division by zero or overflow"));
                 };
                 self.operand_stack.push(val.to_le_bytes()[0]);
                 self.operand_stack.push(val.to_le_bytes()[1]);
@@ -1493,7 +1548,7 @@ impl StackFrame {
                 let val1 = u64::from_le_bytes([val1_h, val1_g, val1_f, val1_e, val1_d, val1_c, val1_b, val1_a]);
                 let val2 = u64::from_le_bytes([val2_h, val2_g, val2_f, val2_e, val2_d, val2_c, val2_b, val2_a]);
                 let Some(val) = val1.checked_rem(val2) else {
-                    todo!("Overflow");
+                    return Err(ErrorInfo::new("Division by zero or overflow"));
                 };
                 self.operand_stack.push(val.to_le_bytes()[0]);
                 self.operand_stack.push(val.to_le_bytes()[1]);
@@ -1525,7 +1580,7 @@ impl StackFrame {
                 let val1 = i64::from_le_bytes([val1_h, val1_g, val1_f, val1_e, val1_d, val1_c, val1_b, val1_a]);
                 let val2 = i64::from_le_bytes([val2_h, val2_g, val2_f, val2_e, val2_d, val2_c, val2_b, val2_a]);
                 let Some(val) = val1.checked_rem(val2) else {
-                    todo!("Overflow");
+                    return Err(ErrorInfo::new("Division by zero or overflow"));
                 };
                 self.operand_stack.push(val.to_le_bytes()[0]);
                 self.operand_stack.push(val.to_le_bytes()[1]);
@@ -1550,7 +1605,7 @@ impl StackFrame {
                 let val2 = f32::from_le_bytes([val2_d, val2_c, val2_b, val2_a]);
 
                 if val2 == 0.0 {
-                    todo!("Division by zero");
+                    return Err(ErrorInfo::new("Division by zero"));
                 }
 
                 let val = val1 % val2;
@@ -1582,7 +1637,7 @@ impl StackFrame {
                 let val2 = f64::from_le_bytes([val2_h, val2_g, val2_f, val2_e, val2_d, val2_c, val2_b, val2_a]);
 
                 if val2 == 0.0 {
-                    todo!("Division by zero");
+                    return Err(ErrorInfo::new("Division by zero"));
                 }
 
                 let val = val1 % val2;
@@ -3255,6 +3310,7 @@ impl StackFrameUtils<i8> for StackFrame {
         self.local_variables[index as usize] = 0;
         self.local_variables[index as usize] = value.to_le_bytes()[0] as usize;
         self.local_variable_types[index as usize] = Type::I8;
+        self.locals_used = self.locals_used.max(index as usize + 1);
     }
 
     fn load_argument(&mut self, index: u8) -> i8 {
@@ -3281,6 +3337,7 @@ impl StackFrameUtils<i16> for StackFrame {
         self.local_variables[index as usize] = 0;
         self.local_variables[index as usize] = u16::from_le_bytes([value.to_le_bytes()[0], value.to_le_bytes()[1]]) as usize;
         self.local_variable_types[index as usize] = Type::I16;
+        self.locals_used = self.locals_used.max(index as usize + 1);
     }
 
     fn load_argument(&mut self, index: u8) -> i16 {
@@ -3311,6 +3368,7 @@ impl StackFrameUtils<i32> for StackFrame {
         self.local_variables[index as usize] = 0;
         self.local_variables[index as usize] = i32::from_le_bytes([value.to_le_bytes()[0], value.to_le_bytes()[1], value.to_le_bytes()[2], value.to_le_bytes()[3]]) as usize;
         self.local_variable_types[index as usize] = Type::I32;
+        self.locals_used = self.locals_used.max(index as usize + 1);
     }
 
     fn load_argument(&mut self, index: u8) -> i32 {
@@ -3349,6 +3407,7 @@ impl StackFrameUtils<i64> for StackFrame {
         self.local_variables[index as usize] = 0;
         self.local_variables[index as usize] = u64::from_le_bytes([value.to_le_bytes()[0], value.to_le_bytes()[1], value.to_le_bytes()[2], value.to_le_bytes()[3], value.to_le_bytes()[4], value.to_le_bytes()[5], value.to_le_bytes()[6], value.to_le_bytes()[7]]) as usize;
         self.local_variable_types[index as usize] = Type::I64;
+        self.locals_used = self.locals_used.max(index as usize + 1);
     }
 
     fn load_argument(&mut self, index: u8) -> i64 {
@@ -3372,6 +3431,7 @@ impl StackFrameUtils<u8> for StackFrame {
         self.local_variables[index as usize] = 0;
         self.local_variables[index as usize] = value as usize;
         self.local_variable_types[index as usize] = Type::U8;
+        self.locals_used = self.locals_used.max(index as usize + 1);
     }
 
     fn load_argument(&mut self, index: u8) -> u8 {
@@ -3398,6 +3458,7 @@ impl StackFrameUtils<u16> for StackFrame {
         self.local_variables[index as usize] = 0;
         self.local_variables[index as usize] = value as usize;
         self.local_variable_types[index as usize] = Type::U16;
+        self.locals_used = self.locals_used.max(index as usize + 1);
     }
 
     fn load_argument(&mut self, index: u8) -> u16 {
@@ -3428,6 +3489,7 @@ impl StackFrameUtils<u32> for StackFrame {
         self.local_variables[index as usize] = 0;
         self.local_variables[index as usize] = value as usize;
         self.local_variable_types[index as usize] = Type::U32;
+        self.locals_used = self.locals_used.max(index as usize + 1);
     }
 
     fn load_argument(&mut self, index: u8) -> u32 {
@@ -3466,6 +3528,7 @@ impl StackFrameUtils<u64> for StackFrame {
         self.local_variables[index as usize] = 0;
         self.local_variables[index as usize] = value as usize;
         self.local_variable_types[index as usize] = Type::U64;
+        self.locals_used = self.locals_used.max(index as usize + 1);
     }
 
     fn load_argument(&mut self, index: u8) -> u64 {
@@ -3498,6 +3561,7 @@ impl StackFrameUtils<f32> for StackFrame {
         self.local_variables[index as usize] = 0;
         self.local_variables[index as usize] = value as usize;
         self.local_variable_types[index as usize] = Type::F32;
+        self.locals_used = self.locals_used.max(index as usize + 1);
     }
 
     fn load_argument(&mut self, index: u8) -> f32 {
@@ -3538,6 +3602,7 @@ impl StackFrameUtils<f64> for StackFrame {
         self.local_variables[index as usize] = 0;
         self.local_variables[index as usize] = value as usize;
         self.local_variable_types[index as usize] = Type::F64;
+        self.locals_used = self.locals_used.max(index as usize + 1);
     }
 
     fn load_argument(&mut self, index: u8) -> f64 {
@@ -3576,6 +3641,7 @@ impl StackFrameUtils<Reference> for StackFrame {
         self.local_variables[index as usize] = 0;
         self.local_variables[index as usize] = value as usize;
         self.local_variable_types[index as usize] = Type::Reference;
+        self.locals_used = self.locals_used.max(index as usize + 1);
     }
 
     fn load_argument(&mut self, index: u8) -> Reference {
@@ -3745,7 +3811,22 @@ mod test {
         assert_eq!(StackFrameUtils::<Reference>::load_argument(&mut stack_frame, 0), 1);
     }
 
-    
-    
-
+    #[test]
+    fn test_snapshot() {
+        let mut stack_frame = StackFrame::new(3, 1);
+        stack_frame.store_argument(0, -2i32);
+        stack_frame.push(7u8);
+        stack_frame.store_local(2);
+        stack_frame.push(1.5f64);
+        stack_frame.push(-1i16);
+
+        let snapshot = stack_frame.snapshot();
+        assert_eq!(snapshot.class_reference, 3);
+        assert_eq!(snapshot.method_index, 1);
+        assert_eq!(snapshot.locals.len(), 3);
+        assert_eq!(snapshot.locals[0].0, Type::I32);
+        assert_eq!(snapshot.locals[0].1 as u32 as i32, -2);
+        assert_eq!(snapshot.locals[2], (Type::U8, 7));
+        assert_eq!(snapshot.operands, vec![(Type::F64, 1.5f64.to_bits()), (Type::I16, 0xffff)]);
+    }
 }
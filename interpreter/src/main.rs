@@ -1,10 +1,163 @@
-use definitions::{bytecode::Bytecode, class::{ClassHeader, ClassInfo, Method, MethodFlags, MethodInfo, PoolEntry, TypeInfo}};
-use virtual_machine::{ConstantPoolSingleton, Linker, Machine, NativeMethodTable, ObjectTableSingleton};
+use definitions::{bytecode::Bytecode, class::{ClassHeader, ClassInfo, Method, MethodFlags, MethodInfo, PoolEntry, TypeInfo}, class_file};
+use virtual_machine::{Breakpoint, ClassPath, Console, Debugger, Limits, ObjectRef, Profiler, TraceFilter, Tracer, VmInstance};
 
 mod virtual_machine;
 
 
+/// Reports a problem with the command line or the files it names and exits
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+fn load_classes(paths: &[String]) -> Vec<ClassHeader> {
+    paths.iter().map(|path| {
+        let file = std::fs::File::open(path).unwrap_or_else(|error| fail(format!("Failed to open {}: {}", path, error)));
+        let mut reader = std::io::BufReader::new(file);
+        class_file::read_class(&mut reader).unwrap_or_else(|error| fail(format!("Failed to load {}: {}", path, error)))
+    }).collect()
+}
+
+/// Parses a breakpoint written as `class:method:pc`
+fn parse_breakpoint(location: &str) -> Option<Breakpoint> {
+    let mut parts = location.rsplitn(3, ':');
+    let pc = parts.next()?.parse().ok()?;
+    let method = parts.next()?.parse().ok()?;
+    let class = parts.next()?.to_owned();
+    Some(Breakpoint { class, method, pc })
+}
+
+/// Removes `flag` and the number after it from the arguments
+fn take_number<T: std::str::FromStr>(args: &mut Vec<String>, flag: &str) -> Option<T> {
+    let i = args.iter().position(|arg| arg == flag)?;
+    if i + 1 >= args.len() {
+        fail(format!("{} needs a number", flag));
+    }
+    let value = args.remove(i + 1);
+    args.remove(i);
+    Some(value.parse().unwrap_or_else(|_| fail(format!("{} needs a number, found {}", flag, value))))
+}
+
 fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let class_path = match args.iter().position(|arg| arg == "--classpath") {
+        Some(i) if i + 1 < args.len() => {
+            let search_path = args.remove(i + 1);
+            args.remove(i);
+            Some(ClassPath::from_search_path(&search_path).unwrap_or_else(|error| fail(format!("Invalid class path: {}", error))))
+        }
+        Some(_) => fail(String::from("--classpath needs a search path")),
+        None => None,
+    };
+    let mut debug = match args.iter().position(|arg| arg == "--debug") {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    };
+    let mut breakpoints = Vec::new();
+    while let Some(i) = args.iter().position(|arg| arg == "--break") {
+        if i + 1 >= args.len() {
+            fail(String::from("--break needs a location like Main:0:3"));
+        }
+        let location = args.remove(i + 1);
+        args.remove(i);
+        breakpoints.push(parse_breakpoint(&location).unwrap_or_else(|| fail(format!("Invalid breakpoint {}", location))));
+        debug = true;
+    }
+    let mut tracer = match args.iter().position(|arg| arg == "--trace") {
+        Some(i) => {
+            args.remove(i);
+            Some(Tracer::stderr())
+        }
+        None => None,
+    };
+    match args.iter().position(|arg| arg == "--trace-file") {
+        Some(i) if i + 1 < args.len() => {
+            let path = args.remove(i + 1);
+            args.remove(i);
+            tracer = Some(Tracer::to_file(&path).unwrap_or_else(|error| fail(error.to_string())));
+        }
+        Some(_) => fail(String::from("--trace-file needs a path")),
+        None => {}
+    }
+    while let Some(i) = args.iter().position(|arg| arg == "--trace-filter") {
+        if i + 1 >= args.len() {
+            fail(String::from("--trace-filter needs a class, class:method or :method"));
+        }
+        let filter = TraceFilter::parse(&args.remove(i + 1));
+        args.remove(i);
+        tracer = Some(tracer.unwrap_or_else(Tracer::stderr).with_filter(filter));
+    }
+    let mut profile = match args.iter().position(|arg| arg == "--profile") {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    };
+    let collapsed_stacks = match args.iter().position(|arg| arg == "--profile-collapsed") {
+        Some(i) if i + 1 < args.len() => {
+            let path = args.remove(i + 1);
+            args.remove(i);
+            profile = true;
+            Some(path)
+        }
+        Some(_) => fail(String::from("--profile-collapsed needs a path")),
+        None => None,
+    };
+    let limits = Limits {
+        fuel: take_number(&mut args, "--fuel"),
+        max_call_depth: take_number(&mut args, "--max-call-depth"),
+        max_live_objects: take_number(&mut args, "--max-live-objects"),
+        max_array_bytes: take_number(&mut args, "--max-array-bytes"),
+    };
+    if !args.is_empty() || class_path.is_some() {
+        let classes = load_classes(&args);
+
+        let instance = VmInstance::new();
+        let mut linker = instance.linker();
+        if let Some(class_path) = &class_path {
+            linker = linker.with_class_loader(class_path);
+        }
+
+        let (class_ref, method_index) = match linker.link_classes(classes, "Main", "Main") {
+            Ok(main) => main,
+            Err(error) => fail(format!("Failed to link: {}", error)),
+        };
+
+        let mut vm = instance.machine().with_linker(linker).with_limits(limits);
+        if profile {
+            vm = vm.with_profiler(Profiler::new());
+        }
+        if let Some(tracer) = tracer {
+            vm = vm.with_tracer(tracer);
+        }
+        if debug {
+            let console = Console::new(std::io::stdin().lock(), std::io::stdout());
+            let mut debugger = Debugger::new(Box::new(console));
+            if breakpoints.is_empty() {
+                debugger = debugger.pause_on_start();
+            }
+            for breakpoint in breakpoints {
+                debugger.add_breakpoint(breakpoint);
+            }
+            vm = vm.with_debugger(debugger);
+        }
+
+        let result = vm.run_bootstrap(class_ref, method_index);
+        if let Some(profiler) = vm.profiler() {
+            eprint!("{}", profiler.report());
+            if let Some(path) = &collapsed_stacks {
+                std::fs::write(path, profiler.collapsed_stacks()).unwrap_or_else(|error| fail(format!("Failed to write {}: {}", path, error)));
+            }
+        }
+        if let Err(error) = result {
+            fail(error.to_string());
+        }
+        return;
+    }
 
         let mut class = ClassHeader::new(10, 0, 0, 2, 0);
 
@@ -20,7 +173,7 @@ fn main() {
             class_ref: None,
         }));
         class.set_constant_pool_entry(2, PoolEntry::String("MainBase".to_owned()));
-        class.set_constant_pool_entry(3, PoolEntry::String("Object".to_owned()));
+        class.set_constant_pool_entry(3, PoolEntry::String("cocoa.lang.Object".to_owned()));
         class.set_constant_pool_entry(4, PoolEntry::Method(Method::Bytecode(vec![Bytecode::New(0), Bytecode::InvokeVirtual(1), Bytecode::Return].into())));
         class.set_constant_pool_entry(5, PoolEntry::Method(Method::Native(0)));
         class.set_constant_pool_entry(6, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![], ret: Box::new(TypeInfo::U64) }));
@@ -43,7 +196,7 @@ fn main() {
         
         let parent_class = class;
         
-        let mut class = ClassHeader::new(10, 0, 0, 2, 0);
+        let mut class = ClassHeader::new(6, 0, 0, 1, 0);
 
         class.set_parent_info(1);
         class.set_this_info(0);
@@ -59,34 +212,25 @@ fn main() {
         class.set_constant_pool_entry(2, PoolEntry::String("Main".to_owned()));
         class.set_constant_pool_entry(3, PoolEntry::String("MainBase".to_owned()));
         class.set_constant_pool_entry(4, PoolEntry::Method(Method::Bytecode(vec![Bytecode::New(0), Bytecode::InvokeVirtual(1), Bytecode::Return].into())));
-        class.set_constant_pool_entry(5, PoolEntry::Method(Method::Foreign(1)));
-        class.set_constant_pool_entry(6, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(7, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![TypeInfo::Object(3)], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(8, PoolEntry::String("printRef".to_owned()));
+        class.set_constant_pool_entry(5, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![], ret: Box::new(TypeInfo::U64) }));
 
         class.set_method(0, MethodInfo {
             flags: MethodFlags::Static,
             name: 2,
-            type_info: 6,
+            type_info: 5,
             location: 4,
         });
 
-        class.set_method(1, MethodInfo {
-            flags: MethodFlags::Public,
-            name: 8,
-            type_info: 7,
-            location: 5,
+        let mut instance = VmInstance::new();
+        instance.native_methods().register_fn("MainBase.printRef", |object: ObjectRef| {
+            println!("{}", object.0);
+            0u64
         });
+        let mut linker = instance.linker();
 
-        let constant_pool = ConstantPoolSingleton::new();
-        let object_table = ObjectTableSingleton::get_singleton();
-        let mut linker = Linker::new(&constant_pool, &object_table);
-
-    let (class_ref, method_index) = linker.link_classes(vec![parent_class, class], "Main", "Main");
-
-    let method_table = NativeMethodTable::get_table();
+    let (class_ref, method_index) = linker.link_classes(vec![parent_class, class], "Main", "Main").unwrap();
 
-    let mut vm = Machine::new(&object_table, &method_table, &constant_pool);
+    let mut vm = instance.machine();
 
     vm.run_bootstrap(class_ref, method_index).unwrap();
 }
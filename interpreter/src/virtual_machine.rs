@@ -2,18 +2,35 @@ mod object_table;
 mod machine;
 mod constant_pool;
 mod linker;
+mod verifier;
+mod class_loader;
+mod debugger;
+mod tracer;
+mod profiler;
+mod instance;
+mod convert;
+#[cfg(test)]
+mod fixtures;
 
 use definitions::ArgType;
-pub use object_table::ObjectTableSingleton;
-pub use machine::Machine;
+pub use object_table::InstanceObjectTable;
+pub use machine::{Limits, Machine};
 pub use machine::MethodTable;
 pub use machine::ObjectTable;
 pub use machine::ConstantPool;
 pub use linker::Linker;
-pub use constant_pool::ConstantPoolSingleton;
+pub use verifier::{Verifier, VerifyError, VerifyErrorKind};
+pub use constant_pool::InstanceConstantPool;
+pub use class_loader::{ClassLoader, ClassPath};
+pub use debugger::{Breakpoint, Console, Debugger};
+pub use tracer::{TraceFilter, Tracer};
+pub use profiler::Profiler;
+pub use instance::VmInstance;
+pub use convert::{FromCocoa, IntoCocoa, NativeFunction, ObjectRef};
 
-use definitions::CocoaResult;
-use once_cell::sync::Lazy;
+use std::{collections::HashMap, sync::Arc};
+
+use definitions::{class::{NativeMethodIndex, TypeInfo}, object::with_view, CocoaResult, ErrorInfo};
 
 pub type RustNativeMethod = fn(
     &[ArgType],
@@ -22,27 +39,104 @@ pub type RustNativeMethod = fn(
     constant_pool: &dyn ConstantPool
 ) -> CocoaResult<ArgType>;
 
-#[derive(Clone, Copy)]
+/// A native method that captures state, registered with [`NativeMethodTable::register_closure`]
+pub type RustNativeClosure = dyn Fn(
+    &[ArgType],
+    &dyn ObjectTable,
+    &dyn MethodTable,
+    &dyn ConstantPool
+) -> CocoaResult<ArgType> + Send + Sync;
+
+/// Checks the `TypeInfo::Method` of a method a typed function is bound to
+type SignatureCheck = fn(&TypeInfo) -> bool;
+
+#[derive(Clone)]
 enum NativeMethod {
     Rust(RustNativeMethod),
+    Closure(Arc<RustNativeClosure>),
 }
 
-static NATIVE_METHOD_TABLE: Lazy<Vec<NativeMethod>> = Lazy::new(|| {
-    vec![
-        NativeMethod::Rust(array_size),
-]});
-
-pub struct NativeMethodTable {}
+/// The native methods of a single virtual machine instance
+///
+/// Methods are registered under the qualified name of the method they implement, such as
+/// `cocoa.lang.Array.size`, and the linker binds native methods in classes by that name.
+pub struct NativeMethodTable {
+    methods: Vec<NativeMethod>,
+    /// Untyped methods have no signature to check
+    signatures: Vec<Option<SignatureCheck>>,
+    names: HashMap<String, NativeMethodIndex>,
+}
 
 impl NativeMethodTable {
-    pub fn get_table() -> Self {
-        Self {}
+    /// A table with the built in native methods
+    pub fn new() -> Self {
+        let mut table = Self {
+            methods: Vec::new(),
+            signatures: Vec::new(),
+            names: HashMap::new(),
+        };
+        table.register("cocoa.lang.Array.size", array_size);
+        table.register("cocoa.lang.Object.getClass", object_get_class);
+        table
+    }
+
+    /// Binds a method to a qualified name, replacing whatever was bound to it before
+    pub fn register(&mut self, name: impl Into<String>, method: RustNativeMethod) -> NativeMethodIndex {
+        self.bind(name.into(), NativeMethod::Rust(method))
+    }
+
+    /// Binds a closure to a qualified name, replacing whatever was bound to it before
+    pub fn register_closure<F>(&mut self, name: impl Into<String>, method: F) -> NativeMethodIndex
+    where
+        F: Fn(&[ArgType], &dyn ObjectTable, &dyn MethodTable, &dyn ConstantPool) -> CocoaResult<ArgType> + Send + Sync + 'static,
+    {
+        self.bind(name.into(), NativeMethod::Closure(Arc::new(method)))
+    }
+
+    /// Binds an ordinary Rust function like `fn(i32, i32) -> i64` to a qualified name
+    ///
+    /// The arguments and result are converted with [`FromCocoa`] and [`IntoCocoa`], and the linker
+    /// refuses to bind the function to a method whose signature doesn't match it.
+    pub fn register_fn<Args, F: NativeFunction<Args>>(&mut self, name: impl Into<String>, function: F) -> NativeMethodIndex {
+        let index = self.register_closure(name, move |args, object_table, _, _| function.call(args, object_table));
+        self.signatures[index] = Some(F::signature_matches);
+        index
+    }
+
+    fn bind(&mut self, name: String, method: NativeMethod) -> NativeMethodIndex {
+        match self.names.get(&name) {
+            Some(index) => {
+                self.methods[*index] = method;
+                self.signatures[*index] = None;
+                *index
+            }
+            None => {
+                self.methods.push(method);
+                self.signatures.push(None);
+                self.names.insert(name, self.methods.len() - 1);
+                self.methods.len() - 1
+            }
+        }
+    }
+}
+
+impl Default for NativeMethodTable {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl MethodTable for NativeMethodTable {
-    fn get_method(&self, index: usize) -> NativeMethod {
-        NATIVE_METHOD_TABLE[index]
+    fn get_method(&self, index: usize) -> Option<NativeMethod> {
+        self.methods.get(index).cloned()
+    }
+
+    fn resolve(&self, name: &str) -> Option<NativeMethodIndex> {
+        self.names.get(name).copied()
+    }
+
+    fn accepts(&self, index: NativeMethodIndex, type_info: &TypeInfo) -> bool {
+        self.signatures[index].is_none_or(|matches| matches(type_info))
     }
 }
 
@@ -52,13 +146,75 @@ fn array_size(
     _: &dyn MethodTable,
     _: &dyn ConstantPool
 ) -> CocoaResult<ArgType> {
-    if let ArgType::Reference(reference) = args[0] {
-        if !object_table.is_array(reference) {
-            todo!("Handle non-array reference")
-        }
-        let object = object_table.get_array(reference);
-        Ok(ArgType::U64(object.get_size() as u64))
+    let array = args.first().ok_or_else(|| ErrorInfo::new("Array.size needs an array"))?;
+    let ObjectRef(reference) = ObjectRef::from_cocoa(array, object_table)?;
+    if !object_table.is_array(reference) {
+        return Err(ErrorInfo::new("Array.size needs an array"));
+    }
+    let size = object_table.get_array(reference).get_size() as u64;
+    size.into_cocoa(object_table)
+}
+
+fn object_get_class(
+    args: &[ArgType],
+    object_table: &dyn ObjectTable,
+    _: &dyn MethodTable,
+    _: &dyn ConstantPool
+) -> CocoaResult<ArgType> {
+    let object = args.first().ok_or_else(|| ErrorInfo::new("Object.getClass needs an object"))?;
+    let ObjectRef(reference) = ObjectRef::from_cocoa(object, object_table)?;
+    let reference = with_view(reference, 0);
+    let class_ref = if object_table.is_object(reference) {
+        object_table.get_object(reference).get_class()
+    } else if object_table.is_array(reference) {
+        object_table.get_array(reference).get_class()
+    } else if object_table.is_string(reference) {
+        object_table.get_string(reference).get_class()
     } else {
-        todo!("Handle non-reference argument")
+        return Err(ErrorInfo::new("Object.getClass needs an object"));
+    };
+    ObjectRef(class_ref).into_cocoa(object_table)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    /// The `package.Class.method` names of the methods the stdlib sources declare with `@`
+    fn stdlib_natives(dir: &Path, natives: &mut Vec<String>) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                stdlib_natives(&path, natives);
+                continue;
+            }
+            let source = std::fs::read_to_string(&path).unwrap();
+            let package = source.lines()
+                .find_map(|line| line.trim().strip_prefix("package "))
+                .map(|package| package.trim_end_matches(';').trim())
+                .unwrap();
+            let class = path.file_stem().unwrap().to_str().unwrap();
+            for line in source.lines().map(str::trim).filter(|line| !line.starts_with("//")) {
+                let Some(at) = line.find('@') else { continue };
+                assert_eq!(line[at..].trim(), "@;", "{}: natives are bound by name and take no index: {}", path.display(), line);
+                let name = line.split("fn ").nth(1).and_then(|rest| rest.split('(').next()).unwrap();
+                natives.push(format!("{}.{}.{}", package, class, name.trim()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_stdlib_natives_are_registered() {
+        let mut natives = Vec::new();
+        stdlib_natives(&Path::new(env!("CARGO_MANIFEST_DIR")).join("../stdlib"), &mut natives);
+        natives.sort();
+        assert_eq!(natives, ["cocoa.lang.Array.size", "cocoa.lang.Object.getClass"]);
+
+        let table = NativeMethodTable::new();
+        for native in &natives {
+            assert!(table.resolve(native).is_some(), "{} is not registered", native);
+        }
     }
 }
@@ -0,0 +1,133 @@
+use std::{collections::HashMap, path::{Path, PathBuf}};
+
+use definitions::{class::ClassHeader, class_file, CocoaResult, ErrorInfo};
+
+
+
+pub trait ClassLoader {
+    /// Reads the class with the given fully qualified name, or returns `None` if it can't be found
+    fn load_class(&self, name: &str) -> CocoaResult<Option<ClassHeader>>;
+}
+
+enum ClassPathEntry {
+    /// A directory where `cocoa.lang.Object` lives at `cocoa/lang/Object.cocoac`
+    Directory(PathBuf),
+    /// The encoded classes of a `.cocoaa` archive by name
+    Archive(HashMap<String, Vec<u8>>),
+}
+
+/// Searches directories and archives in order for class files
+pub struct ClassPath {
+    entries: Vec<ClassPathEntry>,
+}
+
+impl ClassPath {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Builds a class path from a list of paths joined with the platform's path separator
+    ///
+    /// Paths ending in `.cocoaa` are read as archives, everything else is searched as a directory.
+    pub fn from_search_path(search_path: &str) -> CocoaResult<Self> {
+        let mut class_path = Self::new();
+        for path in std::env::split_paths(search_path) {
+            if path.extension().is_some_and(|extension| extension == class_file::ARCHIVE_EXTENSION) {
+                class_path.add_archive(&path)?;
+            } else {
+                class_path.add_directory(path);
+            }
+        }
+        Ok(class_path)
+    }
+
+    pub fn add_directory(&mut self, path: impl Into<PathBuf>) {
+        self.entries.push(ClassPathEntry::Directory(path.into()));
+    }
+
+    pub fn add_archive(&mut self, path: impl AsRef<Path>) -> CocoaResult<()> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .map_err(|error| ErrorInfo::new(format!("Failed to open {}: {}", path.display(), error)))?;
+        let mut reader = std::io::BufReader::new(file);
+        let classes = class_file::read_archive(&mut reader)
+            .map_err(|error| ErrorInfo::new(format!("Failed to read {}: {}", path.display(), error)))?;
+        self.entries.push(ClassPathEntry::Archive(classes));
+        Ok(())
+    }
+}
+
+impl Default for ClassPath {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClassLoader for ClassPath {
+    fn load_class(&self, name: &str) -> CocoaResult<Option<ClassHeader>> {
+        for entry in self.entries.iter() {
+            match entry {
+                ClassPathEntry::Directory(directory) => {
+                    let mut path = directory.clone();
+                    path.extend(name.split('.'));
+                    path.set_extension(class_file::EXTENSION);
+                    if !path.is_file() {
+                        continue;
+                    }
+                    let bytes = std::fs::read(&path)
+                        .map_err(|error| ErrorInfo::new(format!("Failed to read {}: {}", path.display(), error)))?;
+                    return class_file::class_from_bytes(&bytes).map(Some);
+                }
+                ClassPathEntry::Archive(classes) => {
+                    if let Some(bytes) = classes.get(name) {
+                        return class_file::class_from_bytes(bytes).map(Some);
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use definitions::assembler::assemble;
+
+    use super::*;
+
+    fn class_named(name: &str) -> ClassHeader {
+        assemble(&format!(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "{}"
+            .const parent_name string "cocoa.lang.Object"
+        "#, name)).unwrap()
+    }
+
+    #[test]
+    fn test_class_path() {
+        let root = std::env::temp_dir().join(format!("cocoa-class-path-{}", std::process::id()));
+        let directory = root.join("classes");
+        std::fs::create_dir_all(directory.join("cocoa/lang")).unwrap();
+        std::fs::write(directory.join("cocoa/lang/Object.cocoac"), class_file::class_to_bytes(&class_named("cocoa.lang.Object"))).unwrap();
+
+        let archive = root.join("app.cocoaa");
+        let mut bytes = Vec::new();
+        class_file::write_archive(&mut bytes, &[("app.Main", class_named("app.Main"))]).unwrap();
+        std::fs::write(&archive, bytes).unwrap();
+
+        let search_path = std::env::join_paths([&directory, &archive]).unwrap();
+        let class_path = ClassPath::from_search_path(search_path.to_str().unwrap()).unwrap();
+
+        let object = class_path.load_class("cocoa.lang.Object").unwrap().unwrap();
+        assert_eq!(object.get_constant_pool_entry(2), &definitions::class::PoolEntry::String("cocoa.lang.Object".to_owned()));
+        let main = class_path.load_class("app.Main").unwrap().unwrap();
+        assert_eq!(main.get_constant_pool_entry(2), &definitions::class::PoolEntry::String("app.Main".to_owned()));
+        assert!(class_path.load_class("app.Missing").unwrap().is_none());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
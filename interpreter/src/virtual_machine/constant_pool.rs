@@ -1,26 +1,31 @@
 use std::sync::RwLock;
 
 use definitions::class::{PoolEntry, PoolIndex};
-use once_cell::sync::Lazy;
 
 use super::machine::ConstantPool;
 
 
-static CONSTANT_POOL: Lazy<RawConstantPool> = Lazy::new(|| {
-    RawConstantPool::new()
-});
-
+/// The constant pool of a single virtual machine instance
 #[derive(Debug)]
-struct RawConstantPool {
+pub struct InstanceConstantPool {
     pool: RwLock<Vec<PoolEntry>>,
 }
 
-impl RawConstantPool {
+impl InstanceConstantPool {
     pub fn new() -> Self {
         Self {
             pool: RwLock::new(Vec::new()),
         }
     }
+}
+
+impl Default for InstanceConstantPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConstantPool for InstanceConstantPool {
     fn add_constant(&self, entry: PoolEntry) -> PoolIndex {
         let mut pool = self.pool.write().expect("Constant pool poisoned");
         let index = pool.len();
@@ -36,25 +41,3 @@ impl RawConstantPool {
         self.pool.read().expect("Constant pool poisoned").get(index).unwrap().clone()
     }
 }
-
-pub struct ConstantPoolSingleton {}
-
-impl ConstantPoolSingleton {
-    pub fn new() -> Self {
-        Self {}
-    }
-}
-
-impl ConstantPool for ConstantPoolSingleton {
-    fn add_constant(&self, entry: PoolEntry) -> PoolIndex {
-        CONSTANT_POOL.add_constant(entry)
-    }
-
-    fn set_constant(&self, index: PoolIndex, entry: PoolEntry) {
-        CONSTANT_POOL.set_constant(index, entry)
-    }
-
-    fn get_constant(&self, index: PoolIndex) -> PoolEntry {
-        CONSTANT_POOL.get_constant(index)
-    }
-}
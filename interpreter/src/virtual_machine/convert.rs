@@ -0,0 +1,323 @@
+use definitions::{bytecode::Type, class::TypeInfo, object::Reference, ArgType, CocoaResult, ErrorInfo};
+
+use super::ObjectTable;
+
+
+/// A Rust type with a counterpart in Cocoa
+pub trait CocoaType {
+    /// Whether a value declared with `type_info` converts to or from this type
+    fn matches(type_info: &TypeInfo) -> bool;
+}
+
+/// A Rust type that can be made from a value passed to a native method
+pub trait FromCocoa: CocoaType + Sized {
+    fn from_cocoa(value: &ArgType, object_table: &dyn ObjectTable) -> CocoaResult<Self>;
+}
+
+/// A Rust type that can be returned from a native method
+pub trait IntoCocoa: CocoaType {
+    fn into_cocoa(self, object_table: &dyn ObjectTable) -> CocoaResult<ArgType>;
+}
+
+/// A primitive that can be stored in a Cocoa array
+pub trait ArrayElement: CocoaType + Copy {
+    const TYPE: Type;
+}
+
+/// A reference to any object, array or string, `0` being null
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjectRef(pub Reference);
+
+fn kind(value: &ArgType) -> &'static str {
+    match value {
+        ArgType::Unit => "unit",
+        ArgType::U8(_) => "u8",
+        ArgType::I8(_) => "i8",
+        ArgType::U16(_) => "u16",
+        ArgType::I16(_) => "i16",
+        ArgType::U32(_) => "u32",
+        ArgType::I32(_) => "i32",
+        ArgType::U64(_) => "u64",
+        ArgType::I64(_) => "i64",
+        ArgType::F32(_) => "f32",
+        ArgType::F64(_) => "f64",
+        ArgType::Reference(_) => "reference",
+    }
+}
+
+fn mismatch(expected: &str, found: &ArgType) -> ErrorInfo {
+    ErrorInfo::new(format!("Expected {}, found {}", expected, kind(found)))
+}
+
+macro_rules! primitive {
+    ($ty:ty, $variant:ident, $info:ident, $element:ident) => {
+        impl CocoaType for $ty {
+            fn matches(type_info: &TypeInfo) -> bool {
+                matches!(type_info, TypeInfo::$info)
+            }
+        }
+
+        impl FromCocoa for $ty {
+            fn from_cocoa(value: &ArgType, _: &dyn ObjectTable) -> CocoaResult<Self> {
+                match value {
+                    ArgType::$variant(value) => Ok(*value),
+                    x => Err(mismatch(stringify!($ty), x)),
+                }
+            }
+        }
+
+        impl IntoCocoa for $ty {
+            fn into_cocoa(self, _: &dyn ObjectTable) -> CocoaResult<ArgType> {
+                Ok(ArgType::$variant(self))
+            }
+        }
+
+        impl ArrayElement for $ty {
+            const TYPE: Type = Type::$element;
+        }
+    };
+}
+
+primitive!(u8, U8, U8, U8);
+primitive!(u16, U16, U16, U16);
+primitive!(u32, U32, U32, U32);
+primitive!(u64, U64, U64, U64);
+primitive!(i8, I8, I8, I8);
+primitive!(i16, I16, I16, I16);
+primitive!(i32, I32, I32, I32);
+primitive!(i64, I64, I64, I64);
+primitive!(f32, F32, F32, F32);
+primitive!(f64, F64, F64, F64);
+
+impl CocoaType for bool {
+    fn matches(type_info: &TypeInfo) -> bool {
+        matches!(type_info, TypeInfo::Bool)
+    }
+}
+
+impl FromCocoa for bool {
+    fn from_cocoa(value: &ArgType, _: &dyn ObjectTable) -> CocoaResult<Self> {
+        match value {
+            ArgType::U8(value) => Ok(*value != 0),
+            x => Err(mismatch("bool", x)),
+        }
+    }
+}
+
+impl IntoCocoa for bool {
+    fn into_cocoa(self, _: &dyn ObjectTable) -> CocoaResult<ArgType> {
+        Ok(ArgType::U8(self as u8))
+    }
+}
+
+impl CocoaType for () {
+    fn matches(type_info: &TypeInfo) -> bool {
+        matches!(type_info, TypeInfo::Unit)
+    }
+}
+
+impl IntoCocoa for () {
+    fn into_cocoa(self, _: &dyn ObjectTable) -> CocoaResult<ArgType> {
+        Ok(ArgType::Unit)
+    }
+}
+
+impl CocoaType for ObjectRef {
+    fn matches(type_info: &TypeInfo) -> bool {
+        matches!(type_info, TypeInfo::Object(_) | TypeInfo::String | TypeInfo::Array(_))
+    }
+}
+
+impl FromCocoa for ObjectRef {
+    fn from_cocoa(value: &ArgType, object_table: &dyn ObjectTable) -> CocoaResult<Self> {
+        match value {
+            ArgType::Reference(reference) if object_table.is_stale(*reference) => {
+                Err(ErrorInfo::new(format!("Reference {:#x} is stale", reference)))
+            }
+            ArgType::Reference(reference) => Ok(ObjectRef(*reference)),
+            x => Err(mismatch("reference", x)),
+        }
+    }
+}
+
+impl IntoCocoa for ObjectRef {
+    fn into_cocoa(self, _: &dyn ObjectTable) -> CocoaResult<ArgType> {
+        Ok(ArgType::Reference(self.0))
+    }
+}
+
+impl CocoaType for String {
+    fn matches(type_info: &TypeInfo) -> bool {
+        matches!(type_info, TypeInfo::String)
+    }
+}
+
+impl FromCocoa for String {
+    fn from_cocoa(value: &ArgType, object_table: &dyn ObjectTable) -> CocoaResult<Self> {
+        match value {
+            ArgType::Reference(reference) if object_table.is_string(*reference) => {
+                Ok(object_table.get_string(*reference).get_value().to_owned())
+            }
+            ArgType::Reference(_) => Err(ErrorInfo::new("Expected string, found a reference to something else")),
+            x => Err(mismatch("string", x)),
+        }
+    }
+}
+
+impl IntoCocoa for String {
+    fn into_cocoa(self, object_table: &dyn ObjectTable) -> CocoaResult<ArgType> {
+        Ok(ArgType::Reference(object_table.create_string(self)))
+    }
+}
+
+impl<T: ArrayElement> CocoaType for Vec<T> {
+    fn matches(type_info: &TypeInfo) -> bool {
+        matches!(type_info, TypeInfo::Array(element) if T::matches(element))
+    }
+}
+
+impl<T: ArrayElement> FromCocoa for Vec<T> {
+    fn from_cocoa(value: &ArgType, object_table: &dyn ObjectTable) -> CocoaResult<Self> {
+        match value {
+            ArgType::Reference(reference) if object_table.is_array(*reference) => {
+                let array = object_table.get_array(*reference);
+                if array.get_elem_type() != T::TYPE {
+                    return Err(ErrorInfo::new(format!("Expected an array of {:?}, found an array of {:?}", T::TYPE, array.get_elem_type())));
+                }
+                Ok((0..array.get_size()).map(|index| array.get_elem(index)).collect())
+            }
+            ArgType::Reference(_) => Err(ErrorInfo::new("Expected array, found a reference to something else")),
+            x => Err(mismatch("array", x)),
+        }
+    }
+}
+
+impl<T: ArrayElement> IntoCocoa for Vec<T> {
+    fn into_cocoa(self, object_table: &dyn ObjectTable) -> CocoaResult<ArgType> {
+        let reference = object_table.create_array(T::TYPE, self.len());
+        let mut array = object_table.get_array(reference);
+        for (index, value) in self.into_iter().enumerate() {
+            array.set_elem(index, value);
+        }
+        Ok(ArgType::Reference(reference))
+    }
+}
+
+impl<T: CocoaType> CocoaType for CocoaResult<T> {
+    fn matches(type_info: &TypeInfo) -> bool {
+        T::matches(type_info)
+    }
+}
+
+/// Lets a native function fail with an error instead of returning a value
+impl<T: IntoCocoa> IntoCocoa for CocoaResult<T> {
+    fn into_cocoa(self, object_table: &dyn ObjectTable) -> CocoaResult<ArgType> {
+        self?.into_cocoa(object_table)
+    }
+}
+
+/// An ordinary Rust function that can be registered as a native method
+///
+/// `Args` is the tuple of the function's parameter types, it only exists to tell the implementations apart.
+pub trait NativeFunction<Args>: Send + Sync + 'static {
+    /// Whether the function can implement a method with the given `TypeInfo::Method`
+    fn signature_matches(type_info: &TypeInfo) -> bool;
+
+    /// Converts the arguments, calls the function and converts its result
+    fn call(&self, args: &[ArgType], object_table: &dyn ObjectTable) -> CocoaResult<ArgType>;
+}
+
+macro_rules! native_function {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg),*> NativeFunction<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + Send + Sync + 'static,
+            R: IntoCocoa,
+            $($arg: FromCocoa,)*
+        {
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn signature_matches(type_info: &TypeInfo) -> bool {
+                match type_info {
+                    TypeInfo::Method { args, ret } => {
+                        let mut args = args.iter();
+                        $(let $arg = args.next().map_or(false, $arg::matches);)*
+                        args.next().is_none() && R::matches(ret) $(&& $arg)*
+                    }
+                    _ => false,
+                }
+            }
+
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn call(&self, args: &[ArgType], object_table: &dyn ObjectTable) -> CocoaResult<ArgType> {
+                let names: &[&str] = &[$(stringify!($arg)),*];
+                let expected = names.len();
+                if args.len() != expected {
+                    return Err(ErrorInfo::new(format!("Expected {} arguments, found {}", expected, args.len())));
+                }
+                let mut args = args.iter();
+                $(let $arg = $arg::from_cocoa(args.next().unwrap(), object_table)?;)*
+                (self)($($arg),*).into_cocoa(object_table)
+            }
+        }
+    };
+}
+
+native_function!();
+native_function!(A);
+native_function!(A, B);
+native_function!(A, B, C);
+native_function!(A, B, C, D);
+native_function!(A, B, C, D, E);
+native_function!(A, B, C, D, E, G);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::virtual_machine::{InstanceConstantPool, InstanceObjectTable};
+
+    #[test]
+    fn test_conversions() {
+        let object_table = InstanceObjectTable::new();
+
+        let string = String::from("cocoa").into_cocoa(&object_table).unwrap();
+        assert_eq!(String::from_cocoa(&string, &object_table).unwrap(), "cocoa");
+        let array = vec![1i32, -2, 3].into_cocoa(&object_table).unwrap();
+        assert_eq!(Vec::<i32>::from_cocoa(&array, &object_table).unwrap(), vec![1, -2, 3]);
+        assert!(Vec::<u8>::from_cocoa(&array, &object_table).is_err());
+        // Elements of the same size are still of a different type
+        assert!(Vec::<u32>::from_cocoa(&array, &object_table).is_err());
+        assert!(Vec::<f32>::from_cocoa(&array, &object_table).is_err());
+        assert!(String::from_cocoa(&array, &object_table).is_err());
+        assert!(bool::from_cocoa(&true.into_cocoa(&object_table).unwrap(), &object_table).unwrap());
+        assert!(i64::from_cocoa(&ArgType::I32(1), &object_table).is_err());
+
+        assert!(Vec::<u16>::matches(&TypeInfo::Array(Box::new(TypeInfo::U16))));
+        assert!(!Vec::<u16>::matches(&TypeInfo::Array(Box::new(TypeInfo::I16))));
+        assert!(ObjectRef::matches(&TypeInfo::Object(3)));
+
+        // References to collected entries are rejected rather than read through
+        assert!(ObjectRef::from_cocoa(&string, &object_table).is_ok());
+        object_table.collect_garbage(&[], &InstanceConstantPool::new());
+        assert!(ObjectRef::from_cocoa(&string, &object_table).is_err());
+    }
+
+    #[test]
+    fn test_native_function() {
+        fn widen(a: i32, b: i32) -> i64 {
+            a as i64 * b as i64
+        }
+        let object_table = InstanceObjectTable::new();
+        let signature = |args: Vec<TypeInfo>, ret: TypeInfo| TypeInfo::Method { args, ret: Box::new(ret) };
+
+        assert!(<fn(i32, i32) -> i64 as NativeFunction<(i32, i32)>>::signature_matches(&signature(vec![TypeInfo::I32, TypeInfo::I32], TypeInfo::I64)));
+        assert!(!<fn(i32, i32) -> i64 as NativeFunction<(i32, i32)>>::signature_matches(&signature(vec![TypeInfo::I32], TypeInfo::I64)));
+        assert!(!<fn(i32, i32) -> i64 as NativeFunction<(i32, i32)>>::signature_matches(&signature(vec![TypeInfo::I32, TypeInfo::U32], TypeInfo::I64)));
+
+        let value = NativeFunction::call(&widen, &[ArgType::I32(1 << 20), ArgType::I32(1 << 20)], &object_table).unwrap();
+        assert_eq!(value, ArgType::I64(1 << 40));
+        let error = NativeFunction::call(&widen, &[ArgType::I32(1)], &object_table).unwrap_err();
+        assert_eq!(error.get_message(), "Expected 2 arguments, found 1");
+        let error = NativeFunction::call(&widen, &[ArgType::I32(1), ArgType::U8(1)], &object_table).unwrap_err();
+        assert_eq!(error.get_message(), "Expected i32, found u8");
+    }
+}
@@ -0,0 +1,555 @@
+use std::{collections::HashSet, io::{BufRead, Write}};
+
+use definitions::{bytecode::{Bytecode, Type}, class::{Method, PoolEntry, TypeInfo}, object::Reference, stack::{FrameSnapshot, Stack}, CocoaResult, ErrorInfo};
+
+use super::{ConstantPool, ObjectTable};
+
+
+/// A location the machine should pause at
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Breakpoint {
+    /// The fully qualified name of the class
+    pub class: String,
+    pub method: usize,
+    pub pc: usize,
+}
+
+/// Why the machine paused before an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseReason {
+    /// The instruction is a `Breakpoint`
+    Instruction,
+    /// A breakpoint set by the user matched
+    Breakpoint,
+    /// A step or step over finished
+    Step,
+}
+
+/// How the machine carries on after a pause
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Run until the next breakpoint
+    Continue,
+    /// Pause before the next instruction, even if it is in a called method
+    Step,
+    /// Pause before the next instruction in the current frame or one of its callers
+    StepOver,
+    /// Stop running the program
+    Abort,
+}
+
+/// Decides what to do each time the machine pauses
+pub trait DebuggerFrontend {
+    fn paused(&mut self, reason: PauseReason, session: &mut DebugSession) -> Resume;
+}
+
+enum Mode {
+    Continue,
+    Step,
+    /// Stepping over calls made from a frame at this depth
+    StepOver(usize),
+}
+
+/// Pauses the machine on breakpoints and steps and hands control to a frontend
+pub struct Debugger {
+    breakpoints: HashSet<Breakpoint>,
+    mode: Mode,
+    frontend: Box<dyn DebuggerFrontend>,
+}
+
+impl Debugger {
+    /// Creates a debugger that lets the machine run until it reaches a breakpoint
+    pub fn new(frontend: Box<dyn DebuggerFrontend>) -> Self {
+        Self {
+            breakpoints: HashSet::new(),
+            mode: Mode::Continue,
+            frontend,
+        }
+    }
+
+    /// Pauses before the first instruction instead of waiting for a breakpoint
+    pub fn pause_on_start(mut self) -> Self {
+        self.mode = Mode::Step;
+        self
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        self.breakpoints.insert(breakpoint)
+    }
+
+    /// Called by the machine before it executes the instruction at the top frame's pc
+    pub(crate) fn before_instruction(&mut self, instruction: Bytecode, stack: &Stack, object_table: &dyn ObjectTable, constant_pool: &dyn ConstantPool) -> CocoaResult<()> {
+        let depth = stack.depth();
+        let reason = match (instruction, &self.mode) {
+            (Bytecode::Breakpoint, _) => Some(PauseReason::Instruction),
+            (_, Mode::Step) => Some(PauseReason::Step),
+            (_, Mode::StepOver(step_depth)) if depth <= *step_depth => Some(PauseReason::Step),
+            _ if self.breakpoints.is_empty() => None,
+            _ => {
+                let breakpoint = Breakpoint {
+                    class: class_name(object_table, constant_pool, stack.get_class_index()),
+                    method: stack.get_current_method_index(),
+                    pc: stack.get_current_pc(),
+                };
+                self.breakpoints.contains(&breakpoint).then_some(PauseReason::Breakpoint)
+            }
+        };
+        let Some(reason) = reason else {
+            return Ok(());
+        };
+
+        let mut session = DebugSession {
+            frames: stack.frames(),
+            object_table,
+            constant_pool,
+            breakpoints: &mut self.breakpoints,
+        };
+        self.mode = match self.frontend.paused(reason, &mut session) {
+            Resume::Continue => Mode::Continue,
+            Resume::Step => Mode::Step,
+            Resume::StepOver => Mode::StepOver(depth),
+            Resume::Abort => return Err(ErrorInfo::new("Execution aborted by the debugger")),
+        };
+        Ok(())
+    }
+}
+
+/// What a frontend can see and change while the machine is paused
+pub struct DebugSession<'a> {
+    frames: Vec<FrameSnapshot>,
+    object_table: &'a dyn ObjectTable,
+    constant_pool: &'a dyn ConstantPool,
+    breakpoints: &'a mut HashSet<Breakpoint>,
+}
+
+impl DebugSession<'_> {
+    /// The frames on the stack, outermost first
+    pub fn frames(&self) -> &[FrameSnapshot] {
+        &self.frames
+    }
+
+    pub fn class_name(&self, class_ref: Reference) -> String {
+        class_name(self.object_table, self.constant_pool, class_ref)
+    }
+
+    pub fn method_name(&self, class_ref: Reference, method_index: usize) -> String {
+        method_name(self.object_table, self.constant_pool, class_ref, method_index)
+    }
+
+    /// The instruction a frame is about to execute, or has called from if it isn't the top frame
+    pub fn instruction(&self, frame: &FrameSnapshot) -> Option<Bytecode> {
+        let class = self.object_table.get_class(frame.class_reference);
+        if frame.method_index >= class.methods_count() {
+            return None;
+        }
+        match self.constant_pool.get_constant(class.get_method(frame.method_index).location) {
+            PoolEntry::Method(Method::Bytecode(code)) => code.get(frame.pc).copied(),
+            _ => None,
+        }
+    }
+
+    /// A one line description of where a frame is
+    pub fn describe_frame(&self, frame: &FrameSnapshot) -> String {
+        let instruction = match self.instruction(frame) {
+            Some(instruction) => format!("{:?}", instruction),
+            None => String::from("<end of method>"),
+        };
+        format!("{}.{} pc {}: {}",
+            self.class_name(frame.class_reference),
+            self.method_name(frame.class_reference, frame.method_index),
+            frame.pc,
+            instruction)
+    }
+
+    /// Renders whatever a reference points to in the object table
+    pub fn describe_object(&self, reference: Reference) -> String {
+        if reference == 0 {
+            return String::from("null");
+        }
+        if self.object_table.is_string(reference) {
+            return format!("{:?}", self.object_table.get_string(reference).get_value());
+        }
+        if self.object_table.is_class(reference) {
+            return format!("class {}", self.class_name(reference));
+        }
+        if self.object_table.is_array(reference) {
+            let array = self.object_table.get_array(reference);
+            let elements = (0..array.get_size()).map(|index| match array.get_elem_size() {
+                1 => array.get_elem::<u8>(index).to_string(),
+                2 => array.get_elem::<u16>(index).to_string(),
+                4 => array.get_elem::<u32>(index).to_string(),
+                _ => array.get_elem::<u64>(index).to_string(),
+            }).collect::<Vec<_>>();
+            return format!("[{}]", elements.join(", "));
+        }
+        if !self.object_table.is_object(reference) {
+            return format!("<invalid reference {}>", reference);
+        }
+
+        // Inherited fields live in the same object, they are listed first like they are laid out
+        let object = self.object_table.get_object(reference);
+        let mut classes = vec![self.object_table.get_class(object.get_class())];
+        while let Some(parent_ref) = classes.last().unwrap().get_parent_ref() {
+            classes.push(self.object_table.get_class(parent_ref));
+        }
+        let mut fields = Vec::new();
+        for (class, (index, field)) in classes.iter().rev().flat_map(|class| class.fields().iter().enumerate().map(move |field| (class, field))) {
+            let Some(offset) = class.get_field_offset(index) else {
+                continue;
+            };
+            let name = match self.constant_pool.get_constant(field.name) {
+                PoolEntry::String(name) => name,
+                _ => format!("<field {}>", index),
+            };
+            let value = match self.constant_pool.get_constant(field.type_info) {
+                PoolEntry::TypeInfo(TypeInfo::U8) => format_value(Type::U8, object.get_field::<u8>(offset) as u64),
+                PoolEntry::TypeInfo(TypeInfo::U16) => format_value(Type::U16, object.get_field::<u16>(offset) as u64),
+                PoolEntry::TypeInfo(TypeInfo::U32) => format_value(Type::U32, object.get_field::<u32>(offset) as u64),
+                PoolEntry::TypeInfo(TypeInfo::U64) => format_value(Type::U64, object.get_field::<u64>(offset)),
+                PoolEntry::TypeInfo(TypeInfo::I8) => format_value(Type::I8, object.get_field::<u8>(offset) as u64),
+                PoolEntry::TypeInfo(TypeInfo::I16) => format_value(Type::I16, object.get_field::<u16>(offset) as u64),
+                PoolEntry::TypeInfo(TypeInfo::I32) => format_value(Type::I32, object.get_field::<u32>(offset) as u64),
+                PoolEntry::TypeInfo(TypeInfo::I64) => format_value(Type::I64, object.get_field::<u64>(offset)),
+                PoolEntry::TypeInfo(TypeInfo::F32) => format_value(Type::F32, object.get_field::<u32>(offset) as u64),
+                PoolEntry::TypeInfo(TypeInfo::F64) => format_value(Type::F64, object.get_field::<u64>(offset)),
+                PoolEntry::TypeInfo(TypeInfo::Object(_)) => format_value(Type::Reference, object.get_field::<Reference>(offset) as u64),
+                _ => String::from("?"),
+            };
+            fields.push(format!("{}: {}", name, value));
+        }
+        format!("{} {{ {} }}", self.class_name(object.get_class()), fields.join(", "))
+    }
+
+    pub fn breakpoints(&self) -> Vec<Breakpoint> {
+        let mut breakpoints: Vec<_> = self.breakpoints.iter().cloned().collect();
+        breakpoints.sort_by(|a, b| (&a.class, a.method, a.pc).cmp(&(&b.class, b.method, b.pc)));
+        breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        self.breakpoints.insert(breakpoint)
+    }
+
+    pub fn remove_breakpoint(&mut self, breakpoint: &Breakpoint) -> bool {
+        self.breakpoints.remove(breakpoint)
+    }
+}
+
+pub(super) fn class_name(object_table: &dyn ObjectTable, constant_pool: &dyn ConstantPool, class_ref: Reference) -> String {
+    let class = object_table.get_class(class_ref);
+    match class.get_constant_pool_entry(class.get_this_info()) {
+        PoolEntry::ClassInfo(class_info) => match constant_pool.get_constant(class_info.name) {
+            PoolEntry::String(name) => name,
+            _ => String::from("<unknown>"),
+        },
+        _ => String::from("<unknown>"),
+    }
+}
+
+pub(super) fn method_name(object_table: &dyn ObjectTable, constant_pool: &dyn ConstantPool, class_ref: Reference, method_index: usize) -> String {
+    let class = object_table.get_class(class_ref);
+    if method_index >= class.methods_count() {
+        return format!("<method {}>", method_index);
+    }
+    match constant_pool.get_constant(class.get_method(method_index).name) {
+        PoolEntry::String(name) => name,
+        _ => format!("<method {}>", method_index),
+    }
+}
+
+/// Renders the raw bits of a local or operand according to its type
+pub fn format_value(ty: Type, bits: u64) -> String {
+    match ty {
+        Type::U8 => format!("{}u8", bits as u8),
+        Type::U16 => format!("{}u16", bits as u16),
+        Type::U32 => format!("{}u32", bits as u32),
+        Type::U64 => format!("{}u64", bits),
+        Type::I8 => format!("{}i8", bits as u8 as i8),
+        Type::I16 => format!("{}i16", bits as u16 as i16),
+        Type::I32 => format!("{}i32", bits as u32 as i32),
+        Type::I64 => format!("{}i64", bits as i64),
+        Type::F32 => format!("{}f32", f32::from_bits(bits as u32)),
+        Type::F64 => format!("{}f64", f64::from_bits(bits)),
+        Type::Char(_) => format!("{:?}", char::from_u32(bits as u32).unwrap_or(char::REPLACEMENT_CHARACTER)),
+        Type::Reference => format!("@{}", bits),
+    }
+}
+
+const HELP: &str = "\
+step, s                        execute one instruction
+next, n                        execute one instruction, stepping over calls
+continue, c                    run until the next breakpoint
+stack, bt                      list the frames, innermost first
+frame, f [n]                   show the locals and operands of frame n
+print, p <reference>           print an object
+break, b <class> <method> <pc> pause before an instruction
+delete, d <class> <method> <pc> remove a breakpoint
+breakpoints                    list the breakpoints
+quit, q                        stop the program";
+
+/// A line based frontend for a terminal
+pub struct Console<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Console<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self {
+            input,
+            output,
+        }
+    }
+
+    fn prompt(&mut self, reason: PauseReason, session: &mut DebugSession) -> std::io::Result<Resume> {
+        let top = session.frames().last().expect("Paused without a frame");
+        let reason = match reason {
+            PauseReason::Instruction => "breakpoint instruction",
+            PauseReason::Breakpoint => "breakpoint",
+            PauseReason::Step => "step",
+        };
+        writeln!(self.output, "Paused ({}) at {}", reason, session.describe_frame(top))?;
+
+        loop {
+            write!(self.output, "(cocoa) ")?;
+            self.output.flush()?;
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                // Nobody is left to answer, so let the program finish
+                return Ok(Resume::Continue);
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => continue,
+                ["step" | "s"] => return Ok(Resume::Step),
+                ["next" | "n"] => return Ok(Resume::StepOver),
+                ["continue" | "c"] => return Ok(Resume::Continue),
+                ["quit" | "q"] => return Ok(Resume::Abort),
+                ["stack" | "bt"] => {
+                    for (depth, frame) in session.frames().iter().rev().enumerate() {
+                        writeln!(self.output, "#{} {}", depth, session.describe_frame(frame))?;
+                    }
+                }
+                ["frame" | "f", rest @ ..] => {
+                    let depth = match rest {
+                        [] => Some(0),
+                        [depth] => depth.parse::<usize>().ok(),
+                        _ => None,
+                    };
+                    let frame = depth.and_then(|depth| session.frames().iter().rev().nth(depth));
+                    let Some(frame) = frame else {
+                        writeln!(self.output, "No such frame")?;
+                        continue;
+                    };
+                    writeln!(self.output, "{}", session.describe_frame(frame))?;
+                    writeln!(self.output, "locals:")?;
+                    for (index, (ty, bits)) in frame.locals.iter().enumerate() {
+                        writeln!(self.output, "  {} {:?} = {}", index, ty, format_value(*ty, *bits))?;
+                    }
+                    writeln!(self.output, "operands:")?;
+                    for (ty, bits) in frame.operands.iter().rev() {
+                        writeln!(self.output, "  {:?} = {}", ty, format_value(*ty, *bits))?;
+                    }
+                }
+                ["print" | "p", reference] => match reference.trim_start_matches('@').parse::<Reference>() {
+                    Ok(reference) => writeln!(self.output, "{}", session.describe_object(reference))?,
+                    Err(_) => writeln!(self.output, "Invalid reference {}", reference)?,
+                },
+                [command @ ("break" | "b" | "delete" | "d"), class, method, pc] => {
+                    let (Ok(method), Ok(pc)) = (method.parse(), pc.parse()) else {
+                        writeln!(self.output, "The method and pc must be numbers")?;
+                        continue;
+                    };
+                    let breakpoint = Breakpoint {
+                        class: class.to_string(),
+                        method,
+                        pc,
+                    };
+                    if matches!(*command, "break" | "b") {
+                        session.add_breakpoint(breakpoint);
+                    } else if !session.remove_breakpoint(&breakpoint) {
+                        writeln!(self.output, "No such breakpoint")?;
+                    }
+                }
+                ["breakpoints"] => {
+                    for breakpoint in session.breakpoints() {
+                        writeln!(self.output, "{} {} {}", breakpoint.class, breakpoint.method, breakpoint.pc)?;
+                    }
+                }
+                _ => writeln!(self.output, "{}", HELP)?,
+            }
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> DebuggerFrontend for Console<R, W> {
+    fn paused(&mut self, reason: PauseReason, session: &mut DebugSession) -> Resume {
+        self.prompt(reason, session).unwrap_or(Resume::Abort)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::VecDeque, io::Cursor, rc::Rc};
+
+    use definitions::{assembler::assemble, class::ClassHeader};
+
+    use super::*;
+    use crate::virtual_machine::{InstanceConstantPool, Linker, Machine, NativeMethodTable, InstanceObjectTable};
+
+    fn debug_class(name: &str) -> ClassHeader {
+        assemble(&format!(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "{}"
+            .const parent_name string "cocoa.lang.Object"
+            .const two i32 2
+            .const seven i32 7
+            .const i32_type type i32
+            .const unit_type type fn() -> unit
+            .const double_type type fn(i32) -> i32
+            .const main string "Main"
+            .const double string "double"
+            .const result string "result"
+            .const x string "x"
+            .const main_code bytecode
+                LoadConstant two
+                StoreLocal 0
+                LoadConstant seven
+                New this
+                SetField this 1
+                StoreLocal 1
+                Breakpoint
+                LoadLocal 0
+                InvokeStatic this 1
+                StoreStatic 0
+                ReturnUnit
+            .end
+            .const double_code bytecode
+                LoadLocal 0
+                LoadLocal 0
+                Add
+                Return
+            .end
+            .field result i32_type Static
+            .field x i32_type Public
+            .method main unit_type main_code Public Static
+            .method double double_type double_code Public Static
+        "#, name)).unwrap()
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Pause {
+        reason: PauseReason,
+        at: String,
+        depth: usize,
+        operands: Vec<(Type, u64)>,
+    }
+
+    struct Scripted {
+        commands: VecDeque<Resume>,
+        pauses: Rc<RefCell<Vec<Pause>>>,
+        objects: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl DebuggerFrontend for Scripted {
+        fn paused(&mut self, reason: PauseReason, session: &mut DebugSession) -> Resume {
+            let top = session.frames().last().unwrap();
+            if reason == PauseReason::Instruction {
+                let (_, point) = top.locals[1];
+                self.objects.borrow_mut().push(session.describe_object(point as Reference));
+            }
+            self.pauses.borrow_mut().push(Pause {
+                reason,
+                at: session.describe_frame(top),
+                depth: session.frames().len(),
+                operands: top.operands.clone(),
+            });
+            self.commands.pop_front().unwrap_or(Resume::Abort)
+        }
+    }
+
+    #[test]
+    fn test_steps_and_breakpoints() {
+        let constant_pool = InstanceConstantPool::new();
+        let object_table = InstanceObjectTable::new();
+        let method_table = NativeMethodTable::new();
+        let mut linker = Linker::new(&constant_pool, &object_table);
+        let (class_ref, main) = linker.link_classes(vec![debug_class("DebugScripted")], "DebugScripted", "Main").unwrap();
+
+        let pauses = Rc::new(RefCell::new(Vec::new()));
+        let objects = Rc::new(RefCell::new(Vec::new()));
+        let frontend = Scripted {
+            commands: VecDeque::from([Resume::Step, Resume::Step, Resume::Step, Resume::StepOver, Resume::Continue, Resume::StepOver, Resume::StepOver, Resume::Continue]),
+            pauses: pauses.clone(),
+            objects: objects.clone(),
+        };
+        let mut debugger = Debugger::new(Box::new(frontend));
+        debugger.add_breakpoint(Breakpoint {
+            class: String::from("DebugScripted"),
+            method: 1,
+            pc: 2,
+        });
+
+        let mut vm = Machine::new(&object_table, &method_table, &constant_pool).with_linker(linker).with_debugger(debugger);
+        vm.run_bootstrap(class_ref, main).unwrap();
+
+        let pause = |reason, at: &str, depth, operands: &[(Type, u64)]| Pause {
+            reason,
+            at: at.to_owned(),
+            depth,
+            operands: operands.to_vec(),
+        };
+        assert_eq!(*pauses.borrow(), vec![
+            pause(PauseReason::Instruction, "DebugScripted.Main pc 6: Breakpoint", 1, &[]),
+            pause(PauseReason::Step, "DebugScripted.Main pc 7: LoadLocal(0)", 1, &[]),
+            pause(PauseReason::Step, "DebugScripted.Main pc 8: InvokeStatic(0, 1)", 1, &[(Type::I32, 2)]),
+            pause(PauseReason::Step, "DebugScripted.double pc 0: LoadLocal(0)", 2, &[]),
+            pause(PauseReason::Step, "DebugScripted.double pc 1: LoadLocal(0)", 2, &[(Type::I32, 2)]),
+            pause(PauseReason::Breakpoint, "DebugScripted.double pc 2: Add", 2, &[(Type::I32, 2), (Type::I32, 2)]),
+            pause(PauseReason::Step, "DebugScripted.double pc 3: Return", 2, &[(Type::I32, 4)]),
+            pause(PauseReason::Step, "DebugScripted.Main pc 9: StoreStatic(0)", 1, &[(Type::I32, 4)]),
+        ]);
+        assert_eq!(*objects.borrow(), vec![String::from("DebugScripted { x: 7i32 }")]);
+        assert_eq!(object_table.get_class(class_ref).statics(), &[PoolEntry::I32(4)]);
+    }
+
+    #[test]
+    fn test_console() {
+        let constant_pool = InstanceConstantPool::new();
+        let object_table = InstanceObjectTable::new();
+        let method_table = NativeMethodTable::new();
+        let mut linker = Linker::new(&constant_pool, &object_table);
+        let (class_ref, main) = linker.link_classes(vec![debug_class("DebugConsole")], "DebugConsole", "Main").unwrap();
+
+        let input = "bt\nf\nb DebugConsole 1 3\nbreakpoints\nc\nn\nq\n";
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let console = Console::new(Cursor::new(input), SharedOutput(output.clone()));
+
+        let mut vm = Machine::new(&object_table, &method_table, &constant_pool).with_linker(linker).with_debugger(Debugger::new(Box::new(console)));
+        let error = vm.run_bootstrap(class_ref, main).unwrap_err();
+        assert_eq!(error.get_message(), "Execution aborted by the debugger");
+
+        let output = String::from_utf8(output.borrow().clone()).unwrap();
+        assert!(output.contains("Paused (breakpoint instruction) at DebugConsole.Main pc 6: Breakpoint"));
+        assert!(output.contains("#0 DebugConsole.Main pc 6: Breakpoint"));
+        assert!(output.contains("  0 I32 = 2i32"));
+        assert!(output.contains("  1 Reference = @"));
+        assert!(output.contains("DebugConsole 1 3\n"));
+        assert!(output.contains("Paused (breakpoint) at DebugConsole.double pc 3: Return"));
+        assert!(output.contains("Paused (step) at DebugConsole.Main pc 9: StoreStatic(0)"));
+    }
+
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Helpers the virtual machine tests share to link and run assembled classes
+
+use std::sync::{Arc, Mutex};
+
+use definitions::{assembler::assemble, bytecode::MethodIndex, class::{ClassHeader, PoolEntry}, object::Reference, ArgType};
+
+use super::{Machine, ObjectTable, VmInstance};
+
+/// Links the classes into the instance, returns a machine that can load more classes with the same linker
+/// along with the main class and the index of its main method
+pub(super) fn link<'a>(instance: &'a VmInstance, classes: Vec<ClassHeader>, main_class: &str, main_method: &str) -> (Machine<'a>, Reference, MethodIndex) {
+    let mut linker = instance.linker();
+    let (class_ref, main) = linker.link_classes(classes, main_class, main_method).unwrap();
+    (instance.machine().with_linker(linker), class_ref, main)
+}
+
+/// The static fields of a linked class
+pub(super) fn statics(instance: &VmInstance, class_ref: Reference) -> Vec<PoolEntry> {
+    instance.object_table.get_class(class_ref).statics().to_vec()
+}
+
+/// Binds a native method that records the arguments of every call to it and returns unit
+pub(super) fn record_calls(instance: &mut VmInstance, name: &str) -> Arc<Mutex<Vec<Vec<ArgType>>>> {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let recorded = calls.clone();
+    instance.native_methods().register_closure(name, move |args, _, _, _| {
+        recorded.lock().unwrap().push(args.to_vec());
+        Ok(ArgType::Unit)
+    });
+    calls
+}
+
+/// A class with nothing but a parent and interfaces
+pub(super) fn class_with_parent(name: &str, parent: &str, interfaces: &[&str]) -> ClassHeader {
+    let mut source = format!(r#"
+        .class this parent
+        .const this class name
+        .const parent class parent_name
+        .const name string "{}"
+        .const parent_name string "{}"
+    "#, name, parent);
+    for (i, interface) in interfaces.iter().enumerate() {
+        source.push_str(&format!(".const interface_name{i} string \"{interface}\"\n"));
+        source.push_str(&format!(".const interface{i} class interface_name{i}\n"));
+        source.push_str(&format!(".interface interface{i}\n"));
+    }
+    assemble(&source).unwrap()
+}
@@ -0,0 +1,100 @@
+use super::{InstanceConstantPool, InstanceObjectTable, Linker, Machine, NativeMethodTable};
+
+
+/// A virtual machine with its own object table, constant pool and native methods
+///
+/// Nothing is shared between instances, so classes linked into one are invisible to the others and
+/// instances can run side by side, each on its own thread if needed.
+pub struct VmInstance {
+    pub(super) object_table: InstanceObjectTable,
+    pub(super) constant_pool: InstanceConstantPool,
+    method_table: NativeMethodTable,
+}
+
+impl VmInstance {
+    pub fn new() -> Self {
+        Self {
+            object_table: InstanceObjectTable::new(),
+            constant_pool: InstanceConstantPool::new(),
+            method_table: NativeMethodTable::new(),
+        }
+    }
+
+    /// The native methods classes linked into this instance can bind to
+    pub fn native_methods(&mut self) -> &mut NativeMethodTable {
+        &mut self.method_table
+    }
+
+    /// A linker that adds classes to this instance
+    pub fn linker(&self) -> Linker<'_> {
+        Linker::new(&self.constant_pool, &self.object_table).with_method_table(&self.method_table)
+    }
+
+    /// A machine that runs code in this instance
+    pub fn machine(&self) -> Machine<'_> {
+        Machine::new(&self.object_table, &self.method_table, &self.constant_pool)
+    }
+}
+
+impl Default for VmInstance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use definitions::{assembler::assemble, class::PoolEntry};
+
+    use super::*;
+    use crate::virtual_machine::ObjectTable;
+
+    fn counter(start: u32) -> definitions::class::ClassHeader {
+        assemble(&format!(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "Counter"
+            .const parent_name string "cocoa.lang.Object"
+            .const count string "count"
+            .const u32_type type u32
+            .const start u32 {}
+            .const one u32 1
+            .const unit_type type fn() -> unit
+            .const main string "Main"
+            .const main_code bytecode
+                LoadStatic 0
+                LoadConstant one
+                Add
+                StoreStatic 0
+                ReturnUnit
+            .end
+            .field count u32_type = start Static
+            .method main unit_type main_code Public Static
+        "#, start)).unwrap()
+    }
+
+    #[test]
+    fn test_isolated_instances() {
+        let threads: Vec<_> = (0..4u32).map(|i| {
+            std::thread::spawn(move || {
+                let instance = VmInstance::new();
+                let mut linker = instance.linker();
+                let (class_ref, main) = linker.link_classes(vec![counter(i * 100)], "Counter", "Main").unwrap();
+
+                let mut vm = instance.machine().with_linker(linker);
+                for _ in 0..=i {
+                    vm.run_bootstrap(class_ref, main).unwrap();
+                }
+                (class_ref, instance.object_table.get_class(class_ref).statics().to_vec())
+            })
+        }).collect();
+
+        let results: Vec<_> = threads.into_iter().map(|thread| thread.join().unwrap()).collect();
+        // Every instance linked the same class into an empty table and only saw its own runs
+        for (i, (class_ref, statics)) in results.iter().enumerate() {
+            assert_eq!(*class_ref, results[0].0);
+            assert_eq!(statics, &[PoolEntry::U32(i as u32 * 101 + 1)]);
+        }
+    }
+}
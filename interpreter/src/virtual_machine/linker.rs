@@ -1,19 +1,282 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+use definitions::{assembler::assemble, bytecode::MethodIndex, class::{ClassFlags, ClassHeader, ClassInfo, FieldFlags, Method, MethodFlags, CLASS_INITIALIZER, PoolEntry, PoolIndex, QualifiedName, TypeInfo, VTableEntry}, class_file, object::Reference};
+
+use super::{machine::{ARITHMETIC_EXCEPTION, ARRAY_CLASS, ARRAY_STORE_EXCEPTION, CLASS_CAST_EXCEPTION, CLASS_NOT_FOUND_EXCEPTION, EXCEPTION_CLASS, INDEX_OUT_OF_BOUNDS_EXCEPTION, NULL_POINTER_EXCEPTION}, ClassLoader, ConstantPool, MethodTable, ObjectTable, Verifier, VerifyError, VerifyErrorKind};
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkError {
+    /// A pool index points past the end of the class's constant pool
+    InvalidPoolIndex {
+        class: String,
+        index: PoolIndex,
+    },
+    /// A pool entry is not the kind the linker needs at that position
+    WrongPoolEntry {
+        class: String,
+        index: PoolIndex,
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// A class's parent was neither supplied nor linked earlier, the chain ends with the missing name
+    MissingSuperclass(Vec<String>),
+    /// An implemented interface was neither supplied nor linked earlier
+    MissingInterface(Vec<String>),
+    /// Classes inherit from each other, the chain starts and ends with the same name
+    InheritanceCycle(Vec<String>),
+    /// No class with this name was supplied or found by the class loader
+    ClassNotFound(String),
+    /// The class loader found the class but failed to read it
+    LoadFailed {
+        class: String,
+        message: String,
+    },
+    MainClassNotFound(String),
+    MainClassNotLinked(String),
+    MainMethodNotFound {
+        class: String,
+        method: String,
+    },
+    /// The class initializer is not a static `fn() -> unit`
+    InvalidInitializer(String),
+    /// A class that is not abstract lacks a method of an interface it implements
+    MissingInterfaceMethod {
+        class: String,
+        interface: String,
+        method: String,
+    },
+    /// No native method is registered under the qualified name of a native method
+    UnboundNative(String),
+    /// The function registered for a native method can't take the method's arguments or return its result
+    NativeSignatureMismatch {
+        method: String,
+        signature: String,
+    },
+    Verify(VerifyError),
+}
+
+impl std::fmt::Display for LinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkError::InvalidPoolIndex { class, index } => write!(f, "{}: pool index {} is out of bounds", class, index),
+            LinkError::WrongPoolEntry { class, index, expected, found } => {
+                write!(f, "{}: expected {} at pool index {}, found {}", class, expected, index, found)
+            }
+            LinkError::MissingSuperclass(chain) => write!(f, "missing superclass: {}", chain.join(" -> ")),
+            LinkError::MissingInterface(chain) => write!(f, "missing interface: {}", chain.join(" -> ")),
+            LinkError::InheritanceCycle(chain) => write!(f, "inheritance cycle: {}", chain.join(" -> ")),
+            LinkError::ClassNotFound(class) => write!(f, "class {} not found", class),
+            LinkError::LoadFailed { class, message } => write!(f, "failed to load {}: {}", class, message),
+            LinkError::MainClassNotFound(class) => write!(f, "main class {} not found", class),
+            LinkError::MainClassNotLinked(class) => write!(f, "main class {} was not linked", class),
+            LinkError::MainMethodNotFound { class, method } => write!(f, "main method {}.{} not found", class, method),
+            LinkError::InvalidInitializer(class) => write!(f, "{}: {} must be a static fn() -> unit", class, CLASS_INITIALIZER),
+            LinkError::MissingInterfaceMethod { class, interface, method } => {
+                write!(f, "{} does not implement {} from {}", class, method, interface)
+            }
+            LinkError::UnboundNative(method) => write!(f, "no native method is registered for {}", method),
+            LinkError::NativeSignatureMismatch { method, signature } => {
+                write!(f, "the function registered for {} does not match {}", method, signature)
+            }
+            LinkError::Verify(error) => write!(f, "verification failed: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+impl From<VerifyError> for LinkError {
+    fn from(error: VerifyError) -> Self {
+        LinkError::Verify(error)
+    }
+}
+
+/// The value a static field without a default starts out with
+fn zero_value(type_info: &TypeInfo) -> PoolEntry {
+    match type_info {
+        TypeInfo::U8 | TypeInfo::Bool => PoolEntry::U8(0),
+        TypeInfo::U16 => PoolEntry::U16(0),
+        TypeInfo::U32 => PoolEntry::U32(0),
+        TypeInfo::U64 => PoolEntry::U64(0),
+        TypeInfo::I8 => PoolEntry::I8(0),
+        TypeInfo::I16 => PoolEntry::I16(0),
+        TypeInfo::I32 => PoolEntry::I32(0),
+        TypeInfo::I64 => PoolEntry::I64(0),
+        TypeInfo::F32 => PoolEntry::F32(0.0),
+        TypeInfo::F64 => PoolEntry::F64(0.0),
+        TypeInfo::Char => PoolEntry::Char('\0'),
+        TypeInfo::Unit | TypeInfo::String | TypeInfo::Array(_) | TypeInfo::Object(_) | TypeInfo::Method { .. } => PoolEntry::Reference(0),
+    }
+}
+
+/// Best effort name of a class that has not been touched by the linker yet, for error messages
+fn unlinked_class_name(class: &ClassHeader) -> String {
+    let pool = class.constants();
+    if let Some(PoolEntry::ClassInfo(info)) = pool.get(class.get_this_info()) {
+        if let Some(PoolEntry::String(name)) = pool.get(info.name) {
+            return name.clone();
+        }
+    }
+    String::from("<unknown>")
+}
+
+/// Makes an independent copy of a class, the linker rewrites the classes it links in place
+fn copy_class(class: &ClassHeader) -> ClassHeader {
+    class_file::class_from_bytes(&class_file::class_to_bytes(class)).expect("A freshly written class can be read back")
+}
+
+fn get_entry<'a>(class: &'a ClassHeader, class_name: &str, index: PoolIndex) -> Result<&'a PoolEntry, LinkError> {
+    class.constants().get(index).ok_or_else(|| LinkError::InvalidPoolIndex {
+        class: class_name.to_owned(),
+        index,
+    })
+}
+
+fn wrong_entry(class_name: &str, index: PoolIndex, expected: &'static str, found: &PoolEntry) -> LinkError {
+    LinkError::WrongPoolEntry {
+        class: class_name.to_owned(),
+        index,
+        expected,
+        found: found.kind(),
+    }
+}
+
+fn get_string<'a>(class: &'a ClassHeader, class_name: &str, index: PoolIndex) -> Result<&'a String, LinkError> {
+    match get_entry(class, class_name, index)? {
+        PoolEntry::String(string) => Ok(string),
+        x => Err(wrong_entry(class_name, index, "String", x)),
+    }
+}
+
+fn get_class_info<'a>(class: &'a ClassHeader, class_name: &str, index: PoolIndex) -> Result<&'a ClassInfo, LinkError> {
+    match get_entry(class, class_name, index)? {
+        PoolEntry::ClassInfo(class_info) => Ok(class_info),
+        x => Err(wrong_entry(class_name, index, "ClassInfo", x)),
+    }
+}
+
+fn get_type_info<'a>(class: &'a ClassHeader, class_name: &str, index: PoolIndex) -> Result<&'a TypeInfo, LinkError> {
+    match get_entry(class, class_name, index)? {
+        PoolEntry::TypeInfo(type_info) => Ok(type_info),
+        x => Err(wrong_entry(class_name, index, "TypeInfo", x)),
+    }
+}
 
-use definitions::{bytecode::MethodIndex, class::{ClassHeader, ClassInfo, PoolEntry, PoolIndex}, object::Reference};
+fn get_method<'a>(class: &'a ClassHeader, class_name: &str, index: PoolIndex) -> Result<&'a Method, LinkError> {
+    match get_entry(class, class_name, index)? {
+        PoolEntry::Method(method) => Ok(method),
+        x => Err(wrong_entry(class_name, index, "Method", x)),
+    }
+}
 
-use super::{ConstantPool, ObjectTable};
+/// The root of the class hierarchy, it may be left out of the classes being linked
+const ROOT_PACKAGE: &str = "cocoa.lang";
+const ROOT_CLASS: &str = "Object";
+
+/// The class arrays are instances of, linked when no class loader supplies `cocoa.lang.Array`
+///
+/// `size` is bound to the native method registered as `cocoa.lang.Array.size`.
+const BUILTIN_ARRAY_CLASS: &str = r#"
+    .class this parent Public
+    .const this class name
+    .const parent class parent_name
+    .const name string "cocoa.lang.Array"
+    .const parent_name string "cocoa.lang.Object"
+    .const size string "size"
+    .const size_type type fn(object(this)) -> u64
+    .const size_code method native
+    .method size size_type size_code Public
+"#;
+
+/// The exceptions the machine throws and their parents, linked when no class loader supplies them so
+/// that faults can be caught by any program
+const BUILTIN_EXCEPTIONS: &[(&str, &str)] = &[
+    (EXCEPTION_CLASS, "cocoa.lang.Object"),
+    (ARITHMETIC_EXCEPTION, EXCEPTION_CLASS),
+    (NULL_POINTER_EXCEPTION, EXCEPTION_CLASS),
+    (INDEX_OUT_OF_BOUNDS_EXCEPTION, EXCEPTION_CLASS),
+    (ARRAY_STORE_EXCEPTION, EXCEPTION_CLASS),
+    (CLASS_CAST_EXCEPTION, EXCEPTION_CLASS),
+    (CLASS_NOT_FOUND_EXCEPTION, EXCEPTION_CLASS),
+];
+
+/// The built in class with the given name, if there is one
+fn builtin_class(name: &str) -> Option<ClassHeader> {
+    if name == ARRAY_CLASS {
+        return Some(assemble(BUILTIN_ARRAY_CLASS).expect("The built in array class assembles"));
+    }
+    let (_, parent) = BUILTIN_EXCEPTIONS.iter().find(|(exception, _)| *exception == name)?;
+    let class = assemble(&format!(r#"
+        .class this parent Public
+        .const this class name
+        .const parent class parent_name
+        .const name string "{name}"
+        .const parent_name string "{parent}"
+    "#));
+    Some(class.expect("The built in exception classes assemble"))
+}
 
+/// What an entry in the global constant pool was added for
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum PoolKey {
+    String(String),
+    ClassInfo(QualifiedName),
+    Method {
+        class: QualifiedName,
+        method: String,
+    },
+    MethodTypeInfo {
+        class: QualifiedName,
+        method: String,
+    },
+    FieldTypeInfo {
+        class: QualifiedName,
+        field: String,
+    },
+    /// An array type whose object types name class infos in the global pool
+    ArrayType(TypeInfo),
+}
 
+/// The names a class depends on, read before the linker rewrites its pool
+struct ClassDependencies {
+    name: QualifiedName,
+    parent: QualifiedName,
+    interfaces: Vec<QualifiedName>,
+}
 
+impl ClassDependencies {
+    fn new(class: &ClassHeader) -> Result<Self, LinkError> {
+        let class_name = &unlinked_class_name(class);
+        let name_of = |index: PoolIndex| -> Result<QualifiedName, LinkError> {
+            let class_info = get_class_info(class, class_name, index)?;
+            get_string(class, class_name, class_info.name).map(|name| QualifiedName::parse(name))
+        };
 
+        Ok(Self {
+            name: name_of(class.get_this_info())?,
+            parent: name_of(class.get_parent_info())?,
+            interfaces: class.interfaces().iter().map(|interface| name_of(interface.info)).collect::<Result<_, _>>()?,
+        })
+    }
+}
 
+#[derive(Clone, Copy, PartialEq)]
+enum VisitState {
+    Unvisited,
+    Visiting,
+    Done,
+}
 
 pub struct Linker<'a> {
-    pool_mapper: HashMap<String, PoolIndex>,
+    pool_mapper: HashMap<PoolKey, PoolIndex>,
     constant_pool: &'a dyn ConstantPool,
     object_table: &'a dyn ObjectTable,
-    added_classes: HashMap<String, Reference>,
+    added_classes: HashMap<QualifiedName, Reference>,
+    class_loader: Option<&'a dyn ClassLoader>,
+    method_table: Option<&'a dyn MethodTable>,
+    /// Unlinked copies of classes that have been verified or loaded, used to verify classes that refer to them
+    unlinked_classes: HashMap<QualifiedName, ClassHeader>,
 }
 
 impl<'a> Linker<'a> {
@@ -23,60 +286,370 @@ impl<'a> Linker<'a> {
             constant_pool,
             object_table,
             added_classes: HashMap::new(),
+            class_loader: None,
+            method_table: None,
+            unlinked_classes: HashMap::new(),
         }
     }
+
+    /// Lets the linker load classes that were not passed to it by name
+    pub fn with_class_loader(mut self, class_loader: &'a dyn ClassLoader) -> Self {
+        self.class_loader = Some(class_loader);
+        self
+    }
+
+    /// Binds native methods to the methods registered under their qualified names
+    ///
+    /// Without a method table native methods keep the index they were given in the class file.
+    pub fn with_method_table(mut self, method_table: &'a dyn MethodTable) -> Self {
+        self.method_table = Some(method_table);
+        self
+    }
 }
 impl Linker<'_> {
 
-    pub fn link_classes(&mut self, classes: Vec<ClassHeader>, main_class: &str, main_method: &str) -> (Reference, MethodIndex) {
-        let mut deffered = Vec::new();
-
-        for class in classes.into_iter() {
-            if let Some(class) = self.link_class(class) {
-                deffered.push(class);
-            }
+    pub fn link_classes(&mut self, classes: Vec<ClassHeader>, main_class: &str, main_method: &str) -> Result<(Reference, MethodIndex), LinkError> {
+        self.link(classes)?;
+        let main_name = QualifiedName::parse(main_class);
+        if !self.added_classes.contains_key(&main_name) && self.class_loader.is_some() {
+            self.load_class(main_class).map_err(|error| match error {
+                LinkError::ClassNotFound(class) if class == main_class => LinkError::MainClassNotFound(class),
+                error => error,
+            })?;
         }
 
-        while !deffered.is_empty() {
-            let mut new_deffered = Vec::new();
-            for class in deffered.into_iter() {
-                if let Some(class) = self.link_class(class) {
-                    new_deffered.push(class);
-                }
-            }
-            deffered = new_deffered;
-        }
-
-        let class_info_location = self.pool_mapper.get(&format!("ClassInfo: {}", main_class)).expect("Main class not found");
-        let class_info = self.constant_pool.get_constant(*class_info_location);
+        let class_info_location = *self.pool_mapper.get(&PoolKey::ClassInfo(main_name))
+            .ok_or_else(|| LinkError::MainClassNotFound(main_class.to_owned()))?;
+        let class_info = self.constant_pool.get_constant(class_info_location);
         let class_info = match class_info {
             PoolEntry::ClassInfo(class_info) => class_info,
-            x => panic!("Invalid class info {:?}", x),
+            x => return Err(LinkError::WrongPoolEntry {
+                class: main_class.to_owned(),
+                index: class_info_location,
+                expected: "ClassInfo",
+                found: x.kind(),
+            }),
         };
 
-        let class_ref = class_info.class_ref.expect("Main class not linked");
+        let class_ref = class_info.class_ref.ok_or_else(|| LinkError::MainClassNotLinked(main_class.to_owned()))?;
 
         let class = self.object_table.get_class(class_ref);
-        let (method, _) = class.methods().iter().enumerate().find(|(_, method)| {
+        for (index, method) in class.methods().iter().enumerate() {
             let name = self.constant_pool.get_constant(method.name);
             let name = match name {
                 PoolEntry::String(string) => string,
-                x => panic!("Invalid method name {:?}", x),
+                x => return Err(LinkError::WrongPoolEntry {
+                    class: main_class.to_owned(),
+                    index: method.name,
+                    expected: "String",
+                    found: x.kind(),
+                }),
             };
-            name == main_method
-        }).expect("Main method not found");
+            if name == main_method {
+                return Ok((class_ref, index));
+            }
+        }
+
+        Err(LinkError::MainMethodNotFound {
+            class: main_class.to_owned(),
+            method: main_method.to_owned(),
+        })
+    }
+
+    /// Verifies classes and links them into the global constant pool
+    ///
+    /// Supertypes that are missing are read through the class loader and linked along with the classes.
+    pub fn link(&mut self, mut classes: Vec<ClassHeader>) -> Result<(), LinkError> {
+        self.load_supertypes(&mut classes)?;
+        self.verify(&classes)?;
+
+        for class in self.order_classes(classes)? {
+            self.link_class(class)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the class a `ClassInfo` in a linked class's pool refers to, loading and linking it on first use
+    pub fn resolve_class(&mut self, mut class: ClassHeader, pool_index: PoolIndex) -> Result<Reference, LinkError> {
+        let class_name = &self.linked_class_name(&class);
+        let class_info = *get_class_info(&class, class_name, pool_index)?;
+        if let Some(class_ref) = class_info.class_ref {
+            return Ok(class_ref);
+        }
+
+        // The linker points the names of the this and parent class infos at the global pool
+        let name = if pool_index == class.get_this_info() || pool_index == class.get_parent_info() {
+            match self.constant_pool.get_constant(class_info.name) {
+                PoolEntry::String(string) => string,
+                x => return Err(LinkError::WrongPoolEntry {
+                    class: class_name.clone(),
+                    index: class_info.name,
+                    expected: "String",
+                    found: x.kind(),
+                }),
+            }
+        } else {
+            get_string(&class, class_name, class_info.name)?.clone()
+        };
 
-        (class_ref, method)
+        let class_ref = self.load_class(&name)?;
+        class.set_constant_pool_entry(pool_index, PoolEntry::ClassInfo(ClassInfo {
+            name: class_info.name,
+            class_ref: Some(class_ref),
+        }));
+        Ok(class_ref)
     }
 
-    fn link_class(&mut self, mut class: ClassHeader) -> Option<ClassHeader> {
+    /// Returns the global pool index of an array type in a linked class's pool, loading and linking the classes it names
+    ///
+    /// Object types in the global copy name class infos in the global pool, and the class's entry is
+    /// redirected to the copy so it is only resolved once.
+    pub fn resolve_array_type(&mut self, mut class: ClassHeader, pool_index: PoolIndex) -> Result<PoolIndex, LinkError> {
+        let class_name = &self.linked_class_name(&class);
+        let type_info = match get_entry(&class, class_name, pool_index)? {
+            PoolEntry::Redirect(location) => return Ok(*location),
+            PoolEntry::TypeInfo(type_info @ TypeInfo::Array(_)) => type_info.clone(),
+            x => return Err(wrong_entry(class_name, pool_index, "TypeInfo", x)),
+        };
+
+        let type_info = self.resolve_type(class, &type_info)?;
+        let key = PoolKey::ArrayType(type_info.clone());
+        let location = match self.pool_mapper.get(&key) {
+            Some(location) => *location,
+            None => {
+                let location = self.constant_pool.add_constant(PoolEntry::TypeInfo(type_info));
+                self.pool_mapper.insert(key, location);
+                location
+            }
+        };
+        class.set_constant_pool_entry(pool_index, PoolEntry::Redirect(location));
+        Ok(location)
+    }
+
+    /// Points the object types in a type from a linked class's pool at class infos in the global pool
+    fn resolve_type(&mut self, class: ClassHeader, type_info: &TypeInfo) -> Result<TypeInfo, LinkError> {
+        let type_info = match type_info {
+            TypeInfo::Object(index) => {
+                let class_ref = self.resolve_class(class, *index)?;
+                let name = QualifiedName::parse(&self.linked_class_name(&self.object_table.get_class(class_ref)));
+                let location = self.pool_mapper.get(&PoolKey::ClassInfo(name.clone()))
+                    .ok_or_else(|| LinkError::ClassNotFound(name.to_string()))?;
+                TypeInfo::Object(*location)
+            }
+            TypeInfo::Array(element) => TypeInfo::Array(Box::new(self.resolve_type(class, element)?)),
+            type_info => type_info.clone(),
+        };
+        Ok(type_info)
+    }
+
+    /// Links the class with the given name if it has not been linked yet
+    pub fn load_class(&mut self, name: &str) -> Result<Reference, LinkError> {
+        let name = QualifiedName::parse(name);
+        if let Some(class_ref) = self.added_classes.get(&name) {
+            return Ok(*class_ref);
+        }
+        let class = self.find_class(&name)?.ok_or_else(|| LinkError::ClassNotFound(name.to_string()))?;
+        self.link(vec![class])?;
+        self.added_classes.get(&name).copied().ok_or_else(|| LinkError::ClassNotFound(name.to_string()))
+    }
+
+    /// Looks for an unlinked class that was loaded earlier, asks the class loader for it or falls back to the built in classes
+    fn find_class(&mut self, name: &QualifiedName) -> Result<Option<ClassHeader>, LinkError> {
+        if !self.added_classes.contains_key(name) {
+            if let Some(class) = self.unlinked_classes.remove(name) {
+                return Ok(Some(class));
+            }
+        }
+        let loaded = match self.class_loader {
+            Some(class_loader) => class_loader.load_class(&name.to_string()).map_err(|error| LinkError::LoadFailed {
+                class: name.to_string(),
+                message: error.get_message().to_owned(),
+            })?,
+            None => None,
+        };
+        match loaded {
+            Some(class) => Ok(Some(class)),
+            None => Ok(builtin_class(&name.to_string())),
+        }
+    }
+
+    fn load_supertypes(&mut self, classes: &mut Vec<ClassHeader>) -> Result<(), LinkError> {
+        let mut names = HashSet::new();
+        for class in classes.iter() {
+            names.insert(ClassDependencies::new(class)?.name);
+        }
+
+        let mut i = 0;
+        while i < classes.len() {
+            let dependencies = ClassDependencies::new(&classes[i])?;
+            i += 1;
+            for name in std::iter::once(dependencies.parent).chain(dependencies.interfaces) {
+                if names.contains(&name) || self.added_classes.contains_key(&name) {
+                    continue;
+                }
+                // Anything still missing is reported by order_classes
+                if let Some(class) = self.find_class(&name)? {
+                    names.insert(name);
+                    classes.push(class);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Verifies classes against each other and against the classes their code uses
+    ///
+    /// Only the classes the verifier resolves are loaded, along with their supertypes so virtual calls on them
+    /// can be checked, and receivers are loaded once the verifier reaches a call on them. Classes that are
+    /// merely mentioned are loaded when the machine first needs them.
+    fn verify(&mut self, classes: &[ClassHeader]) -> Result<(), LinkError> {
+        let mut names: HashSet<QualifiedName> = classes.iter().map(|class| QualifiedName::parse(&unlinked_class_name(class))).collect();
+        let mut pending: Vec<QualifiedName> = classes.iter()
+            .flat_map(Verifier::required_classes)
+            .map(|name| QualifiedName::parse(&name))
+            .collect();
+        let mut known = classes.to_vec();
+        self.load_for_verification(&mut pending, &mut names, &mut known)?;
+
+        // The class of a receiver is only found by following the code, so it is loaded once the verifier asks for it
+        while let Err(error) = Verifier::new(&known).verify_classes(classes) {
+            match &error.kind {
+                VerifyErrorKind::UnknownClass(name) if !names.contains(&QualifiedName::parse(name)) => {
+                    pending.push(QualifiedName::parse(name));
+                    self.load_for_verification(&mut pending, &mut names, &mut known)?;
+                }
+                _ => return Err(error.into()),
+            }
+        }
+
+        for class in classes {
+            self.unlinked_classes.insert(QualifiedName::parse(&unlinked_class_name(class)), copy_class(class));
+        }
+        Ok(())
+    }
+
+    /// Loads the pending classes the verifier needs and their supertypes, skipping names that were already seen
+    fn load_for_verification(&mut self, pending: &mut Vec<QualifiedName>, names: &mut HashSet<QualifiedName>, known: &mut Vec<ClassHeader>) -> Result<(), LinkError> {
+        while let Some(name) = pending.pop() {
+            if !names.insert(name.clone()) {
+                continue;
+            }
+            if !self.unlinked_classes.contains_key(&name) {
+                if let Some(loaded) = self.find_class(&name)? {
+                    self.unlinked_classes.insert(name.clone(), loaded);
+                }
+            }
+            if let Some(referenced) = self.unlinked_classes.get(&name) {
+                let dependencies = ClassDependencies::new(referenced)?;
+                pending.push(dependencies.parent);
+                pending.extend(dependencies.interfaces);
+                known.push(*referenced);
+            }
+        }
+        Ok(())
+    }
+
+    fn linked_class_name(&self, class: &ClassHeader) -> String {
+        if let Some(PoolEntry::ClassInfo(class_info)) = class.constants().get(class.get_this_info()) {
+            if let PoolEntry::String(name) = self.constant_pool.get_constant(class_info.name) {
+                return name;
+            }
+        }
+        String::from("<unknown>")
+    }
+
+    /// Sorts classes so that every class comes after its parent and the interfaces it implements
+    fn order_classes(&self, classes: Vec<ClassHeader>) -> Result<Vec<ClassHeader>, LinkError> {
+        let dependencies = classes.iter().map(ClassDependencies::new).collect::<Result<Vec<_>, _>>()?;
+        let indices: HashMap<&QualifiedName, usize> = dependencies.iter()
+            .enumerate()
+            .map(|(i, dependencies)| (&dependencies.name, i))
+            .collect();
+
+        let mut states = vec![VisitState::Unvisited; classes.len()];
+        let mut order = Vec::new();
+        for i in 0..classes.len() {
+            let mut chain = Vec::new();
+            self.visit_class(i, &dependencies, &indices, &mut states, &mut chain, &mut order)?;
+        }
+
+        Ok(order.into_iter().map(|i| classes[i]).collect())
+    }
+
+    fn visit_class(
+        &self,
+        index: usize,
+        dependencies: &[ClassDependencies],
+        indices: &HashMap<&QualifiedName, usize>,
+        states: &mut [VisitState],
+        chain: &mut Vec<String>,
+        order: &mut Vec<usize>,
+    ) -> Result<(), LinkError> {
+        let class = &dependencies[index];
+        match states[index] {
+            VisitState::Done => return Ok(()),
+            VisitState::Visiting => {
+                let name = class.name.to_string();
+                let start = chain.iter().position(|link| *link == name).expect("Visiting class is on the chain");
+                let mut cycle = chain[start..].to_vec();
+                cycle.push(name);
+                return Err(LinkError::InheritanceCycle(cycle));
+            }
+            VisitState::Unvisited => {}
+        }
+
+        states[index] = VisitState::Visiting;
+        chain.push(class.name.to_string());
+
+        let parent = if class.parent == class.name || self.added_classes.contains_key(&class.parent) {
+            None
+        } else {
+            match indices.get(&class.parent) {
+                Some(parent) => Some(*parent),
+                None if class.parent.package() == ROOT_PACKAGE && class.parent.name() == ROOT_CLASS => None,
+                None => {
+                    let mut chain = chain.clone();
+                    chain.push(class.parent.to_string());
+                    return Err(LinkError::MissingSuperclass(chain));
+                }
+            }
+        };
+        if let Some(parent) = parent {
+            self.visit_class(parent, dependencies, indices, states, chain, order)?;
+        }
+
+        for interface in class.interfaces.iter() {
+            if self.added_classes.contains_key(interface) {
+                continue;
+            }
+            match indices.get(interface) {
+                Some(interface) => self.visit_class(*interface, dependencies, indices, states, chain, order)?,
+                None => {
+                    let mut chain = chain.clone();
+                    chain.push(interface.to_string());
+                    return Err(LinkError::MissingInterface(chain));
+                }
+            }
+        }
+
+        chain.pop();
+        states[index] = VisitState::Done;
+        order.push(index);
+        Ok(())
+    }
+
+    fn link_class(&mut self, mut class: ClassHeader) -> Result<(), LinkError> {
         let mut skip_indicies = Vec::new();
-        let (name, name_location, this_info_location) = self.link_class_info(&mut class, &mut skip_indicies);
+        let (name, name_location, this_info_location) = self.link_class_info(&mut class, &mut skip_indicies)?;
+        let class_name = name.to_string();
 
         let (class_ref, mut class) = if !self.added_classes.contains_key(&name) {
             let class_ref = self.object_table.add_class(class);
             let mut class = self.object_table.get_class(class_ref);
             self.added_classes.insert(name.clone(), class_ref);
+            if class_name == ARRAY_CLASS {
+                self.object_table.set_array_class(class_ref);
+            }
             class.set_constant_pool_entry(class.get_this_info(), PoolEntry::ClassInfo(ClassInfo {
                 name: name_location,
                 class_ref: Some(class_ref),
@@ -100,46 +673,46 @@ impl Linker<'_> {
                 class_info.class_ref = Some(class_ref);
                 self.constant_pool.set_constant(this_info_location, PoolEntry::ClassInfo(class_info));
             },
-            _ => panic!("Invalid class info"),
+            x => return Err(LinkError::WrongPoolEntry {
+                class: class_name,
+                index: this_info_location,
+                expected: "ClassInfo",
+                found: x.kind(),
+            }),
         }
         
-        self.link_interfaces(&mut class, &mut skip_indicies);
+        self.link_interfaces(&mut class, &mut skip_indicies, &class_name)?;
+        self.link_methods(&mut class, &mut skip_indicies, &name)?;
+        self.build_vtable(&mut class, class_ref);
+        self.build_interface_vtables(&mut class, &class_name)?;
+        self.link_fields(&mut class, &name)?;
+        self.link_initializer(&mut class, &class_name)?;
+        self.link_strings(&mut class, &class_name)?;
+
+        Ok(())
+    }
 
-        if !self.link_methods(&mut class, &mut skip_indicies, &name) {
-            return Some(class);
+    fn intern_string(&mut self, string: &str) -> PoolIndex {
+        let key = PoolKey::String(string.to_owned());
+        if let Some(location) = self.pool_mapper.get(&key) {
+            return *location;
         }
-
-        self.link_fields(&mut class);
-        self.link_strings(&mut class);
-
-
-        None
+        let location = self.constant_pool.add_constant(PoolEntry::String(string.to_owned()));
+        self.pool_mapper.insert(key, location);
+        location
     }
 
-    fn link_class_info(&mut self, class: &mut ClassHeader, skip_indices: &mut Vec<PoolIndex>) -> (String, PoolIndex, PoolIndex) {
+    fn link_class_info(&mut self, class: &mut ClassHeader, skip_indices: &mut Vec<PoolIndex>) -> Result<(QualifiedName, PoolIndex, PoolIndex), LinkError> {
+        let class_name = &unlinked_class_name(class);
         let index = class.get_this_info();
-        let entry = class.get_constant_pool_entry(index);
-        let class_info = match entry {
-            PoolEntry::ClassInfo(class_info) => class_info,
-            _ => panic!("Invalid class info"),
-        };
+        let class_info = get_class_info(class, class_name, index)?;
 
         skip_indices.push(class_info.name);
-        let entry = class.get_constant_pool_entry(class_info.name);
-        let name = match entry {
-            PoolEntry::String(string) => string,
-            _ => panic!("Invalid class name"),
-        };
+        let name = get_string(class, class_name, class_info.name)?;
 
-        let location = if !self.pool_mapper.contains_key(name) {
-            let location = self.constant_pool.add_constant(PoolEntry::String(name.to_owned()));
-            self.pool_mapper.insert(format!("{}", name), location);
-            location
-        } else {
-            *self.pool_mapper.get(name).unwrap()
-        };
+        let location = self.intern_string(name);
 
-        let name = name.clone();
+        let name = QualifiedName::parse(name);
         let name_location = location;
 
         let mut class_info = class_info.clone();
@@ -148,7 +721,7 @@ impl Linker<'_> {
 
         class.set_constant_pool_entry(index, PoolEntry::ClassInfo(class_info));
 
-        let key = format!("ClassInfo: {}", name);
+        let key = PoolKey::ClassInfo(name.clone());
         let location = if !self.pool_mapper.contains_key(&key) {
             let location = self.constant_pool.add_constant(PoolEntry::ClassInfo(class_info));
             self.pool_mapper.insert(key, location);
@@ -160,32 +733,19 @@ impl Linker<'_> {
         let this_info_location = location;
         
         let index = class.get_parent_info();
-        let entry = class.get_constant_pool_entry(index);
-        let class_info = match entry {
-            PoolEntry::ClassInfo(class_info) => class_info,
-            _ => panic!("Invalid class info"),
-        };
+        let class_info = get_class_info(class, class_name, index)?;
 
         skip_indices.push(class_info.name);
-        let entry = class.get_constant_pool_entry(class_info.name);
-        let parent_name = match entry {
-            PoolEntry::String(string) => string.clone(),
-            _ => panic!("Invalid class name"),
-        };
+        let parent_name = get_string(class, class_name, class_info.name)?;
 
-        let location = if !self.pool_mapper.contains_key(&parent_name) {
-            let location = self.constant_pool.add_constant(PoolEntry::String(parent_name.clone()));
-            self.pool_mapper.insert(format!("{}", parent_name), location);
-            location
-        } else {
-            *self.pool_mapper.get(&parent_name).unwrap()
-        };
+        let location = self.intern_string(parent_name);
+        let parent_name = QualifiedName::parse(parent_name);
 
         let mut class_info = class_info.clone();
         class_info.name = location;
         class_info.class_ref = self.added_classes.get(&parent_name).cloned();
 
-        let class_info_key = format!("ClassInfo: {}", parent_name);
+        let class_info_key = PoolKey::ClassInfo(parent_name);
         let _location = if !self.pool_mapper.contains_key(&class_info_key) {
             let location = self.constant_pool.add_constant(PoolEntry::ClassInfo(class_info));
             self.pool_mapper.insert(class_info_key, location);
@@ -196,40 +756,27 @@ impl Linker<'_> {
 
         class.set_constant_pool_entry(index, PoolEntry::ClassInfo(class_info));
 
-        (name, name_location, this_info_location)
+        Ok((name, name_location, this_info_location))
     }
 
-    fn link_interfaces(&mut self, class: &mut ClassHeader, skip_indices: &mut Vec<PoolIndex>) {
+    fn link_interfaces(&mut self, class: &mut ClassHeader, skip_indices: &mut Vec<PoolIndex>, class_name: &str) -> Result<(), LinkError> {
         let mut interface_info_locations = Vec::new();
         let mut interface_locations = Vec::new();
         for interface in class.interfaces() {
             skip_indices.push(interface.info);
 
-            let interface_info = class.get_constant_pool_entry(interface.info);
-            let class_info = match interface_info {
-                PoolEntry::ClassInfo(info) => info,
-                _ => panic!("Invalid class info"),
-            };
-            
-            let interface_name = class.get_constant_pool_entry(class_info.name);
-            let interface_name = match interface_name {
-                PoolEntry::String(string) => string,
-                _ => panic!("Invalid Interface Name"),
+            let class_info = get_class_info(class, class_name, interface.info)?;
+            let interface_name = get_string(class, class_name, class_info.name)?;
 
-            };
-            let location = if !self.pool_mapper.contains_key(interface_name) {
-                let location = self.constant_pool.add_constant(PoolEntry::String(interface_name.to_owned()));
-                self.pool_mapper.insert(format!("{}",interface_name), location);
-                location
-            } else {
-                *self.pool_mapper.get(interface_name).unwrap()
-            };
+            let location = self.intern_string(interface_name);
+            let interface_name = QualifiedName::parse(interface_name);
 
             let mut class_info = class_info.clone();
             let old_location = interface.info;
             class_info.name = location;
+            class_info.class_ref = self.added_classes.get(&interface_name).cloned();
 
-            let class_info_key = format!("ClassInfo: {}", interface_name);
+            let class_info_key = PoolKey::ClassInfo(interface_name);
             let location = if !self.pool_mapper.contains_key(&class_info_key) {
                 let location = self.constant_pool.add_constant(PoolEntry::ClassInfo(class_info));
                 self.pool_mapper.insert(class_info_key, location);
@@ -250,9 +797,11 @@ impl Linker<'_> {
         for (i, interface) in class.interfaces_mut().iter_mut().enumerate() {
             interface.info = interface_info_locations[i];
         }
+        Ok(())
     }
 
-    fn link_methods(&mut self, class: &mut ClassHeader, skip_indices: &mut Vec<PoolIndex>, name: &str) -> bool {
+    fn link_methods(&mut self, class: &mut ClassHeader, skip_indices: &mut Vec<PoolIndex>, name: &QualifiedName) -> Result<(), LinkError> {
+        let class_name = &name.to_string();
         let mut method_indices = Vec::new();
         let mut type_indices = Vec::new();
         let mut name_indices = Vec::new();
@@ -260,33 +809,23 @@ impl Linker<'_> {
             let method_type_info = method_info.type_info;
             let method_name = method_info.name;
             let method_location = method_info.location;
+            let is_abstract = method_info.flags.contains(MethodFlags::Abstract);
 
             skip_indices.push(method_type_info);
             skip_indices.push(method_name);
             skip_indices.push(method_location);
 
-            let method_name = class.get_constant_pool_entry(method_name);
-            let method_name = match method_name {
-                PoolEntry::String(string) => string,
-                x => panic!("Invalid String {:?}", x),
-            };
+            let method_name = get_string(class, class_name, method_name)?;
 
-            let location = if !self.pool_mapper.contains_key(method_name) {
-                let location = self.constant_pool.add_constant(PoolEntry::String(method_name.to_owned()));
-                self.pool_mapper.insert(format!("{}", method_name), location);
-                location
-            } else {
-                *self.pool_mapper.get(method_name).unwrap()
-            };
+            let location = self.intern_string(method_name);
             name_indices.push(location);
 
-            let method_type_info = class.get_constant_pool_entry(method_type_info);
-            let method_type_info = match method_type_info {
-                PoolEntry::TypeInfo(info) => info,
-                _ => panic!("Invalid Type Info"),
-            };
+            let method_type_info = get_type_info(class, class_name, method_type_info)?;
 
-            let key = format!("MethodTypeInfo: {} {}", name, method_name);
+            let key = PoolKey::MethodTypeInfo {
+                class: name.clone(),
+                method: method_name.clone(),
+            };
             let location = if !self.pool_mapper.contains_key(&key) {
                 let location = self.constant_pool.add_constant(PoolEntry::TypeInfo(method_type_info.clone()));
                 self.pool_mapper.insert(key, location);
@@ -296,17 +835,32 @@ impl Linker<'_> {
             };
             type_indices.push(location);
 
-            let method = class.get_constant_pool_entry(method_location);
-            let method = match method {
-                PoolEntry::Method(method) => method,
-                _ => panic!("Invalid Method"),
+            let method = get_method(class, class_name, method_location)?;
+
+            let method = match (method, self.method_table) {
+                // Abstract methods have no body to bind
+                (Method::Native(_), Some(method_table)) if !is_abstract => {
+                    let native_name = format!("{}.{}", class_name, method_name);
+                    let index = method_table.resolve(&native_name).ok_or_else(|| LinkError::UnboundNative(native_name.clone()))?;
+                    if !method_table.accepts(index, method_type_info) {
+                        return Err(LinkError::NativeSignatureMismatch {
+                            method: native_name,
+                            signature: method_type_info.describe(&|index| self.object_type_name(class, index)),
+                        });
+                    }
+                    Method::Native(index)
+                }
+                (method, _) => method.clone(),
             };
 
             let method_location = match method {
                 method => {
-                    let key = format!("Method: {} {}", name, method_name);
+                    let key = PoolKey::Method {
+                        class: name.clone(),
+                        method: method_name.clone(),
+                    };
                     if !self.pool_mapper.contains_key(&key) {
-                        let location = self.constant_pool.add_constant(PoolEntry::Method(method.clone()));
+                        let location = self.constant_pool.add_constant(PoolEntry::Method(method));
                         self.pool_mapper.insert(key, location);
                         location
                     } else {
@@ -325,37 +879,155 @@ impl Linker<'_> {
                    
         }
         
-        true
+        Ok(())
     }
 
-    fn link_fields(&mut self, class: &mut ClassHeader) {
-        let mut new_fields = Vec::new();
+    /// Starts from the parent's vtable, lets methods with the same name and signature replace
+    /// the inherited slot and appends the rest
+    ///
+    /// `Method::Foreign` entries used to forward to the parent by hand and are left out.
+    fn build_vtable(&self, class: &mut ClassHeader, class_ref: Reference) {
+        let mut vtable = match class.get_constant_pool_entry(class.get_parent_info()) {
+            PoolEntry::ClassInfo(ClassInfo { class_ref: Some(parent_ref), .. }) if *parent_ref != class_ref => {
+                self.object_table.get_class(*parent_ref).vtable().to_vec()
+            }
+            _ => Vec::new(),
+        };
+        let mut keys = vtable.iter()
+            .map(|entry| self.method_key(&self.object_table.get_class(entry.class_ref), entry.method))
+            .collect::<Vec<_>>();
 
-        for field in class.fields() {
-            let name = class.get_constant_pool_entry(field.name);
-            let name = match name {
-                PoolEntry::String(string) => string,
-                x => panic!("Invalid String {:?}", x),
+        for (index, method_info) in class.methods().iter().enumerate() {
+            if let PoolEntry::Method(Method::Foreign(_)) = self.constant_pool.get_constant(method_info.location) {
+                continue;
+            }
+            let key = self.method_key(class, index);
+            let entry = VTableEntry {
+                class_ref,
+                method: index,
             };
+            match keys.iter().position(|slot| *slot == key) {
+                Some(slot) => vtable[slot] = entry,
+                None => {
+                    keys.push(key);
+                    vtable.push(entry);
+                }
+            }
+        }
+        class.set_vtable(vtable);
+    }
 
-            let location = if !self.pool_mapper.contains_key(name) {
-                let location = self.constant_pool.add_constant(PoolEntry::String(name.to_owned()));
-                self.pool_mapper.insert(format!("{}", name), location);
-                location
-            } else {
-                *self.pool_mapper.get(name).unwrap()
+    /// Maps every method of each implemented interface to the vtable slot with the same name and signature
+    ///
+    /// Abstract classes may leave methods unimplemented, their interface vtables are then left empty.
+    fn build_interface_vtables(&self, class: &mut ClassHeader, class_name: &str) -> Result<(), LinkError> {
+        let keys = class.vtable().iter()
+            .map(|entry| self.method_key(&self.object_table.get_class(entry.class_ref), entry.method))
+            .collect::<Vec<_>>();
+        let is_abstract = class.get_class_flags().contains(ClassFlags::Abstract);
+
+        let mut vtables = Vec::new();
+        for interface in class.interfaces() {
+            let interface_info = match self.constant_pool.get_constant(interface.info) {
+                PoolEntry::ClassInfo(class_info) => class_info,
+                x => return Err(wrong_entry(class_name, interface.info, "ClassInfo", &x)),
+            };
+            let interface_name = match self.constant_pool.get_constant(interface_info.name) {
+                PoolEntry::String(name) => name,
+                x => return Err(wrong_entry(class_name, interface_info.name, "String", &x)),
             };
+            let interface_class = interface_info.class_ref
+                .map(|interface_ref| self.object_table.get_class(interface_ref))
+                .ok_or_else(|| LinkError::MissingInterface(vec![class_name.to_owned(), interface_name.clone()]))?;
+
+            let mut vtable = Vec::new();
+            for method in 0..interface_class.methods_count() {
+                let key = self.method_key(&interface_class, method);
+                match keys.iter().position(|slot| *slot == key) {
+                    Some(slot) => vtable.push(slot),
+                    None if is_abstract => {
+                        vtable.clear();
+                        break;
+                    }
+                    None => return Err(LinkError::MissingInterfaceMethod {
+                        class: class_name.to_owned(),
+                        interface: interface_name,
+                        method: key,
+                    }),
+                }
+            }
+            vtables.push(vtable);
+        }
+
+        for (interface, vtable) in class.interfaces_mut().iter_mut().zip(vtables) {
+            interface.vtable = vtable;
+        }
+        Ok(())
+    }
+
+    /// The name and signature of a linked method, with object types named by class
+    fn method_key(&self, class: &ClassHeader, method: MethodIndex) -> String {
+        let method_info = class.get_method(method);
+        let name = match self.constant_pool.get_constant(method_info.name) {
+            PoolEntry::String(name) => name,
+            _ => String::new(),
+        };
+        match self.constant_pool.get_constant(method_info.type_info) {
+            PoolEntry::TypeInfo(type_info) => format!("{} {}", name, type_info.describe(&|index| self.object_type_name(class, index))),
+            _ => name,
+        }
+    }
+
+    /// The class name an object type in a linked class's pool refers to
+    fn object_type_name(&self, class: &ClassHeader, index: PoolIndex) -> String {
+        let global_name = |location: PoolIndex| match self.constant_pool.get_constant(location) {
+            PoolEntry::String(name) => Some(name),
+            PoolEntry::ClassInfo(class_info) => match self.constant_pool.get_constant(class_info.name) {
+                PoolEntry::String(name) => Some(name),
+                _ => None,
+            },
+            _ => None,
+        };
+        let name = match class.constants().get(index) {
+            // The linker points the names of the this and parent class infos at the global pool
+            Some(PoolEntry::ClassInfo(class_info)) if index == class.get_this_info() || index == class.get_parent_info() => global_name(class_info.name),
+            Some(PoolEntry::ClassInfo(class_info)) => match class.constants().get(class_info.name) {
+                Some(PoolEntry::String(name)) => Some(name.clone()),
+                _ => None,
+            },
+            Some(PoolEntry::String(name)) => Some(name.clone()),
+            Some(PoolEntry::Redirect(location)) => global_name(*location),
+            _ => None,
+        };
+        name.unwrap_or_else(|| format!("#{}", index))
+    }
+
+    /// Interns field names and types, allocates static storage and lays out the instance fields
+    ///
+    /// Instance fields come after the fields of the parent, each aligned to its own size, so an object
+    /// of the class can hold the fields of every class it inherits from.
+    fn link_fields(&mut self, class: &mut ClassHeader, qualified_name: &QualifiedName) -> Result<(), LinkError> {
+        let class_name = &qualified_name.to_string();
+        let mut new_fields = Vec::new();
+        let mut statics = Vec::new();
+        let mut offsets = Vec::new();
+        let mut size = class.get_parent_ref()
+            .map_or(0, |parent_ref| self.object_table.get_class(parent_ref).instance_size());
+
+        for field in class.fields() {
+            let name = get_string(class, class_name, field.name)?;
+
+            let location = self.intern_string(name);
 
             let mut field = field.clone();
             field.name = location;
 
-            let type_info = class.get_constant_pool_entry(field.type_info);
-            let type_info = match type_info {
-                PoolEntry::TypeInfo(info) => info,
-                x => panic!("Invalid Type Info {:?}", x),
-            };
+            let type_info = get_type_info(class, class_name, field.type_info)?;
 
-            let key = format!("FieldTypeInfo: {}", name);
+            let key = PoolKey::FieldTypeInfo {
+                class: qualified_name.clone(),
+                field: name.clone(),
+            };
             let location = if !self.pool_mapper.contains_key(&key) {
                 let location = self.constant_pool.add_constant(PoolEntry::TypeInfo(type_info.clone()));
                 self.pool_mapper.insert(key, location);
@@ -366,9 +1038,23 @@ impl Linker<'_> {
 
             field.type_info = location;
 
-            if let Some(index) = field.location {
-                let location = self.constant_pool.add_constant(class.get_constant_pool_entry(index).clone());
-                field.location = Some(location);
+            if field.flags.contains(FieldFlags::Static) {
+                let value = match field.location {
+                    Some(index) => get_entry(class, class_name, index)?.clone(),
+                    None => zero_value(type_info),
+                };
+                field.location = Some(statics.len());
+                statics.push(value);
+                offsets.push(None);
+            } else {
+                if let Some(index) = field.location {
+                    let location = self.constant_pool.add_constant(get_entry(class, class_name, index)?.clone());
+                    field.location = Some(location);
+                }
+                let align = type_info.size().max(1);
+                size = size.div_ceil(align) * align;
+                offsets.push(Some(size));
+                size += type_info.size();
             }
 
             
@@ -378,25 +1064,37 @@ impl Linker<'_> {
         for (i, field) in new_fields.into_iter().enumerate() {
             class.set_field(i, field);
         }
+        class.set_statics(statics);
+        class.set_field_layout(offsets, size);
+        Ok(())
     }
 
-    fn link_strings(&mut self, class: &mut ClassHeader) {
+    /// Records the class's `CLASS_INITIALIZER`, which has to be a static `fn() -> unit`
+    fn link_initializer(&self, class: &mut ClassHeader, class_name: &str) -> Result<(), LinkError> {
+        let mut initializer = None;
+        for (index, method_info) in class.methods().iter().enumerate() {
+            match self.constant_pool.get_constant(method_info.name) {
+                PoolEntry::String(name) if name == CLASS_INITIALIZER => {}
+                _ => continue,
+            }
+            let signature = self.constant_pool.get_constant(method_info.type_info);
+            let is_unit = matches!(&signature, PoolEntry::TypeInfo(TypeInfo::Method { args, ret }) if args.is_empty() && **ret == TypeInfo::Unit);
+            if !is_unit || !method_info.flags.contains(MethodFlags::Static) {
+                return Err(LinkError::InvalidInitializer(class_name.to_owned()));
+            }
+            initializer = Some(index);
+        }
+        class.set_initializer(initializer);
+        Ok(())
+    }
+
+    fn link_strings(&mut self, class: &mut ClassHeader, class_name: &str) -> Result<(), LinkError> {
         let mut new_strings = Vec::new();
 
         for string in class.strings() {
-            let string = class.get_constant_pool_entry(*string);
-            let string = match string {
-                PoolEntry::String(string) => string,
-                x => panic!("Invalid String {:?}", x),
-            };
+            let string = get_string(class, class_name, *string)?;
             
-            let location = if !self.pool_mapper.contains_key(string) {
-                let location = self.constant_pool.add_constant(PoolEntry::String(string.to_owned()));
-                self.pool_mapper.insert(format!("{}", string), location);
-                location
-            } else {
-                *self.pool_mapper.get(string).unwrap()
-            };
+            let location = self.intern_string(string);
 
             new_strings.push(location);
         }
@@ -404,6 +1102,610 @@ impl Linker<'_> {
         for (i, location) in new_strings.into_iter().enumerate() {
             class.set_string(i, location);
         }
+        Ok(())
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use definitions::assembler::assemble;
+
+    use super::*;
+    use crate::virtual_machine::{fixtures::class_with_parent, ClassLoader, InstanceConstantPool, InstanceObjectTable, VmInstance};
+    use definitions::ArgType;
+
+    #[test]
+    fn test_wrong_class_info() {
+        let class = assemble(r#"
+            .class name name
+            .const name string "Broken"
+        "#).unwrap();
+
+        let constant_pool = InstanceConstantPool::new();
+        let object_table = InstanceObjectTable::new();
+        let mut linker = Linker::new(&constant_pool, &object_table);
+
+        let error = linker.link_classes(vec![class], "Broken", "Main").unwrap_err();
+        assert_eq!(error, LinkError::WrongPoolEntry {
+            class: String::from("<unknown>"),
+            index: 0,
+            expected: "ClassInfo",
+            found: "String",
+        });
+    }
+
+    #[test]
+    fn test_wrong_method_name() {
+        let class = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "LinkerWrongMethodName"
+            .const parent_name string "cocoa.lang.Object"
+            .const method_type type fn() -> unit
+            .const native method native
+            .method method_type method_type native
+        "#).unwrap();
+
+        let constant_pool = InstanceConstantPool::new();
+        let object_table = InstanceObjectTable::new();
+        let mut linker = Linker::new(&constant_pool, &object_table);
+
+        let error = linker.link_classes(vec![class], "LinkerWrongMethodName", "Main").unwrap_err();
+        assert_eq!(error, LinkError::WrongPoolEntry {
+            class: String::from("LinkerWrongMethodName"),
+            index: 4,
+            expected: "String",
+            found: "TypeInfo",
+        });
+    }
+
+    #[test]
+    fn test_missing_main() {
+        let class = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "LinkerMissingMain"
+            .const parent_name string "cocoa.lang.Object"
+        "#).unwrap();
+
+        let constant_pool = InstanceConstantPool::new();
+        let object_table = InstanceObjectTable::new();
+        let mut linker = Linker::new(&constant_pool, &object_table);
+
+        let error = linker.link_classes(vec![class], "LinkerMissingMain", "Main").unwrap_err();
+        assert_eq!(error, LinkError::MainMethodNotFound {
+            class: String::from("LinkerMissingMain"),
+            method: String::from("Main"),
+        });
+
+        let error = linker.link_classes(vec![], "Missing", "Main").unwrap_err();
+        assert_eq!(error, LinkError::MainClassNotFound(String::from("Missing")));
+    }
+
+    #[test]
+    fn test_links_parent_first() {
+        let child = class_with_parent("OrderChild", "OrderParent", &[]);
+        let parent = class_with_parent("OrderParent", "cocoa.lang.Object", &[]);
+
+        let constant_pool = InstanceConstantPool::new();
+        let object_table = InstanceObjectTable::new();
+        let mut linker = Linker::new(&constant_pool, &object_table);
+
+        let error = linker.link_classes(vec![child, parent], "OrderChild", "Main").unwrap_err();
+        assert!(matches!(error, LinkError::MainMethodNotFound { .. }));
+
+        let child_ref = linker.added_classes[&QualifiedName::parse("OrderChild")];
+        let parent_ref = linker.added_classes[&QualifiedName::parse("OrderParent")];
+        let child = object_table.get_class(child_ref);
+        match child.get_constant_pool_entry(child.get_parent_info()) {
+            PoolEntry::ClassInfo(info) => assert_eq!(info.class_ref, Some(parent_ref)),
+            x => panic!("Expected a class info, found {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_builds_vtable() {
+        let base = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "VTableBase"
+            .const parent_name string "cocoa.lang.Object"
+            .const greet string "greet"
+            .const describe string "describe"
+            .const greet_type type fn() -> u64
+            .const describe_type type fn(object(this)) -> u64
+            .const native0 method native
+            .const native1 method native
+            .method greet greet_type native0 Public
+            .method describe describe_type native1 Public
+        "#).unwrap();
+        let child = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "VTableChild"
+            .const parent_name string "VTableBase"
+            .const greet string "greet"
+            .const describe string "describe"
+            .const greet_type type fn() -> i64
+            .const describe_type type fn(object(parent)) -> u64
+            .const native2 method native
+            .const native3 method native
+            .method describe describe_type native2 Public
+            .method greet greet_type native3 Public
+        "#).unwrap();
+
+        let constant_pool = InstanceConstantPool::new();
+        let object_table = InstanceObjectTable::new();
+        let mut linker = Linker::new(&constant_pool, &object_table);
+        linker.link(vec![child, base]).unwrap();
+
+        let base_ref = linker.added_classes[&QualifiedName::parse("VTableBase")];
+        let child_ref = linker.added_classes[&QualifiedName::parse("VTableChild")];
+        assert_eq!(object_table.get_class(base_ref).vtable(), &[
+            VTableEntry { class_ref: base_ref, method: 0 },
+            VTableEntry { class_ref: base_ref, method: 1 },
+        ]);
+        // describe has the same signature and overrides, greet returns a different type and gets a new slot
+        assert_eq!(object_table.get_class(child_ref).vtable(), &[
+            VTableEntry { class_ref: base_ref, method: 0 },
+            VTableEntry { class_ref: child_ref, method: 0 },
+            VTableEntry { class_ref: child_ref, method: 1 },
+        ]);
+    }
+
+    fn interface_sources(implementor: &str, flags: &str, with_stop: bool) -> Vec<ClassHeader> {
+        let interface = assemble(&format!(r#"
+            .class this parent Interface
+            .const this class name
+            .const parent class parent_name
+            .const name string "{implementor}Runner"
+            .const parent_name string "cocoa.lang.Object"
+            .const run string "run"
+            .const stop string "stop"
+            .const method_type type fn() -> u64
+            .const native method native
+            .method run method_type native Public Abstract
+            .method stop method_type native Public Abstract
+        "#)).unwrap();
+        let base = assemble(&format!(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "{implementor}Base"
+            .const parent_name string "cocoa.lang.Object"
+            .const run string "run"
+            .const method_type type fn() -> u64
+            .const native method native
+            .method run method_type native Public
+        "#)).unwrap();
+        let stop = if with_stop { ".method stop method_type native Public" } else { "" };
+        let class = assemble(&format!(r#"
+            .class this parent {flags}
+            .const this class name
+            .const parent class parent_name
+            .const name string "{implementor}"
+            .const parent_name string "{implementor}Base"
+            .const interface class interface_name
+            .const interface_name string "{implementor}Runner"
+            .const other string "other"
+            .const stop string "stop"
+            .const method_type type fn() -> u64
+            .const native method native
+            .method other method_type native Public
+            {stop}
+            .interface interface
+        "#)).unwrap();
+        vec![class, base, interface]
+    }
+
+    #[test]
+    fn test_builds_interface_vtable() {
+        let constant_pool = InstanceConstantPool::new();
+        let object_table = InstanceObjectTable::new();
+        let mut linker = Linker::new(&constant_pool, &object_table);
+        linker.link(interface_sources("InterfaceImpl", "", true)).unwrap();
+
+        // run is inherited from the parent, stop comes after the class's own other method
+        let class_ref = linker.added_classes[&QualifiedName::parse("InterfaceImpl")];
+        assert_eq!(object_table.get_class(class_ref).get_interface(0).vtable, vec![0, 2]);
+    }
+
+    #[test]
+    fn test_missing_interface_method() {
+        let constant_pool = InstanceConstantPool::new();
+        let object_table = InstanceObjectTable::new();
+        let mut linker = Linker::new(&constant_pool, &object_table);
+        let error = linker.link(interface_sources("InterfaceMissing", "", false)).unwrap_err();
+        assert_eq!(error, LinkError::MissingInterfaceMethod {
+            class: String::from("InterfaceMissing"),
+            interface: String::from("InterfaceMissingRunner"),
+            method: String::from("stop fn() -> u64"),
+        });
+
+        let mut linker = Linker::new(&constant_pool, &object_table);
+        linker.link(interface_sources("InterfaceAbstract", "Abstract", false)).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_initializer() {
+        let class = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "BadInitializer"
+            .const parent_name string "cocoa.lang.Object"
+            .const clinit string "<clinit>"
+            .const clinit_type type fn() -> u32
+            .const native method native
+            .method clinit clinit_type native Static
+        "#).unwrap();
+
+        let constant_pool = InstanceConstantPool::new();
+        let object_table = InstanceObjectTable::new();
+        let mut linker = Linker::new(&constant_pool, &object_table);
+        let error = linker.link(vec![class]).unwrap_err();
+        assert_eq!(error, LinkError::InvalidInitializer(String::from("BadInitializer")));
+    }
+
+    #[test]
+    fn test_missing_superclass() {
+        let main = class_with_parent("MissingMain", "MissingBase", &[]);
+        let base = class_with_parent("MissingBase", "Missing", &[]);
+
+        let constant_pool = InstanceConstantPool::new();
+        let object_table = InstanceObjectTable::new();
+        let mut linker = Linker::new(&constant_pool, &object_table);
+
+        let error = linker.link_classes(vec![main, base], "MissingMain", "Main").unwrap_err();
+        assert_eq!(error, LinkError::MissingSuperclass(vec![
+            String::from("MissingMain"),
+            String::from("MissingBase"),
+            String::from("Missing"),
+        ]));
+    }
+
+    #[test]
+    fn test_missing_interface() {
+        let main = class_with_parent("InterfaceMain", "cocoa.lang.Object", &["Runnable"]);
+
+        let constant_pool = InstanceConstantPool::new();
+        let object_table = InstanceObjectTable::new();
+        let mut linker = Linker::new(&constant_pool, &object_table);
+
+        let error = linker.link_classes(vec![main], "InterfaceMain", "Main").unwrap_err();
+        assert_eq!(error, LinkError::MissingInterface(vec![
+            String::from("InterfaceMain"),
+            String::from("Runnable"),
+        ]));
+    }
+
+    #[test]
+    fn test_inheritance_cycle() {
+        let a = class_with_parent("CycleA", "CycleB", &[]);
+        let b = class_with_parent("CycleB", "CycleC", &[]);
+        let c = class_with_parent("CycleC", "CycleA", &[]);
+
+        let constant_pool = InstanceConstantPool::new();
+        let object_table = InstanceObjectTable::new();
+        let mut linker = Linker::new(&constant_pool, &object_table);
+
+        let error = linker.link_classes(vec![a, b, c], "CycleA", "Main").unwrap_err();
+        assert_eq!(error, LinkError::InheritanceCycle(vec![
+            String::from("CycleA"),
+            String::from("CycleB"),
+            String::from("CycleC"),
+            String::from("CycleA"),
+        ]));
+    }
+
+    struct SourceLoader(HashMap<&'static str, String>);
+
+    impl ClassLoader for SourceLoader {
+        fn load_class(&self, name: &str) -> definitions::CocoaResult<Option<ClassHeader>> {
+            self.0.get(name).map(|source| assemble(source)).transpose()
+        }
+    }
+
+    #[test]
+    fn test_loads_on_demand() {
+        let main = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "DemandMain"
+            .const parent_name string "cocoa.lang.Object"
+            .const helper class helper_name
+            .const helper_name string "DemandHelper"
+            .const main_type type fn() -> i32
+            .const main_code bytecode
+                InvokeStatic helper 0
+                Return
+            .end
+            .method name main_type main_code Static
+        "#).unwrap();
+
+        let mut sources = HashMap::new();
+        sources.insert("DemandHelper", String::from(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "DemandHelper"
+            .const parent_name string "DemandBase"
+            .const answer i32 42
+            .const answer_name string "answer"
+            .const answer_type type fn() -> i32
+            .const answer_code bytecode
+                LoadConstant answer
+                Return
+            .end
+            .method answer_name answer_type answer_code Static
+        "#));
+        sources.insert("DemandBase", String::from(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "DemandBase"
+            .const parent_name string "cocoa.lang.Object"
+        "#));
+        let loader = SourceLoader(sources);
+
+        let constant_pool = InstanceConstantPool::new();
+        let object_table = InstanceObjectTable::new();
+        let mut linker = Linker::new(&constant_pool, &object_table).with_class_loader(&loader);
+
+        let (class_ref, method_index) = linker.link_classes(vec![main], "DemandMain", "DemandMain").unwrap();
+        assert!(!linker.added_classes.contains_key(&QualifiedName::parse("DemandHelper")));
+
+        let main = object_table.get_class(class_ref);
+        let helper_ref = linker.resolve_class(main, 4).unwrap();
+        assert_eq!(linker.added_classes.get(&QualifiedName::parse("DemandHelper")), Some(&helper_ref));
+        assert!(linker.added_classes.contains_key(&QualifiedName::parse("DemandBase")));
+        assert_eq!(linker.resolve_class(main, 4).unwrap(), helper_ref);
+        assert_eq!(method_index, 0);
+
+        assert_eq!(linker.load_class("DemandMissing"), Err(LinkError::ClassNotFound(String::from("DemandMissing"))));
+    }
+
+    /// Loads classes from source like `SourceLoader` and records every class it is asked for
+    struct RecordingLoader {
+        sources: SourceLoader,
+        requested: RefCell<Vec<String>>,
+    }
+
+    impl ClassLoader for RecordingLoader {
+        fn load_class(&self, name: &str) -> definitions::CocoaResult<Option<ClassHeader>> {
+            self.requested.borrow_mut().push(name.to_string());
+            self.sources.load_class(name)
+        }
+    }
+
+    #[test]
+    fn test_verification_loads_only_used_classes() {
+        let main = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "LazyMain"
+            .const parent_name string "cocoa.lang.Object"
+            .const helper class helper_name
+            .const helper_name string "LazyHelper"
+            .const unused class unused_name
+            .const unused_name string "LazyUnused"
+            .const arg class arg_name
+            .const arg_name string "LazyArg"
+            .const main_type type fn() -> i32
+            .const main_code bytecode
+                InvokeStatic helper 0
+                Return
+            .end
+            .const call string "call"
+            .const call_type type fn(object(arg)) -> unit
+            .const call_code bytecode
+                LoadLocal 0
+                InvokeVirtual 0
+                ReturnUnit
+            .end
+            .method name main_type main_code Static
+            .method call call_type call_code Static
+        "#).unwrap();
+
+        let mut sources = HashMap::new();
+        sources.insert("LazyHelper", String::from(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "LazyHelper"
+            .const parent_name string "LazyBase"
+            .const answer i32 42
+            .const answer_name string "answer"
+            .const answer_type type fn() -> i32
+            .const answer_code bytecode
+                LoadConstant answer
+                Return
+            .end
+            .method answer_name answer_type answer_code Static
+        "#));
+        sources.insert("LazyArg", String::from(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "LazyArg"
+            .const parent_name string "cocoa.lang.Object"
+            .const get string "get"
+            .const get_type type fn(object(this)) -> unit
+            .const get_code bytecode
+                ReturnUnit
+            .end
+            .method get get_type get_code Public
+        "#));
+        sources.insert("LazyBase", String::from(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "LazyBase"
+            .const parent_name string "cocoa.lang.Object"
+        "#));
+        let loader = RecordingLoader {
+            sources: SourceLoader(sources),
+            requested: RefCell::new(Vec::new()),
+        };
+
+        let constant_pool = InstanceConstantPool::new();
+        let object_table = InstanceObjectTable::new();
+        let mut linker = Linker::new(&constant_pool, &object_table).with_class_loader(&loader);
+
+        // The invoked class, its parent and the receiver of the virtual call are needed to verify main,
+        // the class that is only mentioned is not
+        linker.link_classes(vec![main], "LazyMain", "LazyMain").unwrap();
+        let requested = loader.requested.borrow();
+        assert!(requested.iter().any(|name| name == "LazyHelper"));
+        assert!(requested.iter().any(|name| name == "LazyBase"));
+        assert!(requested.iter().any(|name| name == "LazyArg"));
+        assert!(!requested.iter().any(|name| name == "LazyUnused"));
+    }
+
+    #[test]
+    fn test_loads_main_class() {
+        let mut sources = HashMap::new();
+        sources.insert("LoadedMain", String::from(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "LoadedMain"
+            .const parent_name string "cocoa.lang.Object"
+            .const main_type type fn() -> unit
+            .const main_code bytecode
+                ReturnUnit
+            .end
+            .method name main_type main_code Static
+        "#));
+        let loader = SourceLoader(sources);
+
+        let constant_pool = InstanceConstantPool::new();
+        let object_table = InstanceObjectTable::new();
+        let mut linker = Linker::new(&constant_pool, &object_table).with_class_loader(&loader);
+
+        let (class_ref, _) = linker.link_classes(vec![], "LoadedMain", "LoadedMain").unwrap();
+        assert_eq!(linker.added_classes.get(&QualifiedName::parse("LoadedMain")), Some(&class_ref));
+
+        let error = linker.link_classes(vec![], "NotThere", "Main").unwrap_err();
+        assert_eq!(error, LinkError::MainClassNotFound(String::from("NotThere")));
+    }
+
+    #[test]
+    fn test_qualified_names_do_not_collide() {
+        let class_with_method = |package: &str, ret: &str| assemble(&format!(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "{package}.Map"
+            .const parent_name string "cocoa.lang.Object"
+            .const get_name string "get"
+            .const get_type type fn() -> {ret}
+            .const get_code method native
+            .method get_name get_type get_code Static
+        "#)).unwrap();
+        let library = class_with_method("cocoa.collection", "i32");
+        let user = class_with_method("app", "u8");
+
+        let constant_pool = InstanceConstantPool::new();
+        let object_table = InstanceObjectTable::new();
+        let mut linker = Linker::new(&constant_pool, &object_table);
+        linker.link(vec![library, user]).unwrap();
+
+        let library_ref = linker.added_classes[&QualifiedName::new("cocoa.collection", "Map")];
+        let user_ref = linker.added_classes[&QualifiedName::new("app", "Map")];
+        assert_ne!(library_ref, user_ref);
+
+        let library = object_table.get_class(library_ref);
+        let user = object_table.get_class(user_ref);
+        assert_eq!(constant_pool.get_constant(library.get_method(0).type_info), PoolEntry::TypeInfo(TypeInfo::Method {
+            args: vec![],
+            ret: Box::new(TypeInfo::I32),
+        }));
+        assert_eq!(constant_pool.get_constant(user.get_method(0).type_info), PoolEntry::TypeInfo(TypeInfo::Method {
+            args: vec![],
+            ret: Box::new(TypeInfo::U8),
+        }));
+    }
+
+    /// A class whose main method stores `add(3, 2)` in a static field, `add` being native
+    fn native_adder(name: &str) -> ClassHeader {
+        assemble(&format!(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "{name}"
+            .const parent_name string "cocoa.lang.Object"
+            .const two i32 2
+            .const three i32 3
+            .const i32_type type i32
+            .const unit_type type fn() -> unit
+            .const add_type type fn(i32, i32) -> i32
+            .const main string "Main"
+            .const add string "add"
+            .const sum string "sum"
+            .const main_code bytecode
+                LoadConstant two
+                LoadConstant three
+                InvokeStatic this 1
+                StoreStatic 0
+                ReturnUnit
+            .end
+            .const add_code method native
+            .field sum i32_type Static
+            .method main unit_type main_code Public Static
+            .method add add_type add_code Public Static
+        "#)).unwrap()
+    }
+
+    #[test]
+    fn test_binds_natives_by_name() {
+        let mut instance = VmInstance::new();
+        let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = calls.clone();
+        instance.native_methods().register_closure("app.Natives.add", move |args, _, _, _| {
+            counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            match args {
+                [ArgType::I32(a), ArgType::I32(b)] => Ok(ArgType::I32(a + b)),
+                _ => panic!("Expected two i32 arguments"),
+            }
+        });
+
+        let mut linker = instance.linker();
+        let (class_ref, main) = linker.link_classes(vec![native_adder("app.Natives")], "app.Natives", "Main").unwrap();
+        let mut vm = instance.machine().with_linker(linker);
+        vm.run_bootstrap(class_ref, main).unwrap();
+        assert_eq!(instance.object_table.get_class(class_ref).statics(), &[PoolEntry::I32(5)]);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        // The index in the class file does not matter, only the name does
+        let error = instance.linker().link(vec![native_adder("app.Unbound")]).unwrap_err();
+        assert_eq!(error, LinkError::UnboundNative(String::from("app.Unbound.add")));
+    }
+
+    #[test]
+    fn test_binds_typed_natives() {
+        let mut instance = VmInstance::new();
+        // The first argument is the one pushed last
+        instance.native_methods().register_fn("app.Typed.add", |a: i32, b: i32| a * 10 + b);
+        instance.native_methods().register_fn("app.Mismatch.add", |a: i64, b: i64| a + b);
+
+        let mut linker = instance.linker();
+        let (class_ref, main) = linker.link_classes(vec![native_adder("app.Typed")], "app.Typed", "Main").unwrap();
+        instance.machine().with_linker(linker).run_bootstrap(class_ref, main).unwrap();
+        assert_eq!(instance.object_table.get_class(class_ref).statics(), &[PoolEntry::I32(32)]);
+
+        let error = instance.linker().link(vec![native_adder("app.Mismatch")]).unwrap_err();
+        assert_eq!(error, LinkError::NativeSignatureMismatch {
+            method: String::from("app.Mismatch.add"),
+            signature: String::from("fn(i32, i32) -> i32"),
+        });
+    }
+}
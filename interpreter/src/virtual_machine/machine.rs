@@ -1,7 +1,7 @@
 
-use definitions::{bytecode::{Bytecode, MethodIndex, Type}, class::{ClassHeader, ClassInfo, FieldFlags, Method, MethodFlags, NativeMethodIndex, PoolEntry, PoolIndex, TypeInfo}, object::{Array, Object, Reference, StringObject}, stack::{Stack, StackUtils}, ArgType, CocoaResult};
+use definitions::{bytecode::{Bytecode, MethodIndex, Type}, class::{ClassHeader, ClassInfo, FieldFlags, Method, MethodFlags, NativeMethodIndex, PoolEntry, PoolIndex, TypeInfo}, object::{reference_view, with_view, Array, Object, Reference, StringObject, MAX_VIEW}, stack::{Stack, StackUtils}, ArgType, CocoaResult, ErrorInfo, Limit};
 
-use crate::virtual_machine::NativeMethod;
+use crate::virtual_machine::{Debugger, Linker, NativeMethod, Profiler, Tracer};
 
 
 pub trait ObjectTable {
@@ -10,6 +10,10 @@ pub trait ObjectTable {
     fn get_object(&self, object_ref: Reference) -> Object;
     fn get_class(&self, class_ref: Reference) -> ClassHeader;
     fn create_array(&self, ty: Type, length: usize) -> Reference;
+    /// An array of references that knows its type, `type_info` is a `TypeInfo::Array` in the global pool
+    fn create_typed_array(&self, type_info: PoolIndex, length: usize) -> Reference;
+    /// Makes arrays created from now on instances of the class, the linker calls it when it links `cocoa.lang.Array`
+    fn set_array_class(&self, class_ref: Reference);
     fn get_array(&self, reference: Reference) -> Array;
     fn create_string(&self, string: String) -> Reference;
     fn get_string(&self, reference: Reference) -> StringObject;
@@ -17,10 +21,23 @@ pub trait ObjectTable {
     fn is_array(&self, reference: Reference) -> bool;
     fn is_class(&self, reference: Reference) -> bool;
     fn is_string(&self, reference: Reference) -> bool;
+    /// Whether the entry the reference pointed to has been freed, its slot may hold another entry by now
+    fn is_stale(&self, reference: Reference) -> bool;
+    /// The number of entries in the table that are alive
+    fn live_objects(&self) -> usize;
+    /// The bytes held by the elements of arrays that are alive
+    fn array_bytes(&self) -> usize;
+    /// Frees every object, array and string that can't be reached from `roots` or a class, returns how many were freed
+    fn collect_garbage(&self, roots: &[Reference], constant_pool: &dyn ConstantPool) -> usize;
 }
 
 pub trait MethodTable {
-    fn get_method(&self, method_index: PoolIndex) -> NativeMethod;
+    /// The native method at an index, `None` if nothing was bound to it
+    fn get_method(&self, method_index: NativeMethodIndex) -> Option<NativeMethod>;
+    /// The index of the native method bound to a qualified name like `cocoa.lang.Array.size`
+    fn resolve(&self, name: &str) -> Option<NativeMethodIndex>;
+    /// Whether the native method can implement a method of the given type, untyped methods accept any
+    fn accepts(&self, method_index: NativeMethodIndex, type_info: &TypeInfo) -> bool;
 }
 
 pub trait ConstantPool {
@@ -29,11 +46,55 @@ pub trait ConstantPool {
     fn get_constant(&self, index: PoolIndex) -> PoolEntry;
 }
 
+/// The class every exception the machine throws extends
+pub const EXCEPTION_CLASS: &str = "cocoa.lang.Exception";
+/// Thrown by `Divide` and `Modulo` on division by zero
+pub const ARITHMETIC_EXCEPTION: &str = "cocoa.lang.ArithmeticException";
+/// Thrown when a method is invoked on null, null is thrown or a null array is indexed
+pub const NULL_POINTER_EXCEPTION: &str = "cocoa.lang.NullPointerException";
+/// Thrown by `ArrayGet` and `ArraySet` for indices past the end of the array
+pub const INDEX_OUT_OF_BOUNDS_EXCEPTION: &str = "cocoa.lang.IndexOutOfBoundsException";
+/// Thrown by `ArraySet` when the value is not an instance of the array's element type
+pub const ARRAY_STORE_EXCEPTION: &str = "cocoa.lang.ArrayStoreException";
+/// Thrown when an object is used as an array or an interface it is not an instance of
+pub const CLASS_CAST_EXCEPTION: &str = "cocoa.lang.ClassCastException";
+/// Thrown when a class a method refers to can't be linked
+pub const CLASS_NOT_FOUND_EXCEPTION: &str = "cocoa.lang.ClassNotFoundException";
+/// The class every array is an instance of
+pub const ARRAY_CLASS: &str = "cocoa.lang.Array";
+
+/// The number of live entries the object table may grow to before the first collection
+const INITIAL_GC_THRESHOLD: usize = 1024;
+
+/// Bounds on the resources a program may use, `None` leaves a resource unbounded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// The number of instructions each `run_bootstrap` may execute
+    pub fuel: Option<u64>,
+    /// The number of frames that may be on the stack
+    pub max_call_depth: Option<usize>,
+    /// The number of entries that may be alive in the object table
+    pub max_live_objects: Option<usize>,
+    /// The number of bytes the elements of live arrays may take up
+    pub max_array_bytes: Option<usize>,
+}
+
 pub struct Machine<'a> {
     stack: Stack,
     object_table: &'a dyn ObjectTable,
     method_table: &'a dyn MethodTable,
     constant_pool: &'a dyn ConstantPool,
+    linker: Option<Linker<'a>>,
+    /// Exceptions that reach this stack depth are uncaught
+    unwind_floor: usize,
+    debugger: Option<Debugger>,
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+    limits: Limits,
+    /// Instructions executed since the last `run_bootstrap`
+    fuel_used: u64,
+    /// The number of live entries at which the next allocation collects garbage
+    gc_threshold: usize,
 }
 
 impl<'a> Machine<'a> {
@@ -43,12 +104,84 @@ impl<'a> Machine<'a> {
             object_table,
             method_table,
             constant_pool,
+            linker: None,
+            unwind_floor: 0,
+            debugger: None,
+            tracer: None,
+            profiler: None,
+            limits: Limits::default(),
+            fuel_used: 0,
+            gc_threshold: INITIAL_GC_THRESHOLD,
         }
     }
+
+    /// Lets the machine link classes that are first used at runtime
+    pub fn with_linker(mut self, linker: Linker<'a>) -> Machine<'a> {
+        self.linker = Some(linker);
+        self
+    }
+
+    /// Stops the program with an error once it uses more than the limits allow
+    pub fn with_limits(mut self, limits: Limits) -> Machine<'a> {
+        self.limits = limits;
+        self
+    }
+
+    /// Logs every executed instruction to the tracer
+    pub fn with_tracer(mut self, tracer: Tracer) -> Machine<'a> {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    /// Counts calls and instructions per method while the machine runs
+    pub fn with_profiler(mut self, profiler: Profiler) -> Machine<'a> {
+        self.profiler = Some(profiler);
+        self
+    }
+
+    /// Lets a debugger pause the machine before instructions
+    pub fn with_debugger(mut self, debugger: Debugger) -> Machine<'a> {
+        self.debugger = Some(debugger);
+        self
+    }
 }
 
 impl Machine<'_> {
 
+    /// Returns the class a `ClassInfo` in the current class's pool refers to, linking it if it hasn't been yet
+    fn resolve_class(&mut self, pool_index: PoolIndex) -> CocoaResult<Reference> {
+        let class = self.object_table.get_class(self.stack.get_class_index());
+        match class.get_constant_pool_entry(pool_index) {
+            PoolEntry::ClassInfo(ClassInfo { class_ref: Some(class_ref), .. }) => return Ok(*class_ref),
+            PoolEntry::ClassInfo(_) => {}
+            x => return Err(ErrorInfo::new(format!("Expected class info, found {}", x.kind()))),
+        }
+        let Some(linker) = self.linker.as_mut() else {
+            return Err(ErrorInfo::new(format!("Class info at {} is not linked and there is no linker", pool_index)));
+        };
+        linker.resolve_class(class, pool_index).map_err(|error| ErrorInfo::new(error.to_string()))
+    }
+
+    /// Returns the global pool index of an array type in the current class's pool, linking the classes it names
+    fn resolve_array_type(&mut self, pool_index: PoolIndex) -> CocoaResult<PoolIndex> {
+        let class = self.object_table.get_class(self.stack.get_class_index());
+        if let PoolEntry::Redirect(location) = class.get_constant_pool_entry(pool_index) {
+            return Ok(*location);
+        }
+        let Some(linker) = self.linker.as_mut() else {
+            return Err(ErrorInfo::new(format!("Array type at {} is not linked and there is no linker", pool_index)));
+        };
+        linker.resolve_array_type(class, pool_index).map_err(|error| ErrorInfo::new(error.to_string()))
+    }
+
+    /// Links `cocoa.lang.Array` so new arrays are instances of it, without a linker arrays have no class
+    fn load_array_class(&mut self) -> CocoaResult<()> {
+        let Some(linker) = self.linker.as_mut() else {
+            return Ok(());
+        };
+        linker.load_class(ARRAY_CLASS).map(|_| ()).map_err(|error| ErrorInfo::new(error.to_string()))
+    }
+
     fn increment_pc(&mut self) {
         let pc = self.stack.get_current_pc();
         self.stack.set_current_pc(pc + 1);
@@ -75,23 +208,178 @@ impl Machine<'_> {
         bytecode[pc]
     }
 
+    /// Runs a method to completion on a fresh stack
+    ///
+    /// Frames left behind by an earlier run that failed are dropped and the fuel is refilled.
     pub fn run_bootstrap(&mut self, main_class_ref: Reference, main_method_index: PoolIndex) -> CocoaResult<()> {
-        self.stack.push_frame(main_class_ref, main_method_index);
+        self.stack = Stack::new();
+        self.unwind_floor = 0;
+        self.fuel_used = 0;
+        self.initialize_class(main_class_ref)?;
+        self.push_frame(main_class_ref, main_method_index)?;
         
         self.run()
     }
 
     pub fn run(&mut self) -> CocoaResult<()> {
+        let result = self.run_until(0);
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.sync(&self.stack, self.object_table, self.constant_pool);
+        }
+        result
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Executes instructions until the stack is back down to `depth` frames
+    fn run_until(&mut self, depth: usize) -> CocoaResult<()> {
         loop {
-            if self.stack.is_empty() {
+            if self.stack.depth() <= depth {
                 break;
             } 
+            if let Some(fuel) = self.limits.fuel {
+                if self.fuel_used >= fuel {
+                    return Err(ErrorInfo::limit_exceeded(Limit::Fuel, format!("Ran out of fuel after {} instructions", fuel)));
+                }
+                self.fuel_used += 1;
+            }
             let instruction = self.get_instruction();
-            self.execute_bytecode(instruction)?;
+            if let Some(debugger) = self.debugger.as_mut() {
+                debugger.before_instruction(instruction, &self.stack, self.object_table, self.constant_pool)?;
+            }
+            if let Some(profiler) = self.profiler.as_mut() {
+                profiler.before_instruction(instruction, &self.stack, self.object_table, self.constant_pool);
+            }
+            let trace_point = match self.tracer.as_mut() {
+                Some(tracer) => tracer.before_instruction(&self.stack, self.object_table, self.constant_pool),
+                None => None,
+            };
+            let result = self.execute_bytecode(instruction);
+            if let (Some(tracer), Some(trace_point)) = (self.tracer.as_mut(), trace_point) {
+                tracer.after_instruction(trace_point, instruction, &self.stack)?;
+            }
+            result?;
         }
         Ok(())
     }
 
+    /// Runs the class initializers of a class and its parents the first time the class is used
+    ///
+    /// The class is marked as initialized before its initializer runs, so the initializer can use the class itself.
+    fn initialize_class(&mut self, class_ref: Reference) -> CocoaResult<()> {
+        let mut class = self.object_table.get_class(class_ref);
+        if class.is_initialized() {
+            return Ok(());
+        }
+        class.set_initialized(true);
+
+        if let PoolEntry::ClassInfo(ClassInfo { class_ref: Some(parent_ref), .. }) = class.get_constant_pool_entry(class.get_parent_info()) {
+            if *parent_ref != class_ref {
+                self.initialize_class(*parent_ref)?;
+            }
+        }
+
+        if let Some(initializer) = class.get_initializer() {
+            // Exceptions can't unwind out of the initializer into the frame that caused it to run
+            let depth = self.stack.depth();
+            let unwind_floor = std::mem::replace(&mut self.unwind_floor, depth);
+            let result = self.push_frame(class_ref, initializer)
+                .and_then(|_| self.run_until(depth));
+            self.unwind_floor = unwind_floor;
+            result?;
+        }
+        Ok(())
+    }
+
+    fn push_frame(&mut self, class_ref: Reference, method_index: MethodIndex) -> CocoaResult<()> {
+        if let Some(max_call_depth) = self.limits.max_call_depth {
+            if self.stack.depth() >= max_call_depth {
+                return Err(ErrorInfo::limit_exceeded(Limit::CallDepth, format!("Call depth exceeded the limit of {} frames", max_call_depth)));
+            }
+        }
+        self.stack.push_frame(class_ref, method_index);
+        Ok(())
+    }
+
+    /// Frees everything that can't be reached from the stack, a static field or a class
+    ///
+    /// Returns the number of objects, arrays and strings that were freed.
+    pub fn collect_garbage(&mut self) -> usize {
+        let freed = self.object_table.collect_garbage(&self.stack.references(), self.constant_pool);
+        self.gc_threshold = (self.object_table.live_objects() * 2).max(INITIAL_GC_THRESHOLD);
+        freed
+    }
+
+    /// Checks that there is room in the object table before something is allocated
+    ///
+    /// Garbage is collected first if the table has doubled since the last collection or is at its limit.
+    fn check_live_objects(&mut self) -> CocoaResult<()> {
+        let live_objects = self.object_table.live_objects();
+        if live_objects >= self.gc_threshold || self.limits.max_live_objects.is_some_and(|max| live_objects >= max) {
+            self.collect_garbage();
+        }
+        if let Some(max_live_objects) = self.limits.max_live_objects {
+            if self.object_table.live_objects() >= max_live_objects {
+                return Err(ErrorInfo::limit_exceeded(Limit::LiveObjects, format!("Live objects exceeded the limit of {}", max_live_objects)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that a new array fits next to the live arrays before it is allocated
+    ///
+    /// Garbage is collected first if it doesn't, so arrays that can't be reached no longer count.
+    fn check_array_bytes(&mut self, ty: Type, length: usize) -> CocoaResult<()> {
+        let Some(max_array_bytes) = self.limits.max_array_bytes else {
+            return Ok(());
+        };
+        let size = match ty {
+            Type::U8 | Type::I8 => 1,
+            Type::U16 | Type::I16 => 2,
+            Type::U32 | Type::I32 | Type::F32 => 4,
+            Type::U64 | Type::I64 | Type::F64 | Type::Reference => 8,
+            Type::Char(size) => size as usize,
+        };
+        let fits = |live_bytes: usize| length.checked_mul(size)
+            .and_then(|bytes| bytes.checked_add(live_bytes))
+            .is_some_and(|bytes| bytes <= max_array_bytes);
+        if !fits(self.object_table.array_bytes()) {
+            self.collect_garbage();
+            if !fits(self.object_table.array_bytes()) {
+                return Err(ErrorInfo::limit_exceeded(Limit::ArrayBytes, format!("Live arrays exceeded the limit of {} bytes", max_array_bytes)));
+            }
+        }
+        Ok(())
+    }
+
+    /// The slot a static field's value lives in
+    fn static_slot(&self, class: &ClassHeader, field_index: usize) -> CocoaResult<usize> {
+        let field_info = class.fields().get(field_index)
+            .ok_or_else(|| ErrorInfo::new(format!("Field index {} is out of bounds", field_index)))?;
+        if !field_info.flags.contains(FieldFlags::Static) {
+            return Err(ErrorInfo::new(format!("Field {} is not static", field_index)));
+        }
+        field_info.location
+            .filter(|slot| *slot < class.statics().len())
+            .ok_or_else(|| ErrorInfo::new(format!("Static field {} has no storage", field_index)))
+    }
+
+    /// The byte offset of an instance field in objects of the class, private fields are only reachable from the class itself
+    fn field_offset(&self, class_ref: Reference, class: &ClassHeader, field_index: usize) -> CocoaResult<usize> {
+        let field_info = class.fields().get(field_index)
+            .ok_or_else(|| ErrorInfo::new(format!("Field index {} is out of bounds", field_index)))?;
+        if field_info.flags.contains(FieldFlags::Static) {
+            return Err(ErrorInfo::new(format!("Field {} is static", field_index)));
+        }
+        if field_info.flags.contains(FieldFlags::Private) && class_ref != self.stack.get_class_index() {
+            return Err(ErrorInfo::new(format!("Field {} is private to its class", field_index)));
+        }
+        class.get_field_offset(field_index)
+            .ok_or_else(|| ErrorInfo::new(format!("Field {} has no offset", field_index)))
+    }
+
     fn execute_bytecode(&mut self, code: Bytecode) -> CocoaResult<()> {
         use Bytecode as B;
         match code {
@@ -132,10 +420,14 @@ impl Machine<'_> {
                 self.stack.multiply()?;
             },
             B::Divide => {
-                self.stack.divide()?;
+                if let Err(error) = self.stack.divide() {
+                    return self.raise(ARITHMETIC_EXCEPTION, error);
+                }
             },
             B::Modulo => {
-                self.stack.modulo()?;
+                if let Err(error) = self.stack.modulo() {
+                    return self.raise(ARITHMETIC_EXCEPTION, error);
+                }
             },
             B::Negate => {
                 self.stack.negate()?;
@@ -292,255 +584,278 @@ impl Machine<'_> {
                 self.stack.return_unit();
                 return Ok(());
             }
+            B::Throw => {
+                let exception = StackUtils::<Reference>::pop(&mut self.stack);
+                if exception == 0 {
+                    return self.raise(NULL_POINTER_EXCEPTION, ErrorInfo::new("Attempted to throw null"));
+                }
+                if !self.is_exception(exception) {
+                    return self.raise(CLASS_CAST_EXCEPTION, ErrorInfo::new("Attempted to throw something that is not an exception"));
+                }
+                return self.throw(exception);
+            }
             // Object Related
             B::New(pool_index) => {
-                let class_ref = self.stack.get_class_index();
-                let class = self.object_table.get_class(class_ref);
-                let class_info = class.get_constant_pool_entry(pool_index);
-                let class_ref = match class_info {
-                    PoolEntry::ClassInfo(info) => info.class_ref.unwrap(),
-                    _ => panic!("Expected class info"),
-                };
+                let class_ref = self.resolve_class(pool_index)?;
+                self.initialize_class(class_ref)?;
+                self.check_live_objects()?;
                 let object_ref = self.object_table.create_object(class_ref);
                 self.stack.push(object_ref);
             }
-            B::SetField(field_index) => {
+            B::SetField(class_index, field_index) => {
                 let object_ref = StackUtils::<Reference>::pop(&mut self.stack);
+                // The field is looked up in the class that declares it, which lays it out at the same offset in every subclass
+                let class_ref = self.resolve_class(class_index)?;
+                let class = self.object_table.get_class(class_ref);
+                let offset = self.field_offset(class_ref, &class, field_index)?;
+                if object_ref == 0 {
+                    return self.raise(NULL_POINTER_EXCEPTION, ErrorInfo::new("Attempted to access a field of null"));
+                }
+                if !self.is_instance_of(object_ref, class_ref) {
+                    return Err(ErrorInfo::new(format!("Field {} belongs to a class the object is not an instance of", field_index)));
+                }
                 let object = self.object_table.get_object(object_ref);
-                let class = self.object_table.get_class(object.get_class());
                 let field_info = class.get_field(field_index);
                 let type_info = self.constant_pool.get_constant(field_info.type_info);
 
                 match type_info {
-                    PoolEntry::TypeInfo(TypeInfo::U8) => {
+                    PoolEntry::TypeInfo(TypeInfo::U8 | TypeInfo::Bool) => {
                         let value = StackUtils::<u8>::pop(&mut self.stack);
-                        object.set_field(field_index, value);
+                        object.set_field(offset, value);
                     },
                     PoolEntry::TypeInfo(TypeInfo::U16) => {
                         let value = StackUtils::<u16>::pop(&mut self.stack);
-                        object.set_field(field_index, value);
+                        object.set_field(offset, value);
                     },
-                    PoolEntry::TypeInfo(TypeInfo::U32) => {
+                    PoolEntry::TypeInfo(TypeInfo::U32 | TypeInfo::Char) => {
                         let value = StackUtils::<u32>::pop(&mut self.stack);
-                        object.set_field(field_index, value);
+                        object.set_field(offset, value);
                     },
                     PoolEntry::TypeInfo(TypeInfo::U64) => {
                         let value = StackUtils::<u64>::pop(&mut self.stack);
-                        object.set_field(field_index, value);
+                        object.set_field(offset, value);
                     },
                     PoolEntry::TypeInfo(TypeInfo::I8) => {
                         let value = StackUtils::<i8>::pop(&mut self.stack);
-                        object.set_field(field_index, value);
+                        object.set_field(offset, value);
                     },
                     PoolEntry::TypeInfo(TypeInfo::I16) => {
                         let value = StackUtils::<i16>::pop(&mut self.stack);
-                        object.set_field(field_index, value);
+                        object.set_field(offset, value);
                     },
                     PoolEntry::TypeInfo(TypeInfo::I32) => {
                         let value = StackUtils::<i32>::pop(&mut self.stack);
-                        object.set_field(field_index, value);
+                        object.set_field(offset, value);
                     },
                     PoolEntry::TypeInfo(TypeInfo::I64) => {
                         let value = StackUtils::<i64>::pop(&mut self.stack);
-                        object.set_field(field_index, value);
+                        object.set_field(offset, value);
                     },
                     PoolEntry::TypeInfo(TypeInfo::F32) => {
                         let value = StackUtils::<f32>::pop(&mut self.stack);
-                        object.set_field(field_index, value);
+                        object.set_field(offset, value);
                     },
                     PoolEntry::TypeInfo(TypeInfo::F64) => {
                         let value = StackUtils::<f64>::pop(&mut self.stack);
-                        object.set_field(field_index, value);
+                        object.set_field(offset, value);
                     },
-                    PoolEntry::TypeInfo(TypeInfo::Object(_)) => {
+                    PoolEntry::TypeInfo(TypeInfo::Object(_) | TypeInfo::String | TypeInfo::Array(_)) => {
                         let object_ref = StackUtils::<Reference>::pop(&mut self.stack);
-                        object.set_field(field_index, object_ref);
+                        object.set_field(offset, object_ref);
                     },
-                    _ => todo!(),
+                    type_info => return Err(ErrorInfo::new(format!("Field {} has unsupported type {:?}", field_index, type_info))),
                 }
 
                 StackUtils::<Reference>::push(&mut self.stack, object_ref);
 
             },
-            B::GetField(field_index) => {
+            B::GetField(class_index, field_index) => {
                 let object_ref = StackUtils::<Reference>::pop(&mut self.stack);
+                // The field is looked up in the class that declares it, which lays it out at the same offset in every subclass
+                let class_ref = self.resolve_class(class_index)?;
+                let class = self.object_table.get_class(class_ref);
+                let offset = self.field_offset(class_ref, &class, field_index)?;
+                if object_ref == 0 {
+                    return self.raise(NULL_POINTER_EXCEPTION, ErrorInfo::new("Attempted to access a field of null"));
+                }
+                if !self.is_instance_of(object_ref, class_ref) {
+                    return Err(ErrorInfo::new(format!("Field {} belongs to a class the object is not an instance of", field_index)));
+                }
                 let object = self.object_table.get_object(object_ref);
-                let class = self.object_table.get_class(object.get_class());
                 let field_info = class.get_field(field_index);
                 let type_info = self.constant_pool.get_constant(field_info.type_info);
 
                 match type_info {
-                    PoolEntry::TypeInfo(TypeInfo::U8) => {
-                        let value = object.get_field::<u8>(field_index);
+                    PoolEntry::TypeInfo(TypeInfo::U8 | TypeInfo::Bool) => {
+                        let value = object.get_field::<u8>(offset);
                         StackUtils::<u8>::push(&mut self.stack, value);
                     },
                     PoolEntry::TypeInfo(TypeInfo::U16) => {
-                        let value = object.get_field::<u16>(field_index);
+                        let value = object.get_field::<u16>(offset);
                         StackUtils::<u16>::push(&mut self.stack, value);
                     },
-                    PoolEntry::TypeInfo(TypeInfo::U32) => {
-                        let value = object.get_field::<u32>(field_index);
+                    PoolEntry::TypeInfo(TypeInfo::U32 | TypeInfo::Char) => {
+                        let value = object.get_field::<u32>(offset);
                         StackUtils::<u32>::push(&mut self.stack, value);
                     },
                     PoolEntry::TypeInfo(TypeInfo::U64) => {
-                        let value = object.get_field::<u64>(field_index);
+                        let value = object.get_field::<u64>(offset);
                         StackUtils::<u64>::push(&mut self.stack, value);
                     },
                     PoolEntry::TypeInfo(TypeInfo::I8) => {
-                        let value = object.get_field::<i8>(field_index);
+                        let value = object.get_field::<i8>(offset);
                         StackUtils::<i8>::push(&mut self.stack, value);
                     },
                     PoolEntry::TypeInfo(TypeInfo::I16) => {
-                        let value = object.get_field::<i16>(field_index);
+                        let value = object.get_field::<i16>(offset);
                         StackUtils::<i16>::push(&mut self.stack, value);
                     },
                     PoolEntry::TypeInfo(TypeInfo::I32) => {
-                        let value = object.get_field::<i32>(field_index);
+                        let value = object.get_field::<i32>(offset);
                         StackUtils::<i32>::push(&mut self.stack, value);
                     },
                     PoolEntry::TypeInfo(TypeInfo::I64) => {
-                        let value = object.get_field::<i64>(field_index);
+                        let value = object.get_field::<i64>(offset);
                         StackUtils::<i64>::push(&mut self.stack, value);
                     },
                     PoolEntry::TypeInfo(TypeInfo::F32) => {
-                        let value = object.get_field::<f32>(field_index);
+                        let value = object.get_field::<f32>(offset);
                         StackUtils::<f32>::push(&mut self.stack, value);
                     },
                     PoolEntry::TypeInfo(TypeInfo::F64) => {
-                        let value = object.get_field::<f64>(field_index);
+                        let value = object.get_field::<f64>(offset);
                         StackUtils::<f64>::push(&mut self.stack, value);
                     },
-                    PoolEntry::TypeInfo(TypeInfo::Object(_)) => {
-                        let value = object.get_field::<Reference>(field_index);
+                    PoolEntry::TypeInfo(TypeInfo::Object(_) | TypeInfo::String | TypeInfo::Array(_)) => {
+                        let value = object.get_field::<Reference>(offset);
                         StackUtils::<Reference>::push(&mut self.stack, value);
                     },
-                    _ => todo!(),
+                    type_info => return Err(ErrorInfo::new(format!("Field {} has unsupported type {:?}", field_index, type_info))),
                 }
 
                 StackUtils::<Reference>::push(&mut self.stack, object_ref);
             }
             B::StoreStatic(field_index) => {
                 let class_ref = self.stack.get_class_index();
-                let class = self.object_table.get_class(class_ref);
+                self.initialize_class(class_ref)?;
+                let mut class = self.object_table.get_class(class_ref);
+                let slot = self.static_slot(&class, field_index)?;
                 let field_info = class.get_field(field_index);
-                let type_info = self.constant_pool.get_constant(field_info.type_info);
-                let Some(pool_index) = field_info.location else {
-                    todo!("Error out on not having a default value");
-                };
-                if field_info.flags.contains(FieldFlags::Const) {
-                    todo!("Error out on trying to set a constant field");
-                }
-                if !field_info.flags.contains(FieldFlags::Static) {
-                    todo!("Error out on trying to set a non-static field");
+                // Const statics may only be written by the class initializer
+                if field_info.flags.contains(FieldFlags::Const) && class.get_initializer() != Some(self.stack.get_current_method_index()) {
+                    return Err(ErrorInfo::new(format!("Static field {} is const and can't be set after initialization", field_index)));
                 }
-                
+                let type_info = self.constant_pool.get_constant(field_info.type_info);
 
-                match type_info {
-                    PoolEntry::TypeInfo(TypeInfo::U8) => {
-                        let value = StackUtils::<u8>::pop(&mut self.stack);
-                        self.constant_pool.set_constant(pool_index, PoolEntry::U8(value));
-                    },
-                    PoolEntry::TypeInfo(TypeInfo::U16) => {
-                        let value = StackUtils::<u16>::pop(&mut self.stack);
-                        self.constant_pool.set_constant(pool_index, PoolEntry::U16(value));
-                    },
-                    PoolEntry::TypeInfo(TypeInfo::U32) => {
-                        let value = StackUtils::<u32>::pop(&mut self.stack);
-                        self.constant_pool.set_constant(pool_index, PoolEntry::U32(value));
-                    },
-                    PoolEntry::TypeInfo(TypeInfo::U64) => {
-                        let value = StackUtils::<u64>::pop(&mut self.stack);
-                        self.constant_pool.set_constant(pool_index, PoolEntry::U64(value));
-                    },
-                    PoolEntry::TypeInfo(TypeInfo::I8) => {
-                        let value = StackUtils::<i8>::pop(&mut self.stack);
-                        self.constant_pool.set_constant(pool_index, PoolEntry::I8(value));
-                    },
-                    PoolEntry::TypeInfo(TypeInfo::I16) => {
-                        let value = StackUtils::<i16>::pop(&mut self.stack);
-                        self.constant_pool.set_constant(pool_index, PoolEntry::I16(value));
-                    },
-                    PoolEntry::TypeInfo(TypeInfo::I32) => {
-                        let value = StackUtils::<i32>::pop(&mut self.stack);
-                        self.constant_pool.set_constant(pool_index, PoolEntry::I32(value));
-                    },
-                    PoolEntry::TypeInfo(TypeInfo::I64) => {
-                        let value = StackUtils::<i64>::pop(&mut self.stack);
-                        self.constant_pool.set_constant(pool_index, PoolEntry::I64(value));
-                    },
-                    PoolEntry::TypeInfo(TypeInfo::F32) => {
-                        let value = StackUtils::<f32>::pop(&mut self.stack);
-                        self.constant_pool.set_constant(pool_index, PoolEntry::F32(value));
-                    },
-                    PoolEntry::TypeInfo(TypeInfo::F64) => {
-                        let value = StackUtils::<f64>::pop(&mut self.stack);
-                        self.constant_pool.set_constant(pool_index, PoolEntry::F64(value));
-                    },
-                    PoolEntry::TypeInfo(TypeInfo::Object(_)) => {
-                        let value = StackUtils::<Reference>::pop(&mut self.stack);
-                        self.constant_pool.set_constant(pool_index, PoolEntry::Reference(value));
-                    },
-                    _ => todo!(),
-                }
+                let value = match type_info {
+                    PoolEntry::TypeInfo(TypeInfo::U8 | TypeInfo::Bool) => PoolEntry::U8(StackUtils::<u8>::pop(&mut self.stack)),
+                    PoolEntry::TypeInfo(TypeInfo::U16) => PoolEntry::U16(StackUtils::<u16>::pop(&mut self.stack)),
+                    PoolEntry::TypeInfo(TypeInfo::U32) => PoolEntry::U32(StackUtils::<u32>::pop(&mut self.stack)),
+                    PoolEntry::TypeInfo(TypeInfo::U64) => PoolEntry::U64(StackUtils::<u64>::pop(&mut self.stack)),
+                    PoolEntry::TypeInfo(TypeInfo::I8) => PoolEntry::I8(StackUtils::<i8>::pop(&mut self.stack)),
+                    PoolEntry::TypeInfo(TypeInfo::I16) => PoolEntry::I16(StackUtils::<i16>::pop(&mut self.stack)),
+                    PoolEntry::TypeInfo(TypeInfo::I32) => PoolEntry::I32(StackUtils::<i32>::pop(&mut self.stack)),
+                    PoolEntry::TypeInfo(TypeInfo::I64) => PoolEntry::I64(StackUtils::<i64>::pop(&mut self.stack)),
+                    PoolEntry::TypeInfo(TypeInfo::F32) => PoolEntry::F32(StackUtils::<f32>::pop(&mut self.stack)),
+                    PoolEntry::TypeInfo(TypeInfo::F64) => PoolEntry::F64(StackUtils::<f64>::pop(&mut self.stack)),
+                    PoolEntry::TypeInfo(TypeInfo::Char) => PoolEntry::Char(char::from_u32(StackUtils::<u32>::pop(&mut self.stack)).unwrap_or(char::REPLACEMENT_CHARACTER)),
+                    PoolEntry::TypeInfo(TypeInfo::Object(_) | TypeInfo::String | TypeInfo::Array(_)) => PoolEntry::Reference(StackUtils::<Reference>::pop(&mut self.stack)),
+                    x => return Err(ErrorInfo::new(format!("Static field {} has unsupported type {:?}", field_index, x))),
+                };
+                class.set_static(slot, value);
             }
             B::LoadStatic(field_index) => {
                 let class_ref = self.stack.get_class_index();
+                self.initialize_class(class_ref)?;
                 let class = self.object_table.get_class(class_ref);
+                let slot = self.static_slot(&class, field_index)?;
                 let field_info = class.get_field(field_index);
                 let type_info = self.constant_pool.get_constant(field_info.type_info);
                 let type_info = match type_info {
                     PoolEntry::TypeInfo(info) => info,
-                    _ => panic!("Expected type info"),
+                    x => return Err(ErrorInfo::new(format!("Expected type info, found {}", x.kind()))),
                 };
-                let Some(pool_index) = field_info.location else {
-                    todo!("Error out on not having a default value");
-                };
-                if !field_info.flags.contains(FieldFlags::Static) {
-                    todo!("Error out on trying to set a non-static field");
-                }
 
-                let value = self.constant_pool.get_constant(pool_index);
-
-                match (value, type_info) {
-                    (PoolEntry::U8(value), TypeInfo::U8) => StackUtils::<u8>::push(&mut self.stack, value),
-                    (PoolEntry::U16(value), TypeInfo::U16) => StackUtils::<u16>::push(&mut self.stack, value),
-                    (PoolEntry::U32(value), TypeInfo::U32) => StackUtils::<u32>::push(&mut self.stack, value),
-                    (PoolEntry::U64(value), TypeInfo::U64) => StackUtils::<u64>::push(&mut self.stack, value),
-                    (PoolEntry::I8(value), TypeInfo::I8) => StackUtils::<i8>::push(&mut self.stack, value),
-                    (PoolEntry::I16(value), TypeInfo::I16) => StackUtils::<i16>::push(&mut self.stack, value),
-                    (PoolEntry::I32(value), TypeInfo::I32) => StackUtils::<i32>::push(&mut self.stack, value),
-                    (PoolEntry::I64(value), TypeInfo::I64) => StackUtils::<i64>::push(&mut self.stack, value),
-                    (PoolEntry::F32(value), TypeInfo::F32) => StackUtils::<f32>::push(&mut self.stack, value),
-                    (PoolEntry::F64(value), TypeInfo::F64) => StackUtils::<f64>::push(&mut self.stack, value),
-                    (PoolEntry::Reference(value), TypeInfo::Object(_)) => StackUtils::<Reference>::push(&mut self.stack, value),
-                    _ => todo!(),
+                match (class.get_static(slot), type_info) {
+                    (PoolEntry::U8(value), TypeInfo::U8 | TypeInfo::Bool) => StackUtils::<u8>::push(&mut self.stack, *value),
+                    (PoolEntry::U16(value), TypeInfo::U16) => StackUtils::<u16>::push(&mut self.stack, *value),
+                    (PoolEntry::U32(value), TypeInfo::U32) => StackUtils::<u32>::push(&mut self.stack, *value),
+                    (PoolEntry::U64(value), TypeInfo::U64) => StackUtils::<u64>::push(&mut self.stack, *value),
+                    (PoolEntry::I8(value), TypeInfo::I8) => StackUtils::<i8>::push(&mut self.stack, *value),
+                    (PoolEntry::I16(value), TypeInfo::I16) => StackUtils::<i16>::push(&mut self.stack, *value),
+                    (PoolEntry::I32(value), TypeInfo::I32) => StackUtils::<i32>::push(&mut self.stack, *value),
+                    (PoolEntry::I64(value), TypeInfo::I64) => StackUtils::<i64>::push(&mut self.stack, *value),
+                    (PoolEntry::F32(value), TypeInfo::F32) => StackUtils::<f32>::push(&mut self.stack, *value),
+                    (PoolEntry::F64(value), TypeInfo::F64) => StackUtils::<f64>::push(&mut self.stack, *value),
+                    (PoolEntry::Char(value), TypeInfo::Char) => StackUtils::<u32>::push(&mut self.stack, *value as u32),
+                    (PoolEntry::Reference(value), TypeInfo::Object(_) | TypeInfo::String | TypeInfo::Array(_)) => StackUtils::<Reference>::push(&mut self.stack, *value),
+                    (value, type_info) => return Err(ErrorInfo::new(format!("Static field {} holds {} but has type {:?}", field_index, value.kind(), type_info))),
                 }
             }
             B::InstanceOf(pool_index) => {
                 let object_ref = StackUtils::<Reference>::pop(&mut self.stack);
                 StackUtils::<Reference>::push(&mut self.stack, object_ref);
-                self.instance_of(object_ref, pool_index);
+                let class = self.object_table.get_class(self.stack.get_class_index());
+                let is_array_type = match class.get_constant_pool_entry(pool_index) {
+                    PoolEntry::TypeInfo(_) => true,
+                    PoolEntry::Redirect(location) => matches!(self.constant_pool.get_constant(*location), PoolEntry::TypeInfo(_)),
+                    _ => false,
+                };
+                let is_instance = if is_array_type {
+                    let type_info = self.resolve_array_type(pool_index)?;
+                    self.is_instance_of_type(object_ref, &self.global_type(type_info)?)
+                } else {
+                    let class_ref = self.resolve_class(pool_index)?;
+                    self.is_instance_of(object_ref, class_ref)
+                };
+                self.instance_of(is_instance);
             }
             B::GetParent => {
                 let object_ref = StackUtils::<Reference>::pop(&mut self.stack);
                 StackUtils::<Reference>::push(&mut self.stack, object_ref);
-                let object = self.object_table.get_object(object_ref);
-                let parent_ref = object.get_parent();
+                if object_ref == 0 {
+                    return self.raise(NULL_POINTER_EXCEPTION, ErrorInfo::new("Attempted to get the parent of null"));
+                }
+                let class_ref = self.view_class(object_ref)?;
+                let view = reference_view(object_ref) + 1;
+                let parent_ref = match self.parent_class(class_ref) {
+                    Some(_) if view <= MAX_VIEW => with_view(object_ref, view),
+                    Some(_) => return Err(ErrorInfo::new(format!("Objects can't be viewed more than {} classes up their hierarchy", MAX_VIEW))),
+                    None => 0,
+                };
                 StackUtils::<Reference>::push(&mut self.stack, parent_ref);
             }
             // Array Related
             B::NewArray(ty) => {
                 let length = StackUtils::<u64>::pop(&mut self.stack) as usize;
+                self.load_array_class()?;
+                self.check_live_objects()?;
+                self.check_array_bytes(ty, length)?;
                 let reference = self.object_table.create_array(ty, length);
                 StackUtils::<Reference>::push(&mut self.stack, reference);
             }
+            B::NewTypedArray(pool_index) => {
+                let length = StackUtils::<u64>::pop(&mut self.stack) as usize;
+                let type_info = self.resolve_array_type(pool_index)?;
+                self.load_array_class()?;
+                self.check_live_objects()?;
+                self.check_array_bytes(Type::Reference, length)?;
+                let reference = self.object_table.create_typed_array(type_info, length);
+                StackUtils::<Reference>::push(&mut self.stack, reference);
+            }
             B::ArrayGet(ty) => {
                 let index = StackUtils::<u64>::pop(&mut self.stack) as usize;
                 let reference = StackUtils::<Reference>::pop(&mut self.stack);
                 StackUtils::<Reference>::push(&mut self.stack, reference);
 
+                if reference == 0 {
+                    return self.raise(NULL_POINTER_EXCEPTION, ErrorInfo::new("Attempted to index a null array"));
+                }
                 if !self.object_table.is_array(reference) {
-                    todo!("Not an Array");
+                    return self.raise(CLASS_CAST_EXCEPTION, ErrorInfo::new("Attempted to index an object that is not an array"));
+                }
+                if index >= self.object_table.get_array(reference).get_size() {
+                    return self.raise(INDEX_OUT_OF_BOUNDS_EXCEPTION, ErrorInfo::new(format!("Array index {} is out of bounds", index)));
                 }
                 
                 let array = self.object_table.get_array(reference);
@@ -596,8 +911,14 @@ impl Machine<'_> {
                 let index = StackUtils::<u64>::pop(&mut self.stack) as usize;
                 let reference = StackUtils::<Reference>::pop(&mut self.stack);
 
+                if reference == 0 {
+                    return self.raise(NULL_POINTER_EXCEPTION, ErrorInfo::new("Attempted to index a null array"));
+                }
                 if !self.object_table.is_array(reference) {
-                    todo!("Not an Array");
+                    return self.raise(CLASS_CAST_EXCEPTION, ErrorInfo::new("Attempted to index an object that is not an array"));
+                }
+                if index >= self.object_table.get_array(reference).get_size() {
+                    return self.raise(INDEX_OUT_OF_BOUNDS_EXCEPTION, ErrorInfo::new(format!("Array index {} is out of bounds", index)));
                 }
 
                 let mut array = self.object_table.get_array(reference);
@@ -644,6 +965,12 @@ impl Machine<'_> {
                     }
                     Type::Reference => {
                         let value = self.stack.pop();
+                        // Null fits any array, anything else has to be an instance of the element type
+                        if let Some(element) = self.array_element(reference).filter(|_| value != 0) {
+                            if !self.is_instance_of_type(value, &element) {
+                                return self.raise(ARRAY_STORE_EXCEPTION, ErrorInfo::new(format!("Value {:#x} is not an instance of the array's element type", value)));
+                            }
+                        }
                         array.set_elem::<Reference>(index, value);
                     }
                     _ => panic!("invalid size "),
@@ -661,12 +988,13 @@ impl Machine<'_> {
                     _ => panic!("Was not a string"),
                 };
 
+                self.check_live_objects()?;
                 let string_ref = self.object_table.create_string(string);
                 self.stack.push(string_ref);
             }
             // Misc
             B::Breakpoint => {
-                todo!("breakpoint")
+                // The debugger has already paused before this instruction, without one it does nothing
             }
             B::Nop => {}
             
@@ -675,27 +1003,189 @@ impl Machine<'_> {
         Ok(())
     }
 
-    fn instance_of(&mut self, object_ref: Reference, pool_index: PoolIndex) {
-        let object = self.object_table.get_object(object_ref);
-        let class_ref = object.get_class();
-
-        let stack_class_index = self.stack.get_class_index();
-        let stack_class = self.object_table.get_class(stack_class_index);
-        let class_info = stack_class.get_constant_pool_entry(pool_index);
-        let class_info = match class_info {
-            PoolEntry::ClassInfo(info) => info,
-            x => panic!("Expected redirect {:?}", x),
-        };
-
-        if class_ref == class_info.class_ref.unwrap() {
+    fn instance_of(&mut self, is_instance: bool) {
+        if is_instance {
             StackUtils::<i8>::push(&mut self.stack, 0);
-        } else if object.get_parent() != 0 {
-            self.instance_of(object.get_parent(), pool_index);
         } else {
             StackUtils::<i8>::push(&mut self.stack, 1);
         }
     }
 
+    /// Whether the object's class is the target class or inherits from it, null is an instance of nothing
+    fn is_instance_of(&self, object_ref: Reference, target_class_ref: Reference) -> bool {
+        self.class_of(object_ref).is_some_and(|class_ref| self.is_subclass(class_ref, target_class_ref))
+    }
+
+    /// Whether a class is the target class or inherits from it
+    fn is_subclass(&self, mut class_ref: Reference, target_class_ref: Reference) -> bool {
+        loop {
+            if class_ref == target_class_ref {
+                return true;
+            }
+            match self.parent_class(class_ref) {
+                Some(parent_ref) => class_ref = parent_ref,
+                None => return false,
+            }
+        }
+    }
+
+    /// Whether a reference is an instance of a type in the global pool, null is an instance of nothing
+    ///
+    /// Arrays are covariant, so an array of a class is an instance of an array of any class it inherits from.
+    fn is_instance_of_type(&self, reference: Reference, type_info: &TypeInfo) -> bool {
+        match type_info {
+            TypeInfo::Object(class_info) => self.global_class(*class_info).is_some_and(|class_ref| self.is_instance_of(reference, class_ref)),
+            TypeInfo::String => self.object_table.is_string(reference),
+            TypeInfo::Array(element) => self.array_element(reference).is_some_and(|actual| self.is_subtype(&actual, element)),
+            _ => false,
+        }
+    }
+
+    /// Whether every value of the type `sub` is also a value of the type `sup`
+    fn is_subtype(&self, sub: &TypeInfo, sup: &TypeInfo) -> bool {
+        match (sub, sup) {
+            (TypeInfo::Object(sub), TypeInfo::Object(sup)) => match (self.global_class(*sub), self.global_class(*sup)) {
+                (Some(sub), Some(sup)) => self.is_subclass(sub, sup),
+                _ => false,
+            },
+            (TypeInfo::Array(sub), TypeInfo::Array(sup)) => self.is_subtype(sub, sup),
+            (sub, sup) => sub == sup,
+        }
+    }
+
+    /// The element type of an array that was created with one, `None` for anything else
+    fn array_element(&self, reference: Reference) -> Option<TypeInfo> {
+        if !self.object_table.is_array(reference) {
+            return None;
+        }
+        let type_info = self.object_table.get_array(reference).get_type_info()?;
+        match self.constant_pool.get_constant(type_info) {
+            PoolEntry::TypeInfo(TypeInfo::Array(element)) => Some(*element),
+            _ => None,
+        }
+    }
+
+    /// The class a `ClassInfo` in the global pool refers to, if it has been linked
+    fn global_class(&self, class_info: PoolIndex) -> Option<Reference> {
+        match self.constant_pool.get_constant(class_info) {
+            PoolEntry::ClassInfo(ClassInfo { class_ref, .. }) => class_ref,
+            _ => None,
+        }
+    }
+
+    fn global_type(&self, index: PoolIndex) -> CocoaResult<TypeInfo> {
+        match self.constant_pool.get_constant(index) {
+            PoolEntry::TypeInfo(type_info) => Ok(type_info),
+            x => Err(ErrorInfo::new(format!("Expected type info, found {}", x.kind()))),
+        }
+    }
+
+    /// The class of an object or array
+    ///
+    /// Null, strings and classes have none, and neither do arrays created before `cocoa.lang.Array` was linked.
+    fn class_of(&self, reference: Reference) -> Option<Reference> {
+        if self.object_table.is_object(reference) {
+            Some(self.object_table.get_object(reference).get_class())
+        } else if self.object_table.is_array(reference) {
+            Some(self.object_table.get_array(reference).get_class()).filter(|class_ref| *class_ref != 0)
+        } else {
+            None
+        }
+    }
+
+    /// The linked parent of a class, `None` for the root of the hierarchy
+    fn parent_class(&self, class_ref: Reference) -> Option<Reference> {
+        self.object_table.get_class(class_ref).get_parent_ref()
+    }
+
+    /// The class a reference views its object as, which is what virtual calls through it dispatch on
+    fn view_class(&self, object_ref: Reference) -> CocoaResult<Reference> {
+        let mut class_ref = self.class_of(object_ref)
+            .ok_or_else(|| ErrorInfo::new(format!("Reference {:#x} has no class to dispatch on", object_ref)))?;
+        for _ in 0..reference_view(object_ref) {
+            class_ref = self.parent_class(class_ref)
+                .ok_or_else(|| ErrorInfo::new("Reference views its object past the root of the class hierarchy"))?;
+        }
+        Ok(class_ref)
+    }
+
+    /// Throws a new instance of one of the classes the machine uses for faults
+    ///
+    /// If the class can't be loaded the fault can't be caught, so `error` is returned instead.
+    fn raise(&mut self, class_name: &str, error: ErrorInfo) -> CocoaResult<()> {
+        let Some(linker) = self.linker.as_mut() else {
+            return Err(error);
+        };
+        let Ok(class_ref) = linker.load_class(class_name) else {
+            return Err(error);
+        };
+        self.initialize_class(class_ref)?;
+        self.check_live_objects()?;
+        let exception = self.object_table.create_object(class_ref);
+        self.throw(exception)
+    }
+
+    /// Whether a reference is an instance of `cocoa.lang.Exception`
+    ///
+    /// Without a linker to look the class up any object passes, strings and arrays never do.
+    fn is_exception(&mut self, reference: Reference) -> bool {
+        match self.linker.as_mut().map(|linker| linker.load_class(EXCEPTION_CLASS)) {
+            Some(Ok(exception_ref)) => self.is_instance_of(reference, exception_ref),
+            _ => self.object_table.is_object(reference),
+        }
+    }
+
+    /// Unwinds to the innermost handler that catches the exception
+    ///
+    /// Frames without a matching handler are popped. The frame with the handler continues at the
+    /// handler's pc with only the exception on its operand stack.
+    fn throw(&mut self, exception: Reference) -> CocoaResult<()> {
+        let mut top_frame = true;
+        while self.stack.depth() > self.unwind_floor {
+            let class_ref = self.stack.get_class_index();
+            let method_index = self.stack.get_current_method_index();
+            let mut pc = self.stack.get_current_pc();
+            // Calling frames have already moved past their invoke instruction
+            if !top_frame {
+                pc -= 1;
+            }
+            top_frame = false;
+
+            let class = self.object_table.get_class(class_ref);
+            for handler in class.exception_handlers() {
+                if handler.method != method_index || pc < handler.start || pc >= handler.end {
+                    continue;
+                }
+                let caught = match handler.catch {
+                    Some(pool_index) => {
+                        let catch_ref = self.resolve_class(pool_index)?;
+                        self.is_instance_of(exception, catch_ref)
+                    }
+                    None => true,
+                };
+                if caught {
+                    self.stack.clear_operands();
+                    self.stack.set_current_pc(handler.handler);
+                    self.stack.push(exception);
+                    return Ok(());
+                }
+            }
+            self.stack.pop_frame();
+        }
+
+        let name = match self.class_of(exception).map(|class_ref| self.object_table.get_class(class_ref)) {
+            Some(class) => match class.get_constant_pool_entry(class.get_this_info()) {
+                PoolEntry::ClassInfo(class_info) => match self.constant_pool.get_constant(class_info.name) {
+                    PoolEntry::String(name) => name,
+                    _ => String::from("<unknown>"),
+                },
+                _ => String::from("<unknown>"),
+            },
+            None => String::from("<unknown>"),
+        };
+        Err(ErrorInfo::new(format!("Uncaught exception {}", name)))
+    }
+
     fn check_method_permissions(&mut self, flags: MethodFlags, current_class: Reference, current_object_class: Reference) -> CocoaResult<()> {
         if flags.contains(MethodFlags::Public) {
             return Ok(());
@@ -728,13 +1218,12 @@ impl Machine<'_> {
     }
 
     fn invoke_static(&mut self, pool_index: PoolIndex, method_index: MethodIndex, tail: bool) -> CocoaResult<()> {
-        let class_ref = self.stack.get_class_index();
-        let class = self.object_table.get_class(class_ref);
-        let class_info = class.get_constant_pool_entry(pool_index);
-        let class_ref = match class_info {
-            PoolEntry::ClassInfo(ClassInfo { class_ref: Some(class_ref), .. }) => *class_ref,
-            x => panic!("Invalid class info {:?}", x),
-        };
+        let class_ref = self.resolve_class(pool_index)?;
+        self.invoke_static_method(class_ref, method_index, tail)
+    }
+
+    fn invoke_static_method(&mut self, class_ref: Reference, method_index: MethodIndex, tail: bool) -> CocoaResult<()> {
+        self.initialize_class(class_ref)?;
         let class = self.object_table.get_class(class_ref);
         let method_info = class.get_method(method_index);
         self.check_method_permissions(method_info.flags, self.stack.get_class_index(), class_ref)?;
@@ -742,7 +1231,7 @@ impl Machine<'_> {
         self.increment_pc();
         match method {
             PoolEntry::Method(Method::Native(native_method_index)) => {
-                self.invoke_rust_native_method(class_ref, native_method_index, method_info.type_info)?;
+                self.invoke_rust_native_method(class_ref, native_method_index, method_info.type_info, tail)?;
             },
             PoolEntry::Method(Method::Bytecode(_)) => {
                 self.invoke_bytecode_method(class_ref, method_index, tail)?
@@ -754,30 +1243,34 @@ impl Machine<'_> {
 
     fn invoke_virtual(&mut self, object_ref: Reference, method_index: MethodIndex, tail: bool) -> CocoaResult<()> {
         if object_ref == 0 {
-            panic!("Attempted to invoke method on null object");
+            return self.raise(NULL_POINTER_EXCEPTION, ErrorInfo::new("Attempted to invoke method on null object"));
         }
-        let object = self.object_table.get_object(object_ref);
-        let class = self.object_table.get_class(object.get_class());
+        let class = self.object_table.get_class(self.view_class(object_ref)?);
 
-        let method_info = class.get_method(method_index);
+        let entry = *class.vtable().get(method_index)
+            .ok_or_else(|| ErrorInfo::new(format!("Virtual method index {} is out of bounds", method_index)))?;
+        let class = self.object_table.get_class(entry.class_ref);
+        let method_info = class.get_method(entry.method);
+
+        self.check_method_permissions(method_info.flags, self.stack.get_class_index(), entry.class_ref)?;
 
-        self.check_method_permissions(method_info.flags, self.stack.get_class_index(), object.get_class())?;
+        // A view only picks the implementation, the method gets the object itself so its own calls dispatch dynamically
+        if reference_view(object_ref) != 0 {
+            StackUtils::<Reference>::pop(&mut self.stack);
+            StackUtils::<Reference>::push(&mut self.stack, with_view(object_ref, 0));
+        }
 
         let method = self.constant_pool.get_constant(method_info.location);
         match method {
             PoolEntry::Method(Method::Native(native_method_index)) => {
                 self.increment_pc();
-                self.invoke_rust_native_method(object.get_class(), native_method_index, method_info.type_info)?;
+                self.invoke_rust_native_method(entry.class_ref, native_method_index, method_info.type_info, tail)?;
             }
             PoolEntry::Method(Method::Bytecode(_)) => {
                 self.increment_pc();
-                self.invoke_bytecode_method(object.get_class(), method_index, tail)?;
+                self.invoke_bytecode_method(entry.class_ref, entry.method, tail)?;
                 return Ok(());
             },
-            PoolEntry::Method(Method::Foreign(method_index)) => {
-                let parent_ref = object.get_parent();
-                self.invoke_virtual(parent_ref, method_index, tail)?;
-            }
             _ => panic!("Entry is not a method"),
         }
         Ok(())
@@ -809,25 +1302,22 @@ impl Machine<'_> {
                 let object_ref = StackUtils::<Reference>::pop(&mut self.stack);
                 StackUtils::<Reference>::push(&mut self.stack, object_ref);
 
-                let method_index = interface_info.vtable[method_index];
+                let method_index = *interface_info.vtable.get(method_index)
+                    .ok_or_else(|| ErrorInfo::new(format!("Interface method index {} is out of bounds", method_index)))?;
 
                 self.invoke_virtual(object_ref, method_index, tail)?;
                 return Ok(());
             }
         }
 
-        todo!("error out on interface not found");
+        self.raise(CLASS_CAST_EXCEPTION, ErrorInfo::new("Class does not implement the interface"))
 
     }
 
     fn invoke_interface_static_method(&mut self, class_info_index: PoolIndex, class_pool_entry: PoolIndex, method_index: MethodIndex, tail: bool) -> CocoaResult<()> {
-
-        let class_ref = self.stack.get_class_index();
-        let class = self.object_table.get_class(class_ref);
-        let class_info = class.get_constant_pool_entry(class_info_index);
-        let class_ref = match class_info {
-            PoolEntry::ClassInfo(ClassInfo { class_ref: Some(class_ref), .. }) => *class_ref,
-            x => panic!("Invalid class info {:?}", x),
+        let class_ref = match self.resolve_class(class_info_index) {
+            Ok(class_ref) => class_ref,
+            Err(error) => return self.raise(CLASS_NOT_FOUND_EXCEPTION, error),
         };
         let class = self.object_table.get_class(class_ref);
 
@@ -852,14 +1342,16 @@ impl Machine<'_> {
             };
             if interface.name == interface_name {
 
-                let method_index = interface_info.vtable[method_index];
+                let slot = *interface_info.vtable.get(method_index)
+                    .ok_or_else(|| ErrorInfo::new(format!("Interface method index {} is out of bounds", method_index)))?;
+                let entry = class.vtable()[slot];
 
-                self.invoke_static(class_info_index, method_index, tail)?;
+                self.invoke_static_method(entry.class_ref, entry.method, tail)?;
                 return Ok(());
             }
         }
 
-        todo!("error out on interface not found");
+        self.raise(CLASS_CAST_EXCEPTION, ErrorInfo::new("Class does not implement the interface"))
 
     }
 
@@ -878,34 +1370,53 @@ impl Machine<'_> {
             _ => panic!("Expected method type info"),
         };
 
-        if !tail {
-            self.stack.push_frame(class_ref, method_index);
-            let mut arg_index = 0;
-            for arg in args {
-                match arg {
-                    TypeInfo::U8 => StackUtils::<u8>::set_argument(&mut self.stack, arg_index),
-                    TypeInfo::U16 => StackUtils::<u16>::set_argument(&mut self.stack, arg_index),
-                    TypeInfo::U32 => StackUtils::<u32>::set_argument(&mut self.stack, arg_index),
-                    TypeInfo::U64 => StackUtils::<u64>::set_argument(&mut self.stack, arg_index),
-                    TypeInfo::I8 => StackUtils::<i8>::set_argument(&mut self.stack, arg_index),
-                    TypeInfo::I16 => StackUtils::<i16>::set_argument(&mut self.stack, arg_index),
-                    TypeInfo::I32 => StackUtils::<i32>::set_argument(&mut self.stack, arg_index),
-                    TypeInfo::I64 => StackUtils::<i64>::set_argument(&mut self.stack, arg_index),
-                    TypeInfo::F32 => StackUtils::<f32>::set_argument(&mut self.stack, arg_index),
-                    TypeInfo::F64 => StackUtils::<f64>::set_argument(&mut self.stack, arg_index),
-                    TypeInfo::Object(_) => StackUtils::<Reference>::set_argument(&mut self.stack, arg_index),
-                    _ => todo!(),
+        // A tail call reuses the caller's frame, so the arguments move within the same frame
+        if tail {
+            self.stack.replace_frame(class_ref, method_index);
+        } else {
+            self.push_frame(class_ref, method_index)?;
+        }
+        let mut arg_index = 0;
+        for arg in args {
+            match arg {
+                TypeInfo::U8 | TypeInfo::Bool => self.set_argument::<u8>(arg_index, tail),
+                TypeInfo::U16 => self.set_argument::<u16>(arg_index, tail),
+                TypeInfo::U32 | TypeInfo::Char => self.set_argument::<u32>(arg_index, tail),
+                TypeInfo::U64 => self.set_argument::<u64>(arg_index, tail),
+                TypeInfo::I8 => self.set_argument::<i8>(arg_index, tail),
+                TypeInfo::I16 => self.set_argument::<i16>(arg_index, tail),
+                TypeInfo::I32 => self.set_argument::<i32>(arg_index, tail),
+                TypeInfo::I64 => self.set_argument::<i64>(arg_index, tail),
+                TypeInfo::F32 => self.set_argument::<f32>(arg_index, tail),
+                TypeInfo::F64 => self.set_argument::<f64>(arg_index, tail),
+                TypeInfo::Object(_) | TypeInfo::String | TypeInfo::Array(_) => self.set_argument::<Reference>(arg_index, tail),
+                TypeInfo::Unit | TypeInfo::Method { .. } => {
+                    return Err(ErrorInfo::new(format!("Methods can't take an argument of type {:?}", arg)));
                 }
-                arg_index += 1;
             }
-        } else {
-            todo!("Add tail version of set_argument");
+            arg_index += 1;
+        }
+        if tail {
+            // Whatever the caller left under the arguments is dead after a tail call
+            self.stack.clear_operands();
         }
         
         Ok(())
     }
 
-    fn invoke_rust_native_method(&mut self, class_ref: Reference, native_method_index: NativeMethodIndex, type_info_index: PoolIndex) -> CocoaResult<()> {
+    /// Moves the top of the caller's operand stack into an argument of the new frame, or of the current one for a tail call
+    fn set_argument<T>(&mut self, arg_index: u8, tail: bool) where Stack: StackUtils<T> {
+        if tail {
+            StackUtils::<T>::set_tail_argument(&mut self.stack, arg_index);
+        } else {
+            StackUtils::<T>::set_argument(&mut self.stack, arg_index);
+        }
+    }
+
+    /// Calls a native method with the arguments on the operand stack
+    ///
+    /// A native method has no frame of its own, so a tail call to one returns its value from the calling frame.
+    fn invoke_rust_native_method(&mut self, class_ref: Reference, native_method_index: NativeMethodIndex, type_info_index: PoolIndex, tail: bool) -> CocoaResult<()> {
         let type_info = self.constant_pool.get_constant(type_info_index);
         let type_info = match type_info {
             PoolEntry::TypeInfo(info) => info,
@@ -921,7 +1432,7 @@ impl Machine<'_> {
 
         for arg in args {
             match arg {
-                TypeInfo::U8 => {
+                TypeInfo::U8 | TypeInfo::Bool => {
                     method_args.push(ArgType::U8(StackUtils::<u8>::pop(&mut self.stack)));
                 }
                 TypeInfo::U16 => {
@@ -951,33 +1462,40 @@ impl Machine<'_> {
                 TypeInfo::F64 => {
                     method_args.push(ArgType::F64(StackUtils::<f64>::pop(&mut self.stack)));
                 }
-                TypeInfo::Object(_) => {
+                TypeInfo::Object(_) | TypeInfo::String | TypeInfo::Array(_) => {
                     method_args.push(ArgType::Reference(StackUtils::<Reference>::pop(&mut self.stack)));
                 }
                 _ => todo!(),
             }
         }
 
-        let native_method = self.method_table.get_method(native_method_index);
-        match native_method {
-            NativeMethod::Rust(method) => {
-                let value = method(&method_args, self.object_table, self.method_table, self.constant_pool)?;
-                match value {
-                    ArgType::U8(value) => self.stack.push(value),
-                    ArgType::U16(value) => self.stack.push(value),
-                    ArgType::U32(value) => self.stack.push(value),
-                    ArgType::U64(value) => self.stack.push(value),
-                    ArgType::I8(value) => self.stack.push(value),
-                    ArgType::I16(value) => self.stack.push(value),
-                    ArgType::I32(value) => self.stack.push(value),
-                    ArgType::I64(value) => self.stack.push(value),
-                    ArgType::F32(value) => self.stack.push(value),
-                    ArgType::F64(value) => self.stack.push(value),
-                    ArgType::Reference(value) => self.stack.push(value),
-                    ArgType::Unit => (),
-                }
-
-            },
+        let native_method = self.method_table.get_method(native_method_index)
+            .ok_or_else(|| ErrorInfo::new(format!("Native method {} is not bound, link the class with a method table", native_method_index)))?;
+        let value = match native_method {
+            NativeMethod::Rust(method) => method(&method_args, self.object_table, self.method_table, self.constant_pool)?,
+            NativeMethod::Closure(method) => method(&method_args, self.object_table, self.method_table, self.constant_pool)?,
+        };
+        match value {
+            ArgType::U8(value) => self.stack.push(value),
+            ArgType::U16(value) => self.stack.push(value),
+            ArgType::U32(value) => self.stack.push(value),
+            ArgType::U64(value) => self.stack.push(value),
+            ArgType::I8(value) => self.stack.push(value),
+            ArgType::I16(value) => self.stack.push(value),
+            ArgType::I32(value) => self.stack.push(value),
+            ArgType::I64(value) => self.stack.push(value),
+            ArgType::F32(value) => self.stack.push(value),
+            ArgType::F64(value) => self.stack.push(value),
+            ArgType::Reference(value) => self.stack.push(value),
+            ArgType::Unit => (),
+        }
+        // Arrays the native method created count against the limit once its result is on the stack and kept alive
+        self.check_array_bytes(Type::U8, 0)?;
+        if tail {
+            match value {
+                ArgType::Unit => self.stack.return_unit(),
+                _ => self.stack.return_value(),
+            }
         }
         Ok(())
     }
@@ -986,614 +1504,997 @@ impl Machine<'_> {
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-    use sequential_test::sequential;
-    use std::cell::RefCell;
-    use crate::virtual_machine::NativeMethod;
-    use definitions::{bytecode::Bytecode, class::{ClassHeader, ClassInfo, InterfaceInfo, Method, MethodFlags, MethodInfo, PoolEntry, PoolIndex}, object::{Object, Reference}, ArgType};
-    use crate::ConstantPoolSingleton;
-    use crate::virtual_machine::Linker;
-
-
-    fn hello_world(_: &[ArgType]) -> CocoaResult<ArgType> {
-        println!("Hello, world!");
-        Ok(ArgType::U64(0))
-    }
-
-    fn print_i32(args: &[ArgType]) -> CocoaResult<ArgType> {
-        match &args[0] {
-            ArgType::I32(value) => println!("{}", value),
-            _ => panic!("Expected i32"),
-        }
-        Ok(ArgType::U64(0))
+    use definitions::{assembler::assemble, class::{ClassHeader, Method, PoolEntry}, object::Reference, ArgType, Limit};
+
+    use super::Limits;
+    use crate::virtual_machine::{fixtures::{class_with_parent, link, record_calls, statics}, ConstantPool, MethodTable, NativeMethodTable, ObjectTable, VmInstance};
+
+    /// A class `Main` whose `Main` method runs `code`, with a native `print` method taking `print_args` if given
+    fn main_class(parent: &str, print_args: Option<&str>, code: &str, extra: &str) -> ClassHeader {
+        let print = match print_args {
+            Some(args) => format!(r#"
+                .const print string "print"
+                .const print_type type fn({args}) -> unit
+                .const print_code method native
+                .method print print_type print_code Public
+            "#),
+            None => String::new(),
+        };
+        assemble(&format!(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "Main"
+            .const parent_name string "{parent}"
+            .const answer i32 42
+            .const i8_type type i8
+            .const unit_type type fn() -> unit
+            .const main string "Main"
+            .const result string "result"
+            .const main_code bytecode
+                {code}
+            .end
+            .field result i8_type Static
+            .method main unit_type main_code Public Static
+            {print}
+            {extra}
+        "#)).unwrap()
     }
 
-    fn print_object(args: &[ArgType]) -> CocoaResult<ArgType> {
-        match &args[0] {
-            ArgType::Reference(value) => println!("{:?}", value),
-            _ => panic!("Expected reference"),
-        }
-        Ok(ArgType::U64(0))
-    }
+    #[test]
+    fn test_hello_world() {
+        let mut instance = VmInstance::new();
+        let calls = record_calls(&mut instance, "Main.print");
+        let class = main_class("cocoa.lang.Object", Some(""), "InvokeStatic this 1\n ReturnUnit", "");
 
-    struct TestObjectTable {
-        objects: RefCell<Vec<Object>>,
-        classes: RefCell<Vec<ClassHeader>>,
+        let (mut vm, class_ref, main) = link(&instance, vec![class], "Main", "Main");
+        vm.run_bootstrap(class_ref, main).unwrap();
+        assert_eq!(*calls.lock().unwrap(), vec![vec![]]);
     }
 
-    impl TestObjectTable {
-        fn new() -> Self {
-            TestObjectTable {
-                objects: RefCell::new(vec![Object::new(0,0,0)]),
-                classes: RefCell::new(Vec::new()),
-            }
-        }
+    #[test]
+    fn test_print_i32() {
+        let mut instance = VmInstance::new();
+        let calls = record_calls(&mut instance, "Main.print");
+        let class = main_class("cocoa.lang.Object", Some("i32"), "LoadConstant answer\n InvokeStatic this 1\n ReturnUnit", "");
 
-        fn add_object(&mut self, object: Object) -> Reference {
-            self.objects.borrow_mut().push(object);
-            self.objects.borrow().len() - 1 as Reference
-        }
+        let (mut vm, class_ref, main) = link(&instance, vec![class], "Main", "Main");
+        vm.run_bootstrap(class_ref, main).unwrap();
+        assert_eq!(*calls.lock().unwrap(), vec![vec![ArgType::I32(42)]]);
     }
 
-    impl ObjectTable for TestObjectTable {
-        fn create_object(&self, class_ref: Reference) -> Reference {
-
-            let class = self.get_class(class_ref);
-
-            let parent_info_index = class.get_parent_info();
-            let parent_info = class.get_constant_pool_entry(parent_info_index);
-            let parent_reference = match parent_info {
-                PoolEntry::ClassInfo(ClassInfo {class_ref: Some(class_ref), ..}) => {
-                    self.create_object(*class_ref)
-                }
-                PoolEntry::ClassInfo(ClassInfo {class_ref: None, ..}) => {
-                    0
-                }
-                x => panic!("Entry was not a class info {:?}", x),
-            };
-
-            // TODO: make this not include static members
-            let field_count = class.fields_count();
-
-            let object = Object::new(parent_reference, class_ref, field_count);
-
-            self.objects.borrow_mut().push(object);
-            self.objects.borrow().len() - 1 as Reference
-        }
-
-        fn add_class(&self, class: ClassHeader) -> Reference {
-            self.classes.borrow_mut().push(class);
-            self.classes.borrow().len() - 1 as Reference
-        }
-
-        fn get_object(&self, object_ref: Reference) -> Object {
-            if object_ref == 0 {
-                panic!("Attempted to get object 0");
-            }
-                       
-            self.objects.borrow()[object_ref as usize].clone()
-        }
-
-        fn get_class(&self, class_ref: Reference) -> ClassHeader {
-            self.classes.borrow()[class_ref as usize].clone()
-        }
-
-        fn create_array(&self, ty: Type, length: usize) -> Reference {
-        }
+    #[test]
+    fn test_reference_and_bool_arguments() {
+        let mut instance = VmInstance::new();
+        let calls = record_calls(&mut instance, "Main.print");
+        let code = "LoadConstant length\n NewArray U8\n NewString 0\n LoadConstant yes\n InvokeStatic this 2\n ReturnUnit";
+        // forward is called normally and tail calls relay, which tail calls print
+        let class = main_class("cocoa.lang.Object", Some("bool, string, [u8]"), code, r#"
+            .const length u64 3
+            .const yes u8 1
+            .const text string "text"
+            .string text
+            .const args_type type fn(bool, string, [u8]) -> unit
+            .const forward string "forward"
+            .const forward_code bytecode
+                LoadLocal 2
+                LoadLocal 1
+                LoadLocal 0
+                InvokeStaticTail this 3
+            .end
+            .const relay string "relay"
+            .const relay_code bytecode
+                LoadLocal 2
+                LoadLocal 1
+                LoadLocal 0
+                InvokeStaticTail this 1
+            .end
+            .method forward args_type forward_code Public Static
+            .method relay args_type relay_code Public Static
+        "#);
+
+        let (mut vm, class_ref, main) = link(&instance, vec![class], "Main", "Main");
+        vm.run_bootstrap(class_ref, main).unwrap();
+        let calls = calls.lock().unwrap();
+        let [ArgType::U8(1), ArgType::Reference(string), ArgType::Reference(array)] = calls[0][..] else {
+            panic!("Expected one call with a bool, a string and an array, found {:?}", calls);
+        };
+        assert!(instance.object_table.is_string(string));
+        assert_eq!(instance.object_table.get_array(array).get_size(), 3);
     }
 
-    struct TestMethodTable {
-        methods: Vec<NativeMethod>,
+    #[test]
+    fn test_object_creation_and_method() {
+        let mut instance = VmInstance::new();
+        let calls = record_calls(&mut instance, "Main.print");
+        // Main takes the first slot of the vtable, print the second
+        let class = main_class("cocoa.lang.Object", Some("object(this)"), "New this\n InvokeVirtual 1\n ReturnUnit", "");
+
+        let (mut vm, class_ref, main) = link(&instance, vec![class], "Main", "Main");
+        vm.run_bootstrap(class_ref, main).unwrap();
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 1);
+        let [ArgType::Reference(object)] = calls[0][..] else {
+            panic!("Expected one call with a reference, found {:?}", calls);
+        };
+        assert_eq!(instance.object_table.get_object(object).get_class(), class_ref);
     }
-    impl TestMethodTable {
-        fn new() -> Self {
-            TestMethodTable {
-                methods: Vec::new(),
-            }
-        }
 
-        fn add_method(&mut self, method: NativeMethod) {
-            self.methods.push(method);
-        }
+    #[test]
+    fn test_object_inheritance() {
+        let mut instance = VmInstance::new();
+        let calls = record_calls(&mut instance, "MainBase.print");
+        let base = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "MainBase"
+            .const parent_name string "cocoa.lang.Object"
+            .const print string "print"
+            .const print_type type fn(object(this)) -> unit
+            .const print_code method native
+            .method print print_type print_code Public
+        "#).unwrap();
+        let class = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "Main"
+            .const parent_name string "MainBase"
+            .const unit_type type fn() -> unit
+            .const main string "Main"
+            .const main_code bytecode
+                New this
+                InvokeVirtual 0
+                ReturnUnit
+            .end
+            .method main unit_type main_code Public Static
+        "#).unwrap();
+
+        // The child has no print of its own and calls the one it inherits
+        let (mut vm, class_ref, main) = link(&instance, vec![class, base], "Main", "Main");
+        vm.run_bootstrap(class_ref, main).unwrap();
+        assert_eq!(calls.lock().unwrap().len(), 1);
     }
 
-    impl MethodTable for TestMethodTable {
-        fn get_method(&self, method_index: PoolIndex) -> NativeMethod {
-            self.methods[method_index as usize].clone()
-        }
+    #[test]
+    fn test_interface() {
+        let mut instance = VmInstance::new();
+        let calls = record_calls(&mut instance, "Main.print");
+        let interface = assemble(r#"
+            .class this parent Interface
+            .const this class name
+            .const parent class parent_name
+            .const name string "PrintRef"
+            .const parent_name string "cocoa.lang.Object"
+            .const print string "print"
+            .const print_type type fn(object(this)) -> unit
+            .const print_code method native
+            .method print print_type print_code Public Abstract
+        "#).unwrap();
+        let class = main_class("cocoa.lang.Object", Some("object(interface)"), "New this\n InvokeInterface interface 0\n ReturnUnit", r#"
+            .const interface_name string "PrintRef"
+            .const interface class interface_name
+            .interface interface
+        "#);
+
+        let (mut vm, class_ref, main) = link(&instance, vec![class, interface], "Main", "Main");
+        vm.run_bootstrap(class_ref, main).unwrap();
+        assert_eq!(calls.lock().unwrap().len(), 1);
     }
 
     #[test]
-    #[sequential]
-    fn test_hello_world() {
-        let mut class = ClassHeader::new(9, 0, 0, 2, 0);
-
-        class.set_parent_info(1);
-        class.set_this_info(0);
-
-        class.set_constant_pool_entry(0, PoolEntry::ClassInfo(ClassInfo {
-            name: 2,
-            class_ref: None,
-        }));
-        class.set_constant_pool_entry(1, PoolEntry::ClassInfo(ClassInfo {
-            name: 3,
-            class_ref: None,
-        }));
-        class.set_constant_pool_entry(2, PoolEntry::String("Main".to_owned()));
-        class.set_constant_pool_entry(3, PoolEntry::String("Object".to_owned()));
-        class.set_constant_pool_entry(4, PoolEntry::Method(Method::Bytecode(vec![Bytecode::InvokeStatic(1), Bytecode::Return].into())));
-        class.set_constant_pool_entry(5, PoolEntry::Method(Method::Native(0)));
-        class.set_constant_pool_entry(6, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(7, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(8, PoolEntry::String("helloWorld".to_owned()));
-
-        class.set_method(0, MethodInfo {
-            flags: MethodFlags::Static,
-            name: 2,
-            type_info: 6,
-            location: 4,
-        });
-
-        class.set_method(1, MethodInfo {
-            flags: MethodFlags::Static,
-            name: 8,
-            type_info: 7,
-            location: 5,
-        });
-        
-        let constant_pool = ConstantPoolSingleton::new();
-        let object_table = TestObjectTable::new();
-        let mut linker = Linker::new(&constant_pool, &object_table);
-
-        let (class_ref, method_index) = linker.link_classes(vec![class], "Main", "Main");
-
-        let mut method_table = TestMethodTable::new();
-
-        method_table.add_method(NativeMethod::Rust(hello_world));
-        
-
-        let mut vm = Machine::new(&object_table, &method_table, &constant_pool);
+    fn test_object_instance_of() {
+        let instance = VmInstance::new();
+        let class = main_class("cocoa.lang.Object", None, "New this\n InstanceOf this\n StoreStatic 0\n Pop\n ReturnUnit", "");
 
-        vm.run_bootstrap(class_ref, method_index).unwrap();
+        let (mut vm, class_ref, main) = link(&instance, vec![class], "Main", "Main");
+        vm.run_bootstrap(class_ref, main).unwrap();
+        assert_eq!(statics(&instance, class_ref), [PoolEntry::I8(0)]);
     }
 
     #[test]
-    #[sequential]
-    fn test_print_i32() {
-        let mut class = ClassHeader::new(10, 0, 0, 2, 0);
-
-        class.set_parent_info(1);
-        class.set_this_info(0);
-
-        class.set_constant_pool_entry(0, PoolEntry::ClassInfo(ClassInfo {
-            name: 2,
-            class_ref: None,
-        }));
-        class.set_constant_pool_entry(1, PoolEntry::ClassInfo(ClassInfo {
-            name: 3,
-            class_ref: None,
-        }));
-        class.set_constant_pool_entry(2, PoolEntry::String("Main".to_owned()));
-        class.set_constant_pool_entry(3, PoolEntry::String("Object".to_owned()));
-        class.set_constant_pool_entry(4, PoolEntry::Method(Method::Bytecode(vec![Bytecode::LoadConstant(8), Bytecode::InvokeStatic(1), Bytecode::Return].into())));
-        class.set_constant_pool_entry(5, PoolEntry::Method(Method::Native(0)));
-        class.set_constant_pool_entry(6, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(7, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![TypeInfo::I32], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(8, PoolEntry::I32(42));
-        class.set_constant_pool_entry(9, PoolEntry::String("print".to_owned()));
-
-        class.set_method(0, MethodInfo {
-            flags: MethodFlags::Static,
-            name: 2,
-            type_info: 6,
-            location: 4,
-        });
-
-        class.set_method(1, MethodInfo {
-            flags: MethodFlags::Static,
-            name: 9,
-            type_info: 7,
-            location: 5,
-        });
-
-        let constant_pool = ConstantPoolSingleton::new();
-        let object_table = TestObjectTable::new();
-        let mut linker = Linker::new(&constant_pool, &object_table);
-
-        let (class_ref, method_index) = linker.link_classes(vec![class], "Main", "Main");
-
-        let mut method_table = TestMethodTable::new();
-
-        method_table.add_method(NativeMethod::Rust(print_i32));
-
-        let mut vm = Machine::new(&object_table, &method_table, &constant_pool);
-
-        vm.run_bootstrap(class_ref, method_index).unwrap();
+    fn test_object_instance_of_inheritance() {
+        let instance = VmInstance::new();
+        let base = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "MainBase"
+            .const parent_name string "cocoa.lang.Object"
+        "#).unwrap();
+        let other = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "Other"
+            .const parent_name string "cocoa.lang.Object"
+        "#).unwrap();
+        let class = main_class("MainBase", None, "New this\n InstanceOf parent\n StoreStatic 0\n InstanceOf other\n StoreStatic 1\n Pop\n ReturnUnit", r#"
+            .const other_name string "Other"
+            .const other class other_name
+            .field other_result i8_type Static
+            .const other_result string "other_result"
+        "#);
+
+        // An instance of a child is an instance of its parent but not of an unrelated class
+        let (mut vm, class_ref, main) = link(&instance, vec![class, base, other], "Main", "Main");
+        vm.run_bootstrap(class_ref, main).unwrap();
+        assert_eq!(statics(&instance, class_ref), [PoolEntry::I8(0), PoolEntry::I8(1)]);
     }
 
     #[test]
-    #[sequential]
-    fn test_object_creation_and_method() {
-        let mut class = ClassHeader::new(10, 0, 0, 2, 0);
-
-        class.set_parent_info(1);
-        class.set_this_info(0);
-
-        class.set_constant_pool_entry(0, PoolEntry::ClassInfo(ClassInfo {
-            name: 2,
-            class_ref: Some(0),
-        }));
-        class.set_constant_pool_entry(1, PoolEntry::ClassInfo(ClassInfo {
-            name: 3,
-            class_ref: None,
-        }));
-        class.set_constant_pool_entry(2, PoolEntry::String("Main".to_owned()));
-        class.set_constant_pool_entry(3, PoolEntry::String("Object".to_owned()));
-        class.set_constant_pool_entry(4, PoolEntry::Method(Method::Bytecode(vec![Bytecode::New(0), Bytecode::InvokeVirtual(1), Bytecode::Return].into())));
-        class.set_constant_pool_entry(5, PoolEntry::Method(Method::Native(0)));
-        class.set_constant_pool_entry(6, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(7, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![TypeInfo::Object(3)], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(8, PoolEntry::String("printRef".to_owned()));
-
-        class.set_method(0, MethodInfo {
-            flags: MethodFlags::Static,
-            name: 2,
-            type_info: 6,
-            location: 4,
-        });
-
-        class.set_method(1, MethodInfo {
-            flags: MethodFlags::Public,
-            name: 8,
-            type_info: 7,
-            location: 5,
-        });
-
-        let constant_pool = ConstantPoolSingleton::new();
-        let object_table = TestObjectTable::new();
-        let mut linker = Linker::new(&constant_pool, &object_table);
-
-        let (class_ref, method_index) = linker.link_classes(vec![class], "Main", "Main");
-
-        let mut method_table = TestMethodTable::new();
-
-        method_table.add_method(NativeMethod::Rust(print_object));
+    fn test_static_fields_and_initializer() {
+        let class = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "StaticCounter"
+            .const parent_name string "cocoa.lang.Object"
+            .const count string "count"
+            .const limit string "limit"
+            .const offset string "offset"
+            .const u32_type type u32
+            .const seven u32 7
+            .const one u32 1
+            .const ten u32 10
+            .const unit_type type fn() -> unit
+            .const clinit string "<clinit>"
+            .const main string "Main"
+            .const touch string "touch"
+            .const overwrite string "overwrite"
+            .const clinit_code bytecode
+                LoadStatic 0
+                LoadConstant one
+                Add
+                StoreStatic 0
+                LoadConstant ten
+                StoreStatic 1
+                ReturnUnit
+            .end
+            .const main_code bytecode
+                InvokeStatic this 2
+                InvokeStatic this 2
+                ReturnUnit
+            .end
+            .const touch_code bytecode
+                LoadStatic 0
+                Pop
+                ReturnUnit
+            .end
+            .const overwrite_code bytecode
+                LoadConstant one
+                StoreStatic 1
+                ReturnUnit
+            .end
+            .field count u32_type Static
+            .field limit u32_type = seven Static Const
+            .field offset u32_type = seven
+            .method clinit unit_type clinit_code Static
+            .method main unit_type main_code Static
+            .method touch unit_type touch_code Static
+            .method overwrite unit_type overwrite_code Static
+        "#).unwrap();
+
+        let instance = VmInstance::new();
+        let (mut vm, class_ref, main) = link(&instance, vec![class], "StaticCounter", "Main");
+
+        let class = instance.object_table.get_class(class_ref);
+        assert_eq!(class.statics(), &[PoolEntry::U32(0), PoolEntry::U32(7)]);
+        assert_eq!(class.get_field(0).location, Some(0));
+        assert_eq!(class.get_field(1).location, Some(1));
+        assert_eq!(class.get_initializer(), Some(0));
+
+        // The initializer runs once even though touch is invoked twice
+        vm.run_bootstrap(class_ref, main).unwrap();
+        assert_eq!(class.statics(), &[PoolEntry::U32(1), PoolEntry::U32(10)]);
+
+        let error = vm.run_bootstrap(class_ref, 3).unwrap_err();
+        assert!(error.get_message().contains("const"));
+        assert_eq!(class.statics(), &[PoolEntry::U32(1), PoolEntry::U32(10)]);
+    }
 
-        let mut vm = Machine::new(&object_table, &method_table, &constant_pool);
 
-        vm.run_bootstrap(class_ref, method_index).unwrap();
+    #[test]
+    fn test_bool_and_char_fields() {
+        let class = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "FlagMain"
+            .const parent_name string "cocoa.lang.Object"
+            .const flag string "flag"
+            .const letter string "letter"
+            .const flag_copy string "flag_copy"
+            .const letter_copy string "letter_copy"
+            .const bool_type type bool
+            .const char_type type char
+            .const yes u8 1
+            .const unit_type type fn() -> unit
+            .const main string "Main"
+            .const main_code bytecode
+                New this
+                StoreLocal 0
+                LoadConstant yes
+                LoadLocal 0
+                SetField this 0
+                GetField this 0
+                Pop
+                StoreStatic 2
+                LoadLocal 0
+                GetField this 1
+                Pop
+                LoadLocal 0
+                SetField this 1
+                GetField this 1
+                Pop
+                StoreStatic 3
+                ReturnUnit
+            .end
+            .field flag bool_type
+            .field letter char_type
+            .field flag_copy bool_type Static
+            .field letter_copy char_type Static
+            .method main unit_type main_code Public Static
+        "#).unwrap();
+
+        let instance = VmInstance::new();
+        let (mut vm, class_ref, main) = link(&instance, vec![class], "FlagMain", "Main");
+        vm.run_bootstrap(class_ref, main).unwrap();
+        assert_eq!(statics(&instance, class_ref), [PoolEntry::U8(1), PoolEntry::Char('\0')]);
     }
 
     #[test]
-    #[sequential]
-    fn test_object_inheritance() {
-        let mut class = ClassHeader::new(10, 0, 0, 2, 0);
-
-        class.set_parent_info(1);
-        class.set_this_info(0);
-
-        class.set_constant_pool_entry(0, PoolEntry::ClassInfo(ClassInfo {
-            name: 2,
-            class_ref: Some(0),
-        }));
-        class.set_constant_pool_entry(1, PoolEntry::ClassInfo(ClassInfo {
-            name: 3,
-            class_ref: None,
-        }));
-        class.set_constant_pool_entry(2, PoolEntry::String("MainBase".to_owned()));
-        class.set_constant_pool_entry(3, PoolEntry::String("Object".to_owned()));
-        class.set_constant_pool_entry(4, PoolEntry::Method(Method::Bytecode(vec![Bytecode::New(0), Bytecode::InvokeVirtual(1), Bytecode::Return].into())));
-        class.set_constant_pool_entry(5, PoolEntry::Method(Method::Native(0)));
-        class.set_constant_pool_entry(6, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(7, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![TypeInfo::Object(3)], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(8, PoolEntry::String("printRef".to_owned()));
-
-        class.set_method(0, MethodInfo {
-            flags: MethodFlags::Static,
-            name: 2,
-            type_info: 6,
-            location: 4,
-        });
-
-        class.set_method(1, MethodInfo {
-            flags: MethodFlags::Public,
-            name: 8,
-            type_info: 7,
-            location: 5,
-        });
-        
-        let parent_class = class;
-        
-        let mut class = ClassHeader::new(10, 0, 0, 2, 0);
-
-        class.set_parent_info(1);
-        class.set_this_info(0);
-
-        class.set_constant_pool_entry(0, PoolEntry::ClassInfo(ClassInfo {
-            name: 2,
-            class_ref: Some(0),
-        }));
-        class.set_constant_pool_entry(1, PoolEntry::ClassInfo(ClassInfo {
-            name: 3,
-            class_ref: None,
-        }));
-        class.set_constant_pool_entry(2, PoolEntry::String("Main".to_owned()));
-        class.set_constant_pool_entry(3, PoolEntry::String("MainBase".to_owned()));
-        class.set_constant_pool_entry(4, PoolEntry::Method(Method::Bytecode(vec![Bytecode::New(0), Bytecode::InvokeVirtual(1), Bytecode::Return].into())));
-        class.set_constant_pool_entry(5, PoolEntry::Method(Method::Foreign(1)));
-        class.set_constant_pool_entry(6, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(7, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![TypeInfo::Object(3)], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(8, PoolEntry::String("printRef".to_owned()));
-
-        class.set_method(0, MethodInfo {
-            flags: MethodFlags::Static,
-            name: 2,
-            type_info: 6,
-            location: 4,
-        });
-
-        class.set_method(1, MethodInfo {
-            flags: MethodFlags::Public,
-            name: 8,
-            type_info: 7,
-            location: 5,
-        });
-
-        let constant_pool = ConstantPoolSingleton::new();
-        let object_table = TestObjectTable::new();
-        let mut linker = Linker::new(&constant_pool, &object_table);
-
-        let (class_ref, method_index) = linker.link_classes(vec![parent_class, class], "Main", "Main");
+    fn test_field_layout() {
+        let base = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "LayoutBase"
+            .const parent_name string "cocoa.lang.Object"
+            .const flag string "flag"
+            .const total string "total"
+            .const u8_type type u8
+            .const u64_type type u64
+            .field flag u8_type
+            .field total u64_type
+        "#).unwrap();
+        let child = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "LayoutChild"
+            .const parent_name string "LayoutBase"
+            .const small string "small"
+            .const count string "count"
+            .const big string "big"
+            .const tiny string "tiny"
+            .const node string "node"
+            .const big_copy string "big_copy"
+            .const tiny_copy string "tiny_copy"
+            .const u16_type type u16
+            .const u32_type type u32
+            .const i64_type type i64
+            .const i8_type type i8
+            .const node_type type object(this)
+            .const minus_five i64 -5
+            .const three i8 3
+            .const unit_type type fn() -> unit
+            .const main string "Main"
+            .const main_code bytecode
+                New this
+                StoreLocal 0
+                LoadConstant minus_five
+                LoadLocal 0
+                SetField this 2
+                Pop
+                LoadConstant three
+                LoadLocal 0
+                SetField this 3
+                Pop
+                LoadLocal 0
+                GetField this 2
+                Pop
+                StoreStatic 5
+                LoadLocal 0
+                GetField this 3
+                Pop
+                StoreStatic 6
+                ReturnUnit
+            .end
+            .field small u16_type
+            .field count u32_type Static
+            .field big i64_type
+            .field tiny i8_type
+            .field node node_type
+            .field big_copy i64_type Static
+            .field tiny_copy i8_type Static
+            .method main unit_type main_code Public Static
+        "#).unwrap();
+
+        let instance = VmInstance::new();
+        let (mut vm, child_ref, main) = link(&instance, vec![child, base], "LayoutChild", "Main");
+        let base_ref = vm.linker.as_mut().unwrap().load_class("LayoutBase").unwrap();
+
+        // Statics take no room, the parent's fields come first and every field is aligned to its size
+        let base = instance.object_table.get_class(base_ref);
+        assert_eq!(base.field_offsets(), &[Some(0), Some(8)]);
+        assert_eq!(base.instance_size(), 16);
+        let child = instance.object_table.get_class(child_ref);
+        assert_eq!(child.field_offsets(), &[Some(16), None, Some(24), Some(32), Some(40), None, None]);
+        assert_eq!(child.instance_size(), 48);
+
+        vm.run_bootstrap(child_ref, main).unwrap();
+        assert_eq!(child.statics(), &[PoolEntry::U32(0), PoolEntry::I64(-5), PoolEntry::I8(3)]);
+    }
 
-        let mut method_table = TestMethodTable::new();
 
-        method_table.add_method(NativeMethod::Rust(print_object));
+    #[test]
+    fn test_single_object_inheritance() {
+        let base = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "InheritBase"
+            .const parent_name string "cocoa.lang.Object"
+            .const value string "value"
+            .const kind string "kind"
+            .const total string "total"
+            .const store string "store"
+            .const i32_type type i32
+            .const getter_type type fn(object(this)) -> i32
+            .const store_type type fn(object(this), i32) -> unit
+            .const one i32 1
+            .const kind_code bytecode
+                LoadConstant one
+                Return
+            .end
+            .const total_code bytecode
+                LoadLocal 0
+                InvokeVirtual 0
+                LoadLocal 0
+                GetField this 0
+                Pop
+                Add
+                Return
+            .end
+            .const store_code bytecode
+                LoadLocal 1
+                LoadLocal 0
+                SetField this 0
+                Pop
+                ReturnUnit
+            .end
+            .field value i32_type
+            .method kind getter_type kind_code Public
+            .method total getter_type total_code Public
+            .method store store_type store_code Public
+        "#).unwrap();
+        let child = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "InheritChild"
+            .const parent_name string "InheritBase"
+            .const extra string "extra"
+            .const total string "total"
+            .const through_parent string "through_parent"
+            .const parent_total string "parent_total"
+            .const is_base string "is_base"
+            .const kind string "kind"
+            .const i64_type type i64
+            .const i32_type type i32
+            .const i8_type type i8
+            .const getter_type type fn(object(parent)) -> i32
+            .const unit_type type fn() -> unit
+            .const two i32 2
+            .const forty i32 40
+            .const main string "Main"
+            .const kind_code bytecode
+                LoadConstant two
+                Return
+            .end
+            .const main_code bytecode
+                New this
+                StoreLocal 0
+                LoadConstant forty
+                LoadLocal 0
+                InvokeVirtual 2
+                LoadLocal 0
+                InvokeVirtual 1
+                StoreStatic 1
+                LoadLocal 0
+                GetParent
+                InvokeVirtual 0
+                StoreStatic 2
+                GetParent
+                InvokeVirtual 1
+                StoreStatic 3
+                LoadLocal 0
+                InstanceOf parent
+                StoreStatic 4
+                Pop
+                ReturnUnit
+            .end
+            .field extra i64_type
+            .field total i32_type Static
+            .field through_parent i32_type Static
+            .field parent_total i32_type Static
+            .field is_base i8_type Static
+            .method kind getter_type kind_code Public
+            .method main unit_type main_code Public Static
+        "#).unwrap();
+
+        let instance = VmInstance::new();
+        let (mut vm, child_ref, main) = link(&instance, vec![child, base], "InheritChild", "Main");
+        let child = instance.object_table.get_class(child_ref);
+        assert_eq!(child.field_offsets(), &[Some(8), None, None, None, None]);
+        assert_eq!(child.instance_size(), 16);
+
+        let live = instance.object_table.live_objects();
+        vm.run_bootstrap(child_ref, main).unwrap();
+        // One allocation holds the inherited field, the parent view calls the parent's kind directly but
+        // the methods it reaches still dispatch on the child
+        assert_eq!(instance.object_table.live_objects(), live + 1);
+        assert_eq!(child.statics(), &[PoolEntry::I32(42), PoolEntry::I32(1), PoolEntry::I32(42), PoolEntry::I8(0)]);
+    }
 
-        let mut vm = Machine::new(&object_table, &method_table, &constant_pool);
 
-        vm.run_bootstrap(class_ref, method_index).unwrap();
+    #[test]
+    fn test_fields_of_other_classes() {
+        let point = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "Point"
+            .const parent_name string "cocoa.lang.Object"
+            .const x string "x"
+            .const y string "y"
+            .const secret string "secret"
+            .const i32_type type i32
+            .field x i32_type Public
+            .field y i32_type Public
+            .field secret i32_type Private
+        "#).unwrap();
+        let main = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "FieldMain"
+            .const parent_name string "cocoa.lang.Object"
+            .const point_name string "Point"
+            .const point class point_name
+            .const seven i32 7
+            .const count string "count"
+            .const result string "result"
+            .const i32_type type i32
+            .const unit_type type fn() -> unit
+            .const main string "Main"
+            .const peek string "peek"
+            .const main_code bytecode
+                LoadConstant seven
+                New point
+                SetField point 1
+                GetField point 1
+                Pop
+                StoreStatic 1
+                ReturnUnit
+            .end
+            .const peek_code bytecode
+                New point
+                GetField point 2
+                Pop
+                StoreStatic 1
+                ReturnUnit
+            .end
+            .field count i32_type
+            .field result i32_type Static
+            .method main unit_type main_code Public Static
+            .method peek unit_type peek_code Public Static
+        "#).unwrap();
+
+        // FieldMain's own field 1 is static, the field operands name Point's fields instead
+        let instance = VmInstance::new();
+        let (mut vm, class_ref, main) = link(&instance, vec![main, point], "FieldMain", "Main");
+        vm.run_bootstrap(class_ref, main).unwrap();
+        assert_eq!(statics(&instance, class_ref), [PoolEntry::I32(7)]);
+
+        let error = vm.run_bootstrap(class_ref, 1).unwrap_err();
+        assert!(error.get_message().contains("private"), "{}", error.get_message());
     }
 
     #[test]
-    #[sequential]
-    fn test_interface() {
-        let mut class = ClassHeader::new(11, 1, 0, 2, 0);
-
-        class.set_parent_info(1);
-        class.set_this_info(0);
-
-        class.set_constant_pool_entry(0, PoolEntry::ClassInfo(ClassInfo {
-            name: 2,
-            class_ref: Some(0),
-        }));
-        class.set_constant_pool_entry(1, PoolEntry::ClassInfo(ClassInfo {
-            name: 3,
-            class_ref: None,
-        }));
-        class.set_constant_pool_entry(2, PoolEntry::String("Main".to_owned()));
-        class.set_constant_pool_entry(3, PoolEntry::String("Object".to_owned()));
-        class.set_constant_pool_entry(4, PoolEntry::Method(Method::Bytecode(vec![Bytecode::New(0), Bytecode::InvokeInterface(9, 0), Bytecode::Return].into())));
-        class.set_constant_pool_entry(5, PoolEntry::Method(Method::Native(0)));
-        class.set_constant_pool_entry(6, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(7, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![TypeInfo::Object(3)], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(8, PoolEntry::String("printRef".to_owned()));
-        class.set_constant_pool_entry(9, PoolEntry::ClassInfo(ClassInfo {
-            name: 10,
-            class_ref: None,
-        }));
-        class.set_constant_pool_entry(10, PoolEntry::String("PrintRef".to_owned()));
-
-        class.set_method(0, MethodInfo {
-            flags: MethodFlags::Static,
-            name: 2,
-            type_info: 6,
-            location: 4,
-        });
-
-        class.set_method(1, MethodInfo {
-            flags: MethodFlags::Public,
-            name: 8,
-            type_info: 7,
-            location: 5,
-        });
-
-        class.set_interface(0, InterfaceInfo {
-            info: 9,
-            vtable: vec![1],
-        });
-
-        let constant_pool = ConstantPoolSingleton::new();
-        let object_table = TestObjectTable::new();
-        let mut linker = Linker::new(&constant_pool, &object_table);
-
-        let (class_ref, method_index) = linker.link_classes(vec![class], "Main", "Main");
-
-        let mut method_table = TestMethodTable::new();
-
-        method_table.add_method(NativeMethod::Rust(print_object));
-
-        let mut vm = Machine::new(&object_table, &method_table, &constant_pool);
-
-        vm.run_bootstrap(class_ref, method_index).unwrap();
+    fn test_exceptions() {
+        let main = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "ThrowMain"
+            .const parent_name string "cocoa.lang.Object"
+            .const exception class exception_name
+            .const exception_name string "cocoa.lang.Exception"
+            .const error class error_name
+            .const error_name string "ThrowError"
+            .const zero i32 0
+            .const one i32 1
+            .const seven i32 7
+            .const nine i32 9
+            .const i32_type type i32
+            .const unit_type type fn() -> unit
+            .const main string "Main"
+            .const thrower string "thrower"
+            .const result string "result"
+            .const second string "second"
+            .const main_code bytecode
+                LoadConstant zero
+                LoadConstant one
+                Divide
+                StoreStatic 0
+                ReturnUnit
+                Pop
+                LoadConstant seven
+                StoreStatic 0
+                InvokeStatic this 1
+                ReturnUnit
+                Pop
+                LoadConstant nine
+                StoreStatic 1
+                ReturnUnit
+            .end
+            .const thrower_code bytecode
+                New error
+                Throw
+            .end
+            .const text string "text"
+            .string text
+            .const throw_string string "throw_string"
+            .const throw_string_code bytecode
+                NewString 0
+                Throw
+            .end
+            .field result i32_type Static
+            .field second i32_type Static
+            .method main unit_type main_code Static
+            .method thrower unit_type thrower_code Static
+            .method throw_string unit_type throw_string_code Static
+            .handler 0 0 3 5 exception
+            .handler 0 8 9 10
+        "#).unwrap();
+        let error = class_with_parent("ThrowError", "cocoa.lang.Exception", &[]);
+
+        let instance = VmInstance::new();
+        let (mut vm, class_ref, main) = link(&instance, vec![main, error], "ThrowMain", "Main");
+
+        // The division by zero is caught as the built in cocoa.lang.Exception and the thrown ThrowError by the catch-all
+        vm.run_bootstrap(class_ref, main).unwrap();
+        assert_eq!(statics(&instance, class_ref), [PoolEntry::I32(7), PoolEntry::I32(9)]);
+
+        let error = vm.run_bootstrap(class_ref, 1).unwrap_err();
+        assert_eq!(error.get_message(), "Uncaught exception ThrowError");
+
+        // Only exceptions can be thrown, a string is a class cast
+        let error = vm.run_bootstrap(class_ref, 2).unwrap_err();
+        assert_eq!(error.get_message(), "Uncaught exception cocoa.lang.ClassCastException");
     }
 
     #[test]
-    #[sequential]
-    fn test_object_instance_of() {
-        let mut class = ClassHeader::new(10, 0, 0, 2, 0);
-
-        class.set_parent_info(1);
-        class.set_this_info(0);
-
-        class.set_constant_pool_entry(0, PoolEntry::ClassInfo(ClassInfo {
-            name: 2,
-            class_ref: Some(0),
-        }));
-        class.set_constant_pool_entry(1, PoolEntry::ClassInfo(ClassInfo {
-            name: 3,
-            class_ref: None,
-        }));
-        class.set_constant_pool_entry(2, PoolEntry::String("MainBase".to_owned()));
-        class.set_constant_pool_entry(3, PoolEntry::String("Object".to_owned()));
-        class.set_constant_pool_entry(4, PoolEntry::Method(Method::Bytecode(vec![Bytecode::New(0), Bytecode::InvokeVirtual(1), Bytecode::Return].into())));
-        class.set_constant_pool_entry(5, PoolEntry::Method(Method::Native(0)));
-        class.set_constant_pool_entry(6, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(7, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![TypeInfo::Object(3)], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(8, PoolEntry::String("printRef".to_owned()));
-
-        class.set_method(0, MethodInfo {
-            flags: MethodFlags::Static,
-            name: 2,
-            type_info: 6,
-            location: 4,
-        });
-
-        class.set_method(1, MethodInfo {
-            flags: MethodFlags::Public,
-            name: 8,
-            type_info: 7,
-            location: 5,
-        });
-        
-        let parent_class = class;
-        
-        let mut class = ClassHeader::new(10, 0, 0, 2, 0);
-
-        class.set_parent_info(1);
-        class.set_this_info(0);
-
-        class.set_constant_pool_entry(0, PoolEntry::ClassInfo(ClassInfo {
-            name: 2,
-            class_ref: Some(0),
-        }));
-        class.set_constant_pool_entry(1, PoolEntry::ClassInfo(ClassInfo {
-            name: 3,
-            class_ref: None,
-        }));
-        class.set_constant_pool_entry(2, PoolEntry::String("Main".to_owned()));
-        class.set_constant_pool_entry(3, PoolEntry::String("MainBase".to_owned()));
-        class.set_constant_pool_entry(4, PoolEntry::Method(Method::Bytecode(vec![Bytecode::New(0), Bytecode::InstanceOf(0), Bytecode::IfNot(10), Bytecode::Return].into())));
-        class.set_constant_pool_entry(5, PoolEntry::Method(Method::Foreign(1)));
-        class.set_constant_pool_entry(6, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(7, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![TypeInfo::Object(3)], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(8, PoolEntry::String("printRef".to_owned()));
-
-        class.set_method(0, MethodInfo {
-            flags: MethodFlags::Static,
-            name: 2,
-            type_info: 6,
-            location: 4,
-        });
-
-        class.set_method(1, MethodInfo {
-            flags: MethodFlags::Public,
-            name: 8,
-            type_info: 7,
-            location: 5,
-        });
+    fn test_faults_are_exceptions() {
+        let main = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "FaultMain"
+            .const parent_name string "cocoa.lang.Object"
+            .const cast_name string "cocoa.lang.ClassCastException"
+            .const cast class cast_name
+            .const zero u64 0
+            .const one i32 1
+            .const i32_type type i32
+            .const unit_type type fn() -> unit
+            .const main string "Main"
+            .const caught string "caught"
+            .const main_code bytecode
+                New this
+                LoadConstant zero
+                ArrayGet Reference
+                ReturnUnit
+                Pop
+                LoadConstant one
+                StoreStatic 0
+                ReturnUnit
+            .end
+            .field caught i32_type Static
+            .method main unit_type main_code Public Static
+            .handler 0 0 3 4 cast
+        "#).unwrap();
+
+        // Indexing an object that is not an array throws an exception the program can catch
+        let instance = VmInstance::new();
+        let (mut vm, class_ref, main) = link(&instance, vec![main], "FaultMain", "Main");
+        vm.run_bootstrap(class_ref, main).unwrap();
+        assert_eq!(statics(&instance, class_ref), [PoolEntry::I32(1)]);
+    }
 
-        let constant_pool = ConstantPoolSingleton::new();
-        let object_table = TestObjectTable::new();
-        let mut linker = Linker::new(&constant_pool, &object_table);
 
-        let (class_ref, method_index) = linker.link_classes(vec![parent_class, class], "Main", "Main");
+    #[test]
+    fn test_tail_calls() {
+        let main = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "TailMain"
+            .const parent_name string "cocoa.lang.Object"
+            .const zero i32 0
+            .const one i32 1
+            .const million i32 1000000
+            .const done i32 42
+            .const i32_type type i32
+            .const unit_type type fn() -> unit
+            .const countdown_type type fn(i32) -> i32
+            .const main string "Main"
+            .const countdown string "countdown"
+            .const result string "result"
+            .const main_code bytecode
+                LoadConstant million
+                InvokeStatic this 1
+                StoreStatic 0
+                ReturnUnit
+            .end
+            .const countdown_code bytecode
+                LoadLocal 0
+                LoadConstant zero
+                Equal
+                If 5
+                LoadConstant one
+                LoadLocal 0
+                Subtract
+                InvokeStaticTail this 1
+                LoadConstant done
+                Return
+            .end
+            .field result i32_type Static
+            .method main unit_type main_code Static
+            .method countdown countdown_type countdown_code Public Static
+        "#).unwrap();
+
+        let instance = VmInstance::new();
+        let (mut vm, class_ref, main) = link(&instance, vec![main], "TailMain", "Main");
+
+        // Each tail call replaces the countdown frame, so a million of them run in constant stack space
+        vm.run_bootstrap(class_ref, main).unwrap();
+        assert_eq!(statics(&instance, class_ref), [PoolEntry::I32(42)]);
+    }
 
-        let mut method_table = TestMethodTable::new();
 
-        method_table.add_method(NativeMethod::Rust(print_object));
+    #[test]
+    fn test_limits() {
+        let main = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "LimitMain"
+            .const parent_name string "cocoa.lang.Object"
+            .const length u64 1024
+            .const zero u64 0
+            .const one u64 1
+            .const count u64 100
+            .const node_type type object(this)
+            .const bytes_type type [u8]
+            .const next string "next"
+            .const data string "data"
+            .const unit_type type fn() -> unit
+            .const spin string "spin"
+            .const recurse string "recurse"
+            .const allocate string "allocate"
+            .const arrays string "arrays"
+            .const done string "done"
+            .const churn string "churn"
+            .const spin_code bytecode
+                Goto 0
+            .end
+            .const recurse_code bytecode
+                InvokeStatic this 1
+                ReturnUnit
+            .end
+            .const allocate_code bytecode
+                New this
+                StoreLocal 0
+                LoadLocal 0
+                New this
+                SetField this 0
+                StoreLocal 0
+                Goto -4
+            .end
+            .const arrays_code bytecode
+                New this
+                StoreLocal 0
+                LoadLocal 0
+                LoadConstant length
+                NewArray U8
+                New this
+                SetField this 1
+                SetField this 0
+                StoreLocal 0
+                Goto -7
+            .end
+            .const done_code bytecode
+                ReturnUnit
+            .end
+            .const churn_code bytecode
+                LoadConstant count
+                StoreLocal 0
+                LoadLocal 0
+                LoadConstant zero
+                Equal
+                If 11
+                Pop
+                Pop
+                LoadConstant length
+                NewArray U8
+                Pop
+                LoadConstant one
+                LoadLocal 0
+                Subtract
+                StoreLocal 0
+                Goto -13
+                Pop
+                Pop
+                ReturnUnit
+            .end
+            .field next node_type
+            .field data bytes_type
+            .method spin unit_type spin_code Public Static
+            .method recurse unit_type recurse_code Public Static
+            .method allocate unit_type allocate_code Public Static
+            .method arrays unit_type arrays_code Public Static
+            .method done unit_type done_code Public Static
+            .method churn unit_type churn_code Public Static
+        "#).unwrap();
+
+        let instance = VmInstance::new();
+        let (vm, class_ref, spin) = link(&instance, vec![main], "LimitMain", "spin");
+
+        let limits = Limits {
+            fuel: Some(100_000),
+            max_call_depth: Some(100),
+            max_live_objects: Some(instance.object_table.live_objects() + 1000),
+            max_array_bytes: Some(10_000),
+        };
+        let mut vm = vm.with_limits(limits);
 
-        let mut vm = Machine::new(&object_table, &method_table, &constant_pool);
+        // Every limit stops the program with its own error and leaves the machine usable
+        for (method, limit) in [(spin, Limit::Fuel), (1, Limit::CallDepth), (3, Limit::ArrayBytes), (2, Limit::LiveObjects)] {
+            let error = vm.run_bootstrap(class_ref, method).unwrap_err();
+            assert_eq!(error.get_limit(), Some(limit), "{}", error);
+            vm.run_bootstrap(class_ref, 4).unwrap();
+        }
 
-        vm.run_bootstrap(class_ref, method_index).unwrap();
+        // Only live arrays count, so a hundred arrays that are dropped right away fit in room for nine
+        vm.run_bootstrap(class_ref, 5).unwrap();
+        vm.collect_garbage();
+        assert_eq!(instance.object_table.array_bytes(), 0);
     }
 
-    #[test]
-    #[sequential]
-    fn test_object_instance_of_inheritance() {
-        let mut class = ClassHeader::new(10, 0, 0, 2, 0);
-
-        class.set_parent_info(1);
-        class.set_this_info(0);
-
-        class.set_constant_pool_entry(0, PoolEntry::ClassInfo(ClassInfo {
-            name: 2,
-            class_ref: Some(0),
-        }));
-        class.set_constant_pool_entry(1, PoolEntry::ClassInfo(ClassInfo {
-            name: 3,
-            class_ref: None,
-        }));
-        class.set_constant_pool_entry(2, PoolEntry::String("MainBase".to_owned()));
-        class.set_constant_pool_entry(3, PoolEntry::String("Object".to_owned()));
-        class.set_constant_pool_entry(4, PoolEntry::Method(Method::Bytecode(vec![Bytecode::New(0), Bytecode::InvokeVirtual(1), Bytecode::Return].into())));
-        class.set_constant_pool_entry(5, PoolEntry::Method(Method::Native(0)));
-        class.set_constant_pool_entry(6, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(7, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![TypeInfo::Object(3)], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(8, PoolEntry::String("printRef".to_owned()));
-
-        class.set_method(0, MethodInfo {
-            flags: MethodFlags::Static,
-            name: 2,
-            type_info: 6,
-            location: 4,
-        });
 
-        class.set_method(1, MethodInfo {
-            flags: MethodFlags::Public,
-            name: 8,
-            type_info: 7,
-            location: 5,
-        });
-        
-        let parent_class = class;
-        
-        let mut class = ClassHeader::new(10, 0, 0, 2, 0);
-
-        class.set_parent_info(1);
-        class.set_this_info(0);
-
-        class.set_constant_pool_entry(0, PoolEntry::ClassInfo(ClassInfo {
-            name: 2,
-            class_ref: Some(0),
-        }));
-        class.set_constant_pool_entry(1, PoolEntry::ClassInfo(ClassInfo {
-            name: 3,
-            class_ref: None,
-        }));
-        class.set_constant_pool_entry(2, PoolEntry::String("Main".to_owned()));
-        class.set_constant_pool_entry(3, PoolEntry::String("MainBase".to_owned()));
-        class.set_constant_pool_entry(4, PoolEntry::Method(Method::Bytecode(vec![Bytecode::New(0), Bytecode::InstanceOf(1), Bytecode::IfNot(10), Bytecode::Return].into())));
-        class.set_constant_pool_entry(5, PoolEntry::Method(Method::Foreign(1)));
-        class.set_constant_pool_entry(6, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(7, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![TypeInfo::Object(3)], ret: Box::new(TypeInfo::U64) }));
-        class.set_constant_pool_entry(8, PoolEntry::String("printRef".to_owned()));
-
-        class.set_method(0, MethodInfo {
-            flags: MethodFlags::Static,
-            name: 2,
-            type_info: 6,
-            location: 4,
-        });
-
-        class.set_method(1, MethodInfo {
-            flags: MethodFlags::Public,
-            name: 8,
-            type_info: 7,
-            location: 5,
+    #[test]
+    fn test_native_arrays_count_against_limit() {
+        let mut instance = VmInstance::new();
+        instance.native_methods().register_fn("Main.make", || vec![0u8; 2000]);
+        let class = main_class("cocoa.lang.Object", None, "InvokeStatic this 1\n Pop\n ReturnUnit", r#"
+            .const make string "make"
+            .const make_type type fn() -> [u8]
+            .const make_code method native
+            .method make make_type make_code Public Static
+        "#);
+
+        let (vm, class_ref, main) = link(&instance, vec![class], "Main", "Main");
+        let mut vm = vm.with_limits(Limits {
+            max_array_bytes: Some(1000),
+            ..Limits::default()
         });
+        let error = vm.run_bootstrap(class_ref, main).unwrap_err();
+        assert_eq!(error.get_limit(), Some(Limit::ArrayBytes), "{}", error);
+    }
 
-        let constant_pool = ConstantPoolSingleton::new();
-        let object_table = TestObjectTable::new();
-        let mut linker = Linker::new(&constant_pool, &object_table);
-
-        let (class_ref, method_index) = linker.link_classes(vec![parent_class, class], "Main", "Main");
-
-        let mut method_table = TestMethodTable::new();
-
-        method_table.add_method(NativeMethod::Rust(print_object));
-
-        let mut vm = Machine::new(&object_table, &method_table, &constant_pool);
-
-        vm.run_bootstrap(class_ref, method_index).unwrap();
+    #[test]
+    fn test_typed_arrays() {
+        let base = class_with_parent("ArrayBase", "cocoa.lang.Object", &[]);
+        let child = class_with_parent("ArrayChild", "ArrayBase", &[]);
+        let class = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "ArrayMain"
+            .const parent_name string "cocoa.lang.Object"
+            .const base_name string "ArrayBase"
+            .const base class base_name
+            .const child_name string "ArrayChild"
+            .const child class child_name
+            .const array_name string "cocoa.lang.Array"
+            .const array class array_name
+            .const zero u64 0
+            .const one u64 1
+            .const base_array type [object(base)]
+            .const child_array type [object(child)]
+            .const nested_base type [[object(base)]]
+            .const nested_child type [[object(child)]]
+            .const i8_type type i8
+            .const unit_type type fn() -> unit
+            .const kept string "kept"
+            .const covariant string "covariant"
+            .const is_array string "is_array"
+            .const is_base string "is_base"
+            .const nested string "nested"
+            .const main string "Main"
+            .const bad_store string "bad_store"
+            .const main_code bytecode
+                LoadConstant one
+                NewTypedArray child_array
+                InstanceOf base_array
+                StoreStatic 1
+                InstanceOf array
+                StoreStatic 2
+                InstanceOf base
+                StoreStatic 3
+                StoreLocal 0
+                New child
+                LoadLocal 0
+                LoadConstant zero
+                ArraySet Reference
+                StoreStatic 0
+                LoadConstant one
+                NewTypedArray nested_child
+                InstanceOf nested_base
+                StoreStatic 4
+                Pop
+                ReturnUnit
+            .end
+            .const bad_store_code bytecode
+                LoadConstant one
+                NewTypedArray child_array
+                StoreLocal 0
+                New base
+                LoadLocal 0
+                LoadConstant zero
+                ArraySet Reference
+                Pop
+                ReturnUnit
+            .end
+            .field kept base_array Static
+            .field covariant i8_type Static
+            .field is_array i8_type Static
+            .field is_base i8_type Static
+            .field nested i8_type Static
+            .method main unit_type main_code Public Static
+            .method bad_store unit_type bad_store_code Public Static
+        "#).unwrap();
+
+        let instance = VmInstance::new();
+        let (mut vm, class_ref, main) = link(&instance, vec![base, child, class], "ArrayMain", "Main");
+        let object_table = &instance.object_table;
+        vm.run_bootstrap(class_ref, main).unwrap();
+
+        // An array of a child class is an array of its parent and an instance of cocoa.lang.Array, but not of the parent
+        let statics = statics(&instance, class_ref);
+        assert_eq!(statics[1..], [PoolEntry::I8(0), PoolEntry::I8(0), PoolEntry::I8(1), PoolEntry::I8(0)]);
+
+        // The array keeps the child it holds alive
+        let PoolEntry::Reference(array) = statics[0] else {
+            panic!("Expected a reference, found {:?}", statics[0]);
+        };
+        vm.collect_garbage();
+        let element = object_table.get_array(array).get_elem::<Reference>(0);
+        assert!(object_table.is_object(element));
+
+        // Arrays are instances of a linked cocoa.lang.Array, whose size is the registered native method
+        let array_class = object_table.get_class(object_table.get_array(array).get_class());
+        let size = array_class.get_method(0);
+        assert_eq!(instance.constant_pool.get_constant(size.name), PoolEntry::String(String::from("size")));
+        let size_method = NativeMethodTable::new().resolve("cocoa.lang.Array.size").unwrap();
+        assert_eq!(instance.constant_pool.get_constant(size.location), PoolEntry::Method(Method::Native(size_method)));
+
+        // A parent can't be stored in an array of its child
+        let error = vm.run_bootstrap(class_ref, 1).unwrap_err();
+        assert_eq!(error.get_message(), "Uncaught exception cocoa.lang.ArrayStoreException");
     }
 }
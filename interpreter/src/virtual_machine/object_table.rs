@@ -1,48 +1,91 @@
-use once_cell::sync::Lazy;
-use definitions::{bytecode::Type, class::{ClassHeader, ClassInfo, PoolEntry}, object::{Array, Object, ObjectTable, Reference, StringObject}};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use super::machine;
+use definitions::{bytecode::Type, class::{ClassHeader, PoolEntry, PoolIndex, TypeInfo}, object::{Array, GcMark, HeaderPtr, Object, ObjectHeader, ObjectTable, Reference, Slots, StringObject}};
 
-static OBJECT_TABLE: Lazy<ObjectTable> = Lazy::new(|| {
-    ObjectTable::new()
-});
+use super::{machine, ConstantPool};
 
-pub struct ObjectTableSingleton {}
+/// The object table of a single virtual machine instance
+pub struct InstanceObjectTable {
+    table: ObjectTable,
+    /// The class of new arrays, null until `cocoa.lang.Array` is linked
+    array_class: AtomicUsize,
+    /// The bytes held by the elements of arrays that haven't been freed
+    array_bytes: AtomicUsize,
+}
 
-impl ObjectTableSingleton {
-    pub fn get_singleton() -> Self {
-        Self {} 
+impl InstanceObjectTable {
+    pub fn new() -> Self {
+        Self {
+            table: ObjectTable::new(),
+            array_class: AtomicUsize::new(0),
+            array_bytes: AtomicUsize::new(0),
+        }
     }
 
-    fn get_object_table<'a>(&'a self) -> &'a ObjectTable {
-        &OBJECT_TABLE
+    fn get_object_table(&self) -> &ObjectTable {
+        &self.table
     }
 
-}
+    /// The entry a reference points to, a reference to a deleted entry is a bug in the machine or a native method
+    fn header(&self, reference: Reference) -> ObjectHeader {
+        match self.get_object_table().get_object(reference) {
+            Some(header) => header,
+            None if self.get_object_table().is_stale(reference) => panic!("Stale reference {:#x}", reference),
+            None => panic!("Invalid Reference"),
+        }
+    }
 
-impl machine::ObjectTable for ObjectTableSingleton {
-    fn create_object(&self, class_ref: Reference) -> Reference {
-        let class = self.get_object_table()
-            .get_object(class_ref)
-            .expect("Invalid Reference")
-            .get_class_ptr();
-
-        let parent_info_index = class.get_parent_info();
-        let parent_info = class.get_constant_pool_entry(parent_info_index);
-        let parent_reference = match parent_info {
-            PoolEntry::ClassInfo(ClassInfo {class_ref: Some(class_ref), ..}) => {
-                self.create_object(*class_ref)
+    /// The references an entry holds that keep other entries alive
+    ///
+    /// Classes are roots themselves, so the classes of objects don't have to be followed.
+    fn children(table: &Slots, header: &ObjectHeader, constant_pool: &dyn ConstantPool) -> Vec<Reference> {
+        match header.get_ptr() {
+            HeaderPtr::Object(object) => {
+                // The object holds the fields of every class up its hierarchy
+                let mut children = Vec::new();
+                let mut class_ref = Some(object.get_class());
+                while let Some(class) = class_ref.and_then(|class_ref| table.get(class_ref)).map(|class| class.get_class_ptr()) {
+                    for (field, offset) in class.fields().iter().zip(class.field_offsets()) {
+                        let Some(offset) = offset else {
+                            continue;
+                        };
+                        if let PoolEntry::TypeInfo(TypeInfo::Object(_) | TypeInfo::String | TypeInfo::Array(_)) = constant_pool.get_constant(field.type_info) {
+                            children.push(object.get_field::<Reference>(*offset));
+                        }
+                    }
+                    class_ref = class.get_parent_ref();
+                }
+                children
             }
-            PoolEntry::ClassInfo(ClassInfo {class_ref: None, ..}) => {
-                0
+            HeaderPtr::Array(array) if array.holds_references() => {
+                let mut children: Vec<Reference> = (0..array.get_size()).map(|index| array.get_elem(index)).collect();
+                children.push(array.get_parent());
+                children
             }
-            _ => panic!("Entry was not a class info"),
-        };
+            HeaderPtr::Array(array) => vec![array.get_parent()],
+            HeaderPtr::String(string) => vec![string.get_parent()],
+            HeaderPtr::Class(class) => class.statics().iter()
+                .chain(class.constants())
+                .filter_map(|entry| match entry {
+                    PoolEntry::Reference(reference) => Some(*reference),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+}
 
-        // TODO: make this not include static members
-        let field_count = class.fields_count();
+impl Default for InstanceObjectTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        let object = Object::new(parent_reference, class_ref, field_count);
+impl machine::ObjectTable for InstanceObjectTable {
+    fn create_object(&self, class_ref: Reference) -> Reference {
+        let class = self.get_class(class_ref);
+        let object = Object::new(class_ref, class.instance_size());
 
         self.get_object_table().add_object(object)
     }
@@ -52,40 +95,39 @@ impl machine::ObjectTable for ObjectTableSingleton {
     }
 
     fn get_object(&self, reference: Reference) -> Object {
-        self.get_object_table()
-            .get_object(reference)
-            .expect("Invalid Reference")
-            .get_object_ptr()
+        self.header(reference).get_object_ptr()
     }
 
     fn get_class(&self, reference: Reference) -> ClassHeader {
-        self.get_object_table()
-            .get_object(reference)
-            .expect("Invalid Reference")
-            .get_class_ptr()
+        self.header(reference).get_class_ptr()
     }
 
-    /// TODO: Add array class and create base object
     fn create_array(&self, ty: Type, length: usize) -> Reference {
-        let size = match ty {
-            Type::U8 | Type::I8 => 1,
-            Type::U16 | Type::I16 => 2,
-            Type::U32 | Type::I32 | Type::F32 => 4,
-            Type::U64 | Type::I64 | Type::F64 => 8,
-            Type::Reference => 8,
-            _ => panic!("Invalid type for array"),
+        let class_ref = self.array_class.load(Ordering::Relaxed);
+        let array = match ty {
+            Type::Reference => Array::new_references(0, class_ref, None, length),
+            Type::Char(_) => panic!("Invalid type for array"),
+            _ => Array::new(0, class_ref, ty, length),
         };
-        
-        let array = Array::new(0, 0, size, length);
+        self.array_bytes.fetch_add(array.get_elem_size() * length, Ordering::Relaxed);
+
+        self.get_object_table().add_array(array)
+    }
+
+    fn create_typed_array(&self, type_info: PoolIndex, length: usize) -> Reference {
+        let class_ref = self.array_class.load(Ordering::Relaxed);
+        let array = Array::new_references(0, class_ref, Some(type_info), length);
+        self.array_bytes.fetch_add(8 * length, Ordering::Relaxed);
 
         self.get_object_table().add_array(array)
     }
 
+    fn set_array_class(&self, class_ref: Reference) {
+        self.array_class.store(class_ref, Ordering::Relaxed);
+    }
+
     fn get_array(&self, reference: Reference) -> Array {
-        self.get_object_table()
-            .get_object(reference)
-            .expect("Invalid Reference")
-            .get_array_ptr()
+        self.header(reference).get_array_ptr()
     }
 
     // TODO create base object and add string class
@@ -95,10 +137,7 @@ impl machine::ObjectTable for ObjectTableSingleton {
         self.get_object_table().add_string(string)
     }
     fn get_string(&self, reference: Reference) -> StringObject {
-        self.get_object_table()
-            .get_object(reference)
-            .expect("Invalid Reference")
-            .get_string_ptr()
+        self.header(reference).get_string_ptr()
     }
     fn is_object(&self, reference: Reference) -> bool {
         self.get_object_table().is_object(reference)
@@ -112,4 +151,169 @@ impl machine::ObjectTable for ObjectTableSingleton {
     fn is_string(&self, reference: Reference) -> bool {
         self.get_object_table().is_string(reference)
     }
+    fn is_stale(&self, reference: Reference) -> bool {
+        self.get_object_table().is_stale(reference)
+    }
+    fn live_objects(&self) -> usize {
+        self.get_object_table().live_count()
+    }
+    fn array_bytes(&self) -> usize {
+        self.array_bytes.load(Ordering::Relaxed)
+    }
+
+    /// A tri-color mark and sweep
+    ///
+    /// Every entry starts out white, roots and classes are shaded gray, and gray entries turn black once
+    /// everything they refer to is gray. Entries that are still white at the end are unreachable.
+    fn collect_garbage(&self, roots: &[Reference], constant_pool: &dyn ConstantPool) -> usize {
+        let mut table = self.get_object_table().get_table_mut();
+        let mut gray = Vec::new();
+        for (reference, header) in table.iter_mut() {
+            if header.is_class() {
+                header.set_mark(GcMark::Gray);
+                gray.push(reference);
+            } else {
+                header.set_mark(GcMark::White);
+            }
+        }
+
+        let shade = |table: &mut Slots, gray: &mut Vec<Reference>, reference: Reference| {
+            if let Some(header) = table.get_mut(reference) {
+                if header.get_mark() == GcMark::White {
+                    header.set_mark(GcMark::Gray);
+                    gray.push(reference);
+                }
+            }
+        };
+        for root in roots {
+            shade(&mut table, &mut gray, *root);
+        }
+        while let Some(reference) = gray.pop() {
+            let header = table.get(reference).cloned().expect("Gray entries are alive");
+            for child in Self::children(&table, &header, constant_pool) {
+                shade(&mut table, &mut gray, child);
+            }
+            if let Some(header) = table.get_mut(reference) {
+                header.set_mark(GcMark::Black);
+            }
+        }
+
+        let garbage: Vec<Reference> = table.iter()
+            .filter(|(_, header)| header.get_mark() == GcMark::White)
+            .map(|(reference, _)| reference)
+            .collect();
+        let freed_bytes: usize = garbage.iter()
+            .filter_map(|reference| table.get(*reference))
+            .filter(|header| header.is_array())
+            .map(|header| {
+                let array = header.get_array_ptr();
+                array.get_elem_size() * array.get_size()
+            })
+            .sum();
+        drop(table);
+        self.array_bytes.fetch_sub(freed_bytes, Ordering::Relaxed);
+        for reference in &garbage {
+            self.get_object_table().delete_object(*reference);
+        }
+        garbage.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use definitions::{assembler::assemble, bytecode::Type, class::PoolEntry, object::Reference};
+
+    use crate::virtual_machine::{fixtures::link, ObjectTable, VmInstance};
+
+    #[test]
+    fn test_garbage_collection() {
+        let class = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "GcNode"
+            .const parent_name string "cocoa.lang.Object"
+            .const zero u64 0
+            .const one u64 1
+            .const count u64 3000
+            .const node_type type object(this)
+            .const unit_type type fn() -> unit
+            .const next string "next"
+            .const head string "head"
+            .const kept string "kept"
+            .const main string "Main"
+            .const churn string "churn"
+            .const main_code bytecode
+                New this
+                New this
+                SetField this 0
+                StoreStatic 1
+                New this
+                Pop
+                ReturnUnit
+            .end
+            .const churn_code bytecode
+                New this
+                StoreLocal 0
+                LoadConstant count
+                StoreLocal 1
+                LoadLocal 1
+                LoadConstant zero
+                Equal
+                If 10
+                Pop
+                Pop
+                New this
+                Pop
+                LoadConstant one
+                LoadLocal 1
+                Subtract
+                StoreLocal 1
+                Goto -12
+                Pop
+                Pop
+                LoadLocal 0
+                StoreStatic 2
+                ReturnUnit
+            .end
+            .field next node_type
+            .field head node_type Static
+            .field kept node_type Static
+            .method main unit_type main_code Public Static
+            .method churn unit_type churn_code Public Static
+        "#).unwrap();
+
+        let instance = VmInstance::new();
+        let (mut vm, class_ref, main) = link(&instance, vec![class], "GcNode", "Main");
+        let object_table = &instance.object_table;
+        let static_ref = |index: usize| match object_table.get_class(class_ref).statics()[index] {
+            PoolEntry::Reference(reference) => reference,
+            ref x => panic!("Expected a reference, found {:?}", x),
+        };
+
+        // The object that was popped is garbage, the one in head and the one it points to are not
+        vm.run_bootstrap(class_ref, main).unwrap();
+        assert_eq!(object_table.live_objects(), 4);
+        assert_eq!(vm.collect_garbage(), 1);
+        let head = static_ref(0);
+        let offset = object_table.get_class(class_ref).get_field_offset(0).unwrap();
+        let next = object_table.get_object(head).get_field::<Reference>(offset);
+        assert!(object_table.is_object(head) && object_table.is_object(next));
+
+        // Allocating past the threshold collects, the object in a local survives every collection
+        vm.run_bootstrap(class_ref, 1).unwrap();
+        assert!(object_table.live_objects() < 2 * 1024);
+        vm.collect_garbage();
+        assert_eq!(object_table.live_objects(), 4);
+        assert!(object_table.is_object(static_ref(1)));
+
+        // Arrays of references keep their elements alive
+        let array = object_table.create_array(Type::Reference, 2);
+        let string = object_table.create_string(String::from("element"));
+        object_table.get_array(array).set_elem(1, string);
+        assert_eq!(object_table.collect_garbage(&[array], &instance.constant_pool), 0);
+        assert!(object_table.is_string(string));
+        assert_eq!(object_table.collect_garbage(&[], &instance.constant_pool), 2);
+        assert!(!object_table.is_array(array) && !object_table.is_string(string));
+    }
 }
@@ -0,0 +1,336 @@
+use std::{collections::HashMap, mem::Discriminant};
+
+use definitions::{bytecode::Bytecode, object::Reference, stack::Stack};
+
+use super::{debugger::{class_name, method_name}, ConstantPool, ObjectTable};
+
+
+/// What the profiler collected for one method
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MethodProfile {
+    /// The method as `class.method`
+    pub name: String,
+    /// How many frames were created for the method, tail calls included
+    pub invocations: u64,
+    /// Instructions executed by the method and everything it called
+    ///
+    /// Recursive calls are only counted once, by the outermost frame of the method.
+    pub inclusive: u64,
+    /// Instructions executed by the method itself
+    pub exclusive: u64,
+}
+
+struct ProfiledFrame {
+    method: usize,
+    /// The instruction count when the frame was entered
+    entered_at: u64,
+    /// The call path that leads to this frame
+    path: usize,
+}
+
+/// Counts calls and executed instructions per method
+///
+/// The profiler keeps its own copy of the call stack, which it brings up to date with the machine's
+/// stack before every instruction. A frame that appeared is a call, a frame that disappeared is a return
+/// and a frame that changed method in place is a tail call.
+pub struct Profiler {
+    methods: Vec<MethodProfile>,
+    method_ids: HashMap<(Reference, usize), usize>,
+    /// Calls from one method to another and how often they happened
+    edges: HashMap<(usize, usize), u64>,
+    /// Executed instructions by opcode, with the opcode's name
+    opcodes: HashMap<Discriminant<Bytecode>, (String, u64)>,
+    frames: Vec<ProfiledFrame>,
+    /// Every call path seen as its parent path and its innermost method
+    paths: Vec<(Option<usize>, usize)>,
+    path_ids: HashMap<(Option<usize>, usize), usize>,
+    /// Executed instructions by the call path they were executed in
+    path_counts: Vec<u64>,
+    instructions: u64,
+    /// The depth of a frame that just made a tail call, which may have replaced it with a frame of the same method
+    tail_call: Option<usize>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            methods: Vec::new(),
+            method_ids: HashMap::new(),
+            edges: HashMap::new(),
+            opcodes: HashMap::new(),
+            frames: Vec::new(),
+            paths: Vec::new(),
+            path_ids: HashMap::new(),
+            path_counts: Vec::new(),
+            instructions: 0,
+            tail_call: None,
+        }
+    }
+
+    /// Called by the machine before it executes the instruction at the top frame's pc
+    pub(crate) fn before_instruction(&mut self, instruction: Bytecode, stack: &Stack, object_table: &dyn ObjectTable, constant_pool: &dyn ConstantPool) {
+        self.sync(stack, object_table, constant_pool);
+        let Some(frame) = self.frames.last() else {
+            return;
+        };
+        self.instructions += 1;
+        self.methods[frame.method].exclusive += 1;
+        self.path_counts[frame.path] += 1;
+        let (_, count) = self.opcodes.entry(std::mem::discriminant(&instruction)).or_insert_with(|| {
+            let name = format!("{:?}", instruction);
+            let name = name.split('(').next().unwrap_or_default().to_owned();
+            (name, 0)
+        });
+        *count += 1;
+
+        if matches!(instruction, Bytecode::InvokeStaticTail(..) | Bytecode::InvokeVirtualTail(..) | Bytecode::InvokeInterfaceTail(..) | Bytecode::InvokeInterfaceStaticTail(..)) {
+            self.tail_call = Some(stack.depth());
+        }
+    }
+
+    /// Brings the profiler's call stack up to date with the machine's
+    pub(crate) fn sync(&mut self, stack: &Stack, object_table: &dyn ObjectTable, constant_pool: &dyn ConstantPool) {
+        let depth = stack.depth();
+        while self.frames.len() > depth {
+            self.pop();
+        }
+        let tail_call = match self.tail_call {
+            Some(tail_depth) if tail_depth == depth => {
+                self.tail_call = None;
+                true
+            }
+            Some(tail_depth) if tail_depth > depth => {
+                // The tail call went to a native method, which returned from the frame
+                self.tail_call = None;
+                false
+            }
+            _ => false,
+        };
+        if depth == 0 {
+            return;
+        }
+
+        let key = (stack.get_class_index(), stack.get_current_method_index());
+        let method = match self.method_ids.get(&key) {
+            Some(method) => *method,
+            None => {
+                let name = format!("{}.{}",
+                    class_name(object_table, constant_pool, key.0),
+                    method_name(object_table, constant_pool, key.0, key.1));
+                self.methods.push(MethodProfile {
+                    name,
+                    ..MethodProfile::default()
+                });
+                self.method_ids.insert(key, self.methods.len() - 1);
+                self.methods.len() - 1
+            }
+        };
+
+        let caller = self.frames.last().map(|frame| frame.method);
+        if self.frames.len() == depth {
+            if caller == Some(method) && !tail_call {
+                return;
+            }
+            // The frame was reused by a tail call
+            self.pop();
+        }
+
+        if let Some(caller) = caller {
+            *self.edges.entry((caller, method)).or_insert(0) += 1;
+        }
+        self.methods[method].invocations += 1;
+
+        let parent = self.frames.last().map(|frame| frame.path);
+        let path = *self.path_ids.entry((parent, method)).or_insert_with(|| {
+            self.paths.push((parent, method));
+            self.path_counts.push(0);
+            self.paths.len() - 1
+        });
+        self.frames.push(ProfiledFrame {
+            method,
+            entered_at: self.instructions,
+            path,
+        });
+    }
+
+    fn pop(&mut self) {
+        let frame = self.frames.pop().expect("Profiler stack underflow");
+        if self.frames.iter().all(|outer| outer.method != frame.method) {
+            self.methods[frame.method].inclusive += self.instructions - frame.entered_at;
+        }
+    }
+
+    /// The total number of instructions executed
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Every method that ran, hottest first by exclusive instructions
+    pub fn methods(&self) -> Vec<&MethodProfile> {
+        let mut methods: Vec<&MethodProfile> = self.methods.iter().collect();
+        methods.sort_by(|a, b| b.exclusive.cmp(&a.exclusive).then_with(|| a.name.cmp(&b.name)));
+        methods
+    }
+
+    /// Every caller, callee and how often the call happened, most frequent first
+    pub fn calls(&self) -> Vec<(&str, &str, u64)> {
+        let mut calls: Vec<_> = self.edges.iter()
+            .map(|((caller, callee), count)| (self.methods[*caller].name.as_str(), self.methods[*callee].name.as_str(), *count))
+            .collect();
+        calls.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| (a.0, a.1).cmp(&(b.0, b.1))));
+        calls
+    }
+
+    /// Every opcode that ran and how often, most frequent first
+    pub fn opcodes(&self) -> Vec<(&str, u64)> {
+        let mut opcodes: Vec<_> = self.opcodes.values()
+            .map(|(opcode, count)| (opcode.as_str(), *count))
+            .collect();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        opcodes
+    }
+
+    /// A human readable summary of the methods, calls and opcodes
+    pub fn report(&self) -> String {
+        let mut report = format!("{} instructions executed\n\n", self.instructions());
+
+        report.push_str(&format!("{:<40} {:>12} {:>12} {:>12}\n", "method", "calls", "inclusive", "exclusive"));
+        for method in self.methods() {
+            report.push_str(&format!("{:<40} {:>12} {:>12} {:>12}\n", method.name, method.invocations, method.inclusive, method.exclusive));
+        }
+
+        report.push_str("\ncalls\n");
+        for (caller, callee, count) in self.calls() {
+            report.push_str(&format!("{} -> {} {}\n", caller, callee, count));
+        }
+
+        report.push_str("\nopcodes\n");
+        for (opcode, count) in self.opcodes() {
+            report.push_str(&format!("{:<20} {:>12}\n", opcode, count));
+        }
+        report
+    }
+
+    /// One line per call path with the instructions executed in it, as read by flame graph tools
+    pub fn collapsed_stacks(&self) -> String {
+        let mut lines = Vec::new();
+        for (path, count) in self.path_counts.iter().enumerate() {
+            if *count == 0 {
+                continue;
+            }
+            let mut names = Vec::new();
+            let mut current = Some(path);
+            while let Some(path) = current {
+                let (parent, method) = self.paths[path];
+                names.push(self.methods[method].name.as_str());
+                current = parent;
+            }
+            names.reverse();
+            lines.push(format!("{} {}", names.join(";"), count));
+        }
+        lines.sort();
+        let mut output = lines.join("\n");
+        output.push('\n');
+        output
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use definitions::assembler::assemble;
+
+    use crate::virtual_machine::{InstanceConstantPool, Linker, Machine, NativeMethodTable, InstanceObjectTable};
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let class = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "ProfileMain"
+            .const parent_name string "cocoa.lang.Object"
+            .const zero i32 0
+            .const one i32 1
+            .const three i32 3
+            .const i32_type type i32
+            .const unit_type type fn() -> unit
+            .const count_type type fn(i32) -> i32
+            .const main string "Main"
+            .const count string "count"
+            .const double string "double"
+            .const main_code bytecode
+                LoadConstant three
+                InvokeStatic this 1
+                Pop
+                LoadConstant one
+                InvokeStatic this 2
+                Pop
+                ReturnUnit
+            .end
+            .const count_code bytecode
+                LoadLocal 0
+                LoadConstant zero
+                Equal
+                If 5
+                LoadConstant one
+                LoadLocal 0
+                Subtract
+                InvokeStaticTail this 1
+                LoadConstant zero
+                Return
+            .end
+            .const double_code bytecode
+                LoadLocal 0
+                LoadLocal 0
+                Add
+                Return
+            .end
+            .method main unit_type main_code Public Static
+            .method count count_type count_code Public Static
+            .method double count_type double_code Public Static
+        "#).unwrap();
+
+        let constant_pool = InstanceConstantPool::new();
+        let object_table = InstanceObjectTable::new();
+        let method_table = NativeMethodTable::new();
+        let mut linker = Linker::new(&constant_pool, &object_table);
+        let (class_ref, main) = linker.link_classes(vec![class], "ProfileMain", "Main").unwrap();
+
+        let mut vm = Machine::new(&object_table, &method_table, &constant_pool).with_linker(linker).with_profiler(Profiler::new());
+        vm.run_bootstrap(class_ref, main).unwrap();
+        let profiler = vm.profiler().unwrap();
+
+        // count runs 8 instructions for 3, 2 and 1 and 6 for 0
+        assert_eq!(profiler.instructions(), 7 + 3 * 8 + 6 + 4);
+        let methods: Vec<_> = profiler.methods().into_iter()
+            .map(|method| (method.name.as_str(), method.invocations, method.inclusive, method.exclusive))
+            .collect();
+        assert_eq!(methods, vec![
+            ("ProfileMain.count", 4, 30, 30),
+            ("ProfileMain.Main", 1, 41, 7),
+            ("ProfileMain.double", 1, 4, 4),
+        ]);
+        assert_eq!(profiler.calls(), vec![
+            ("ProfileMain.count", "ProfileMain.count", 3),
+            ("ProfileMain.Main", "ProfileMain.count", 1),
+            ("ProfileMain.Main", "ProfileMain.double", 1),
+        ]);
+        let opcodes = profiler.opcodes();
+        assert!(opcodes.contains(&("LoadLocal", 3 * 2 + 1 + 2)));
+        assert!(opcodes.contains(&("InvokeStaticTail", 3)));
+
+        assert_eq!(profiler.collapsed_stacks(), "\
+ProfileMain.Main 7
+ProfileMain.Main;ProfileMain.count 30
+ProfileMain.Main;ProfileMain.double 4
+");
+        assert!(profiler.report().starts_with("41 instructions executed\n"));
+    }
+}
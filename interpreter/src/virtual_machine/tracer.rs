@@ -0,0 +1,213 @@
+use std::{collections::HashMap, io::Write, path::Path};
+
+use definitions::{bytecode::Bytecode, object::Reference, stack::Stack, CocoaResult, ErrorInfo};
+
+use super::{debugger::{class_name, format_value, method_name}, ConstantPool, ObjectTable};
+
+
+/// Limits tracing to a class, a method name or both
+///
+/// `None` matches anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// The fully qualified name of the class
+    pub class: Option<String>,
+    pub method: Option<String>,
+}
+
+impl TraceFilter {
+    /// Parses `class`, `class:method` or `:method`
+    pub fn parse(spec: &str) -> Self {
+        let (class, method) = match spec.rsplit_once(':') {
+            Some((class, method)) => (class, Some(method)),
+            None => (spec, None),
+        };
+        Self {
+            class: (!class.is_empty()).then(|| class.to_owned()),
+            method: method.filter(|method| !method.is_empty()).map(str::to_owned),
+        }
+    }
+
+    fn matches(&self, class: &str, method: &str) -> bool {
+        self.class.as_deref().is_none_or(|filter| filter == class)
+            && self.method.as_deref().is_none_or(|filter| filter == method)
+    }
+}
+
+/// Where an instruction was executed
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TracePoint {
+    class_ref: Reference,
+    method_index: usize,
+    pc: usize,
+    depth: usize,
+}
+
+/// Writes a line for every executed instruction
+///
+/// Each line has the class, method, pc and instruction followed by the operand stack of the frame
+/// that executed it, or of its caller if the instruction returned.
+pub struct Tracer {
+    output: Box<dyn Write>,
+    filters: Vec<TraceFilter>,
+    /// The `class.method` prefix of each traced method, or `None` if the filters leave it out
+    methods: HashMap<(Reference, usize), Option<String>>,
+}
+
+impl Tracer {
+    pub fn new(output: Box<dyn Write>) -> Self {
+        Self {
+            output,
+            filters: Vec::new(),
+            methods: HashMap::new(),
+        }
+    }
+
+    pub fn stderr() -> Self {
+        Self::new(Box::new(std::io::stderr()))
+    }
+
+    pub fn to_file(path: impl AsRef<Path>) -> CocoaResult<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::create(path)
+            .map_err(|error| ErrorInfo::new(format!("Failed to create {}: {}", path.display(), error)))?;
+        Ok(Self::new(Box::new(std::io::BufWriter::new(file))))
+    }
+
+    /// Only traces instructions that match at least one of the filters added
+    pub fn with_filter(mut self, filter: TraceFilter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Called by the machine before it executes an instruction, returns `None` if it shouldn't be traced
+    pub(crate) fn before_instruction(&mut self, stack: &Stack, object_table: &dyn ObjectTable, constant_pool: &dyn ConstantPool) -> Option<TracePoint> {
+        let class_ref = stack.get_class_index();
+        let method_index = stack.get_current_method_index();
+        let filters = &self.filters;
+        let traced = self.methods.entry((class_ref, method_index)).or_insert_with(|| {
+            let class = class_name(object_table, constant_pool, class_ref);
+            let method = method_name(object_table, constant_pool, class_ref, method_index);
+            if filters.is_empty() || filters.iter().any(|filter| filter.matches(&class, &method)) {
+                Some(format!("{}.{}", class, method))
+            } else {
+                None
+            }
+        });
+        traced.as_ref()?;
+        Some(TracePoint {
+            class_ref,
+            method_index,
+            pc: stack.get_current_pc(),
+            depth: stack.depth(),
+        })
+    }
+
+    /// Called by the machine after it executed the instruction at `point`
+    pub(crate) fn after_instruction(&mut self, point: TracePoint, instruction: Bytecode, stack: &Stack) -> CocoaResult<()> {
+        let prefix = self.methods.get(&(point.class_ref, point.method_index))
+            .and_then(Option::as_deref)
+            .unwrap_or_default();
+        let operands = point.depth.min(stack.depth()).checked_sub(1)
+            .and_then(|index| stack.frame(index))
+            .map(|frame| frame.operands.iter().map(|(ty, bits)| format_value(*ty, *bits)).collect::<Vec<_>>())
+            .unwrap_or_default();
+        writeln!(self.output, "{} pc {}: {:?} [{}]", prefix, point.pc, instruction, operands.join(", "))
+            .map_err(|error| ErrorInfo::new(format!("Failed to write trace: {}", error)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use definitions::assembler::assemble;
+
+    use super::*;
+    use crate::virtual_machine::{InstanceConstantPool, Linker, Machine, NativeMethodTable, InstanceObjectTable};
+
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn trace(name: &str, filter: Option<TraceFilter>) -> String {
+        let class = assemble(&format!(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "{}"
+            .const parent_name string "cocoa.lang.Object"
+            .const two i32 2
+            .const i32_type type i32
+            .const unit_type type fn() -> unit
+            .const double_type type fn(i32) -> i32
+            .const main string "Main"
+            .const double string "double"
+            .const result string "result"
+            .const main_code bytecode
+                LoadConstant two
+                InvokeStatic this 1
+                StoreStatic 0
+                ReturnUnit
+            .end
+            .const double_code bytecode
+                LoadLocal 0
+                LoadLocal 0
+                Add
+                Return
+            .end
+            .field result i32_type Static
+            .method main unit_type main_code Public Static
+            .method double double_type double_code Public Static
+        "#, name)).unwrap();
+
+        let constant_pool = InstanceConstantPool::new();
+        let object_table = InstanceObjectTable::new();
+        let method_table = NativeMethodTable::new();
+        let mut linker = Linker::new(&constant_pool, &object_table);
+        let (class_ref, main) = linker.link_classes(vec![class], name, "Main").unwrap();
+
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut tracer = Tracer::new(Box::new(SharedOutput(output.clone())));
+        if let Some(filter) = filter {
+            tracer = tracer.with_filter(filter);
+        }
+        let mut vm = Machine::new(&object_table, &method_table, &constant_pool).with_linker(linker).with_tracer(tracer);
+        vm.run_bootstrap(class_ref, main).unwrap();
+
+        let output = output.borrow().clone();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_trace() {
+        assert_eq!(trace("TraceAll", None), "\
+TraceAll.Main pc 0: LoadConstant(4) [2i32]
+TraceAll.Main pc 1: InvokeStatic(0, 1) []
+TraceAll.double pc 0: LoadLocal(0) [2i32]
+TraceAll.double pc 1: LoadLocal(0) [2i32, 2i32]
+TraceAll.double pc 2: Add [4i32]
+TraceAll.double pc 3: Return [4i32]
+TraceAll.Main pc 2: StoreStatic(0) []
+TraceAll.Main pc 3: ReturnUnit []
+");
+    }
+
+    #[test]
+    fn test_trace_filter() {
+        let output = trace("TraceFiltered", Some(TraceFilter::parse("TraceFiltered:double")));
+        assert_eq!(output.lines().count(), 4);
+        assert!(output.lines().all(|line| line.starts_with("TraceFiltered.double ")));
+
+        assert_eq!(TraceFilter::parse("cocoa.lang.Object"), TraceFilter { class: Some(String::from("cocoa.lang.Object")), method: None });
+        assert_eq!(TraceFilter::parse(":getClass"), TraceFilter { class: None, method: Some(String::from("getClass")) });
+    }
+}
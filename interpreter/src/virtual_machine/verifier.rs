@@ -0,0 +1,1139 @@
+use std::collections::{HashMap, VecDeque};
+
+use definitions::{bytecode::{Bytecode, Type}, class::{ClassHeader, FieldFlags, Method, PoolEntry, PoolIndex, TypeInfo}};
+
+use super::machine::ARRAY_CLASS;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyErrorKind {
+    /// The method's type info is not a `TypeInfo::Method`
+    NotAMethodType,
+    /// The method has no instructions
+    EmptyMethod,
+    /// Execution can run past the last instruction without returning
+    FallsOffEnd,
+    InvalidPoolIndex(PoolIndex),
+    WrongPoolEntry {
+        index: PoolIndex,
+        expected: &'static str,
+        found: &'static str,
+    },
+    InvalidMethodIndex(usize),
+    InvalidFieldIndex(usize),
+    InvalidStringIndex(usize),
+    /// A referenced class was not among the classes being verified
+    UnknownClass(String),
+    /// A type that can't be held on the operand stack, such as `Unit` as an argument
+    UnsupportedType(TypeInfo),
+    StackUnderflow,
+    TypeMismatch {
+        expected: Type,
+        found: Type,
+    },
+    ExpectedNumeric(Type),
+    ExpectedInteger(Type),
+    UndefinedLocal(u8),
+    JumpOutOfBounds(isize),
+    /// Two control flow paths reach the same instruction with different operand stacks
+    InconsistentStack {
+        expected: Vec<Type>,
+        found: Vec<Type>,
+    },
+    ReturnTypeMismatch {
+        expected: TypeInfo,
+        found: Option<Type>,
+    },
+    StoreToNonStatic(usize),
+    /// A field is accessed on an object whose class doesn't declare or inherit it
+    ReceiverMismatch {
+        expected: String,
+        found: String,
+    },
+    /// A virtual method is called on a value whose class isn't tracked, such as null
+    UnknownReceiver(usize),
+    /// An exception handler's range or handler pc is outside of the method's code
+    InvalidHandler {
+        start: usize,
+        end: usize,
+        handler: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyError {
+    pub class: String,
+    pub method: String,
+    pub pc: usize,
+    pub kind: VerifyErrorKind,
+}
+
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{} at pc {}: {:?}", self.class, self.method, self.pc, self.kind)
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Checks `Method::Bytecode` bodies of unlinked classes before they are handed to the linker
+///
+/// Each method is abstractly interpreted over the same `Type` tags that `StackFrame` tracks, along with
+/// the class of each reference where it is known. Virtual calls are checked against the vtable of the
+/// receiver's class, so a call on a receiver whose class isn't tracked is rejected since what it pops and
+/// pushes is unknown.
+pub struct Verifier {
+    classes: HashMap<String, ClassHeader>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct State {
+    stack: Vec<Type>,
+    locals: Vec<Option<Type>>,
+    /// The class of each value on the stack, `None` for values that aren't objects of a known class
+    classes: Vec<Option<String>>,
+    /// The class of each local, like `classes`
+    local_classes: Vec<Option<String>>,
+}
+
+impl State {
+    /// Merges `other` into `self`, returning true if `self` changed
+    ///
+    /// `join` names the closest class two classes share.
+    fn merge(&mut self, other: &State, join: impl Fn(&str, &str) -> Option<String>) -> Result<bool, VerifyErrorKind> {
+        if self.stack != other.stack {
+            return Err(VerifyErrorKind::InconsistentStack {
+                expected: self.stack.clone(),
+                found: other.stack.clone(),
+            });
+        }
+        let mut changed = false;
+        for (local, other) in self.locals.iter_mut().zip(other.locals.iter()) {
+            if local.is_some() && local != other {
+                *local = None;
+                changed = true;
+            }
+        }
+        // A reference that can be of different classes on different paths is of the class they share
+        for (class, other) in self.classes.iter_mut().chain(self.local_classes.iter_mut()).zip(other.classes.iter().chain(other.local_classes.iter())) {
+            if class.is_some() && class != other {
+                let joined = match (class.as_deref(), other.as_deref()) {
+                    (Some(class), Some(other)) => join(class, other),
+                    _ => None,
+                };
+                if *class != joined {
+                    *class = joined;
+                    changed = true;
+                }
+            }
+        }
+        Ok(changed)
+    }
+}
+
+struct MethodContext<'a> {
+    class: ClassHeader,
+    method: usize,
+    args: &'a [TypeInfo],
+    ret: &'a TypeInfo,
+    code: &'a [Bytecode],
+}
+
+impl Verifier {
+    pub fn new(classes: &[ClassHeader]) -> Self {
+        let mut map = HashMap::new();
+        for class in classes {
+            if let Some(name) = class_name(class) {
+                map.insert(name, *class);
+            }
+        }
+        Self {
+            classes: map,
+        }
+    }
+
+    /// The names of the classes that code in the class invokes, creates or accesses the fields of
+    ///
+    /// These have to be known to verify the class, other classes it refers to are only checked against if they are.
+    pub fn required_classes(class: &ClassHeader) -> Vec<String> {
+        use Bytecode as B;
+        let mut names = Vec::new();
+        for method_info in class.methods() {
+            let Some(PoolEntry::Method(Method::Bytecode(code))) = class.constants().get(method_info.location) else {
+                continue;
+            };
+            for bytecode in code {
+                let indices = match *bytecode {
+                    B::InvokeStatic(class_index, _) | B::InvokeStaticTail(class_index, _)
+                    | B::InvokeInterface(class_index, _) | B::InvokeInterfaceTail(class_index, _)
+                    | B::New(class_index) | B::GetField(class_index, _) | B::SetField(class_index, _) => vec![class_index],
+                    B::InvokeInterfaceStatic(class_index, interface_index, _)
+                    | B::InvokeInterfaceStaticTail(class_index, interface_index, _) => vec![class_index, interface_index],
+                    _ => continue,
+                };
+                for name in indices.into_iter().filter_map(|index| expect_class_info(class, index).ok()) {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+        }
+        names
+    }
+
+    pub fn verify_classes(&self, classes: &[ClassHeader]) -> Result<(), VerifyError> {
+        for class in classes {
+            self.verify_class(class)?;
+        }
+        Ok(())
+    }
+
+    pub fn verify_class(&self, class: &ClassHeader) -> Result<(), VerifyError> {
+        for method_index in 0..class.methods_count() {
+            self.verify_method(class, method_index)?;
+        }
+        Ok(())
+    }
+
+    pub fn verify_method(&self, class: &ClassHeader, method_index: usize) -> Result<(), VerifyError> {
+        let method_info = class.get_method(method_index);
+        let error = |pc: usize, kind: VerifyErrorKind| VerifyError {
+            class: class_name(class).unwrap_or_else(|| String::from("<unknown>")),
+            method: string_at(class, method_info.name).unwrap_or_else(|| format!("<method {}>", method_index)),
+            pc,
+            kind,
+        };
+
+        let code = match pool_entry(class, method_info.location).map_err(|kind| error(0, kind))? {
+            PoolEntry::Method(Method::Bytecode(code)) => code,
+            PoolEntry::Method(_) => return Ok(()),
+            x => return Err(error(0, VerifyErrorKind::WrongPoolEntry {
+                index: method_info.location,
+                expected: "Method",
+                found: x.kind(),
+            })),
+        };
+        let (args, ret) = match pool_entry(class, method_info.type_info).map_err(|kind| error(0, kind))? {
+            PoolEntry::TypeInfo(TypeInfo::Method { args, ret }) => (args, ret),
+            _ => return Err(error(0, VerifyErrorKind::NotAMethodType)),
+        };
+
+        let context = MethodContext {
+            class: *class,
+            method: method_index,
+            args,
+            ret,
+            code,
+        };
+        self.verify_code(&context).map_err(|(pc, kind)| error(pc, kind))
+    }
+
+    fn verify_code(&self, context: &MethodContext) -> Result<(), (usize, VerifyErrorKind)> {
+        if context.code.is_empty() {
+            return Err((0, VerifyErrorKind::EmptyMethod));
+        }
+
+        let mut locals = vec![None; u8::MAX as usize + 1];
+        let mut local_classes = vec![None; u8::MAX as usize + 1];
+        for (i, arg) in context.args.iter().enumerate() {
+            let ty = stack_type(arg).ok_or_else(|| (0, VerifyErrorKind::UnsupportedType(arg.clone())))?;
+            locals[i] = Some(ty);
+            local_classes[i] = object_class(&context.class, arg);
+        }
+
+        let handlers = context.class.exception_handlers().iter()
+            .filter(|handler| handler.method == context.method)
+            .collect::<Vec<_>>();
+        for handler in handlers.iter() {
+            if handler.start >= handler.end || handler.end > context.code.len() || handler.handler >= context.code.len() {
+                return Err((handler.start, VerifyErrorKind::InvalidHandler {
+                    start: handler.start,
+                    end: handler.end,
+                    handler: handler.handler,
+                }));
+            }
+            if let Some(catch) = handler.catch {
+                expect_class_info(&context.class, catch).map_err(|kind| (handler.handler, kind))?;
+            }
+        }
+
+        let mut states: Vec<Option<State>> = vec![None; context.code.len()];
+        states[0] = Some(State { stack: Vec::new(), locals, classes: Vec::new(), local_classes });
+        let mut worklist = VecDeque::from([0]);
+
+        while let Some(pc) = worklist.pop_front() {
+            let mut state = states[pc].clone().expect("Queued instruction has a state");
+            // A handler starts with the locals as they were before the throwing instruction and only the exception on the stack
+            let mut targets = handlers.iter()
+                .filter(|handler| handler.start <= pc && pc < handler.end)
+                .map(|handler| (handler.handler, State {
+                    stack: vec![Type::Reference],
+                    locals: state.locals.clone(),
+                    classes: vec![handler.catch.and_then(|catch| expect_class_info(&context.class, catch).ok())],
+                    local_classes: state.local_classes.clone(),
+                }))
+                .collect::<Vec<_>>();
+            let successors = self.step(context, pc, &mut state).map_err(|kind| (pc, kind))?;
+            // Values the instruction pushed without naming their class are of an unknown class
+            state.classes.resize(state.stack.len(), None);
+            for successor in successors {
+                if successor >= context.code.len() {
+                    return Err((pc, VerifyErrorKind::FallsOffEnd));
+                }
+                targets.push((successor, state.clone()));
+            }
+            for (successor, state) in targets {
+                match &mut states[successor] {
+                    Some(existing) => {
+                        if existing.merge(&state, |a, b| self.common_class(&context.class, a, b)).map_err(|kind| (successor, kind))? {
+                            worklist.push_back(successor);
+                        }
+                    }
+                    slot @ None => {
+                        *slot = Some(state);
+                        worklist.push_back(successor);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies one instruction to `state` and returns the instructions that may run next
+    fn step(&self, context: &MethodContext, pc: usize, state: &mut State) -> Result<Vec<usize>, VerifyErrorKind> {
+        use Bytecode as B;
+        let class = &context.class;
+        let jump = |offset: isize| -> Result<usize, VerifyErrorKind> {
+            let target = pc as isize + offset;
+            if target < 0 || target as usize >= context.code.len() {
+                return Err(VerifyErrorKind::JumpOutOfBounds(target));
+            }
+            Ok(target as usize)
+        };
+
+        match context.code[pc] {
+            // Stack Manipulation
+            B::Pop => {
+                pop(state)?;
+            }
+            B::PushNull => state.stack.push(Type::Reference),
+            B::LoadConstant(index) => {
+                let ty = match pool_entry(class, index)? {
+                    PoolEntry::U8(_) => Type::U8,
+                    PoolEntry::U16(_) => Type::U16,
+                    PoolEntry::U32(_) => Type::U32,
+                    PoolEntry::U64(_) => Type::U64,
+                    PoolEntry::I8(_) => Type::I8,
+                    PoolEntry::I16(_) => Type::I16,
+                    PoolEntry::I32(_) => Type::I32,
+                    PoolEntry::I64(_) => Type::I64,
+                    PoolEntry::F32(_) => Type::F32,
+                    PoolEntry::F64(_) => Type::F64,
+                    x => return Err(VerifyErrorKind::WrongPoolEntry {
+                        index,
+                        expected: "numeric constant",
+                        found: x.kind(),
+                    }),
+                };
+                state.stack.push(ty);
+            }
+            B::Dup => {
+                let class = top_class(state);
+                let ty = pop(state)?;
+                push_class(state, ty, class.clone());
+                push_class(state, ty, class);
+            }
+            B::Swap => {
+                let above = top_class(state);
+                let top = pop(state)?;
+                let below = top_class(state);
+                let bottom = pop(state)?;
+                push_class(state, top, above);
+                push_class(state, bottom, below);
+            }
+            // Local Variables
+            B::StoreLocal(index) => {
+                let class = top_class(state);
+                let ty = pop(state)?;
+                state.locals[index as usize] = Some(ty);
+                state.local_classes[index as usize] = class;
+            }
+            B::LoadLocal(index) => {
+                let ty = state.locals[index as usize].ok_or(VerifyErrorKind::UndefinedLocal(index))?;
+                let class = state.local_classes[index as usize].clone();
+                push_class(state, ty, class);
+            }
+            // Arithmetic
+            B::Add | B::Subtract | B::Multiply | B::Divide | B::Modulo => {
+                let ty = pop_pair(state)?;
+                expect_numeric(ty)?;
+                state.stack.push(ty);
+            }
+            B::Negate => {
+                let ty = pop(state)?;
+                expect_numeric(ty)?;
+                state.stack.push(ty);
+            }
+            // Bitwise
+            B::And | B::Or | B::Xor => {
+                let ty = pop_pair(state)?;
+                expect_integer(ty)?;
+                state.stack.push(ty);
+            }
+            B::Not => {
+                let ty = pop(state)?;
+                expect_integer(ty)?;
+                state.stack.push(ty);
+            }
+            B::ShiftLeft | B::ShiftRight => {
+                let ty = pop(state)?;
+                expect_integer(ty)?;
+                let amount = pop(state)?;
+                expect_integer(amount)?;
+                state.stack.push(ty);
+            }
+            // Comparison leaves both operands on the stack and pushes the result above them
+            B::Equal | B::Greater | B::Less => {
+                let ty = pop_pair(state)?;
+                expect_numeric(ty)?;
+                state.stack.push(ty);
+                state.stack.push(ty);
+                state.stack.push(Type::I8);
+            }
+            // Conversion
+            B::Convert(ty) => {
+                let from = pop(state)?;
+                expect_numeric(from)?;
+                expect_numeric(ty)?;
+                state.stack.push(ty);
+            }
+            B::BinaryConvert(ty) => {
+                let from = pop(state)?;
+                expect_numeric(from)?;
+                expect_numeric(ty)?;
+                if type_size(from) != type_size(ty) {
+                    return Err(VerifyErrorKind::TypeMismatch { expected: from, found: ty });
+                }
+                state.stack.push(ty);
+            }
+            // Control Flow
+            B::Goto(offset) => return Ok(vec![jump(offset)?]),
+            B::If(offset) | B::IfNot(offset) | B::IfGreater(offset) | B::IfGreaterEqual(offset) | B::IfLess(offset) | B::IfLessEqual(offset) => {
+                pop_expect(state, Type::I8)?;
+                return Ok(vec![pc + 1, jump(offset)?]);
+            }
+            B::IfNull(offset) | B::IfNotNull(offset) => {
+                peek_expect(state, Type::Reference)?;
+                return Ok(vec![pc + 1, jump(offset)?]);
+            }
+            B::InvokeVirtual(method_index) => {
+                peek_expect(state, Type::Reference)?;
+                let receiver = self.receiver_class(class, top_class(state), method_index)?;
+                let (target, method_index) = self.virtual_method(&receiver, method_index)?;
+                self.apply_call(state, &target, method_index)?;
+            }
+            B::InvokeVirtualTail(method_index) => {
+                peek_expect(state, Type::Reference)?;
+                let receiver = self.receiver_class(class, top_class(state), method_index)?;
+                let (target, method_index) = self.virtual_method(&receiver, method_index)?;
+                return self.apply_tail_call(context, state, &target, method_index);
+            }
+            B::InvokeStatic(class_index, method_index) => {
+                let target = self.resolve_class(class, class_index)?;
+                self.apply_call(state, &target, method_index)?;
+            }
+            B::InvokeStaticTail(class_index, method_index) => {
+                let target = self.resolve_class(class, class_index)?;
+                return self.apply_tail_call(context, state, &target, method_index);
+            }
+            B::InvokeInterface(interface_index, method_index) => {
+                peek_expect(state, Type::Reference)?;
+                let interface = self.resolve_class(class, interface_index)?;
+                self.apply_call(state, &interface, method_index)?;
+            }
+            B::InvokeInterfaceTail(interface_index, method_index) => {
+                peek_expect(state, Type::Reference)?;
+                let interface = self.resolve_class(class, interface_index)?;
+                return self.apply_tail_call(context, state, &interface, method_index);
+            }
+            B::InvokeInterfaceStatic(class_index, interface_index, method_index) => {
+                self.resolve_class(class, class_index)?;
+                let interface = self.resolve_class(class, interface_index)?;
+                self.apply_call(state, &interface, method_index)?;
+            }
+            B::InvokeInterfaceStaticTail(class_index, interface_index, method_index) => {
+                self.resolve_class(class, class_index)?;
+                let interface = self.resolve_class(class, interface_index)?;
+                return self.apply_tail_call(context, state, &interface, method_index);
+            }
+            B::Return => {
+                let found = state.stack.pop();
+                match (stack_type(context.ret), found) {
+                    (Some(expected), Some(found)) if expected == found => {}
+                    _ => return Err(VerifyErrorKind::ReturnTypeMismatch {
+                        expected: context.ret.clone(),
+                        found,
+                    }),
+                }
+                return Ok(Vec::new());
+            }
+            B::Throw => {
+                pop_expect(state, Type::Reference)?;
+                return Ok(Vec::new());
+            }
+            B::ReturnUnit => {
+                if *context.ret != TypeInfo::Unit {
+                    return Err(VerifyErrorKind::ReturnTypeMismatch {
+                        expected: context.ret.clone(),
+                        found: None,
+                    });
+                }
+                return Ok(Vec::new());
+            }
+            // Object Related
+            B::New(class_index) => {
+                let name = expect_class_info(class, class_index)?;
+                push_class(state, Type::Reference, Some(name));
+            }
+            B::SetField(class_index, field_index) => {
+                let declaring = self.resolve_class(class, class_index)?;
+                let ty = field_type(&declaring, field_index)?;
+                let receiver = top_class(state);
+                self.check_receiver(class, &declaring, receiver.clone())?;
+                pop_expect(state, Type::Reference)?;
+                pop_expect(state, ty)?;
+                push_class(state, Type::Reference, receiver);
+            }
+            B::GetField(class_index, field_index) => {
+                let declaring = self.resolve_class(class, class_index)?;
+                let ty = field_type(&declaring, field_index)?;
+                let receiver = top_class(state);
+                self.check_receiver(class, &declaring, receiver.clone())?;
+                pop_expect(state, Type::Reference)?;
+                push_class(state, ty, field_class(&declaring, field_index));
+                push_class(state, Type::Reference, receiver);
+            }
+            B::StoreStatic(field_index) => {
+                let ty = field_type(class, field_index)?;
+                if !class.get_field(field_index).flags.contains(FieldFlags::Static) {
+                    return Err(VerifyErrorKind::StoreToNonStatic(field_index));
+                }
+                pop_expect(state, ty)?;
+            }
+            B::LoadStatic(field_index) => {
+                let ty = field_type(class, field_index)?;
+                push_class(state, ty, field_class(class, field_index));
+            }
+            B::InstanceOf(index) => {
+                match pool_entry(class, index)? {
+                    PoolEntry::TypeInfo(_) => expect_array_type(class, index)?,
+                    _ => {
+                        expect_class_info(class, index)?;
+                    }
+                }
+                peek_expect(state, Type::Reference)?;
+                state.stack.push(Type::I8);
+            }
+            B::GetParent => {
+                peek_expect(state, Type::Reference)?;
+                let parent = self.known_class(class, top_class(state))
+                    .and_then(|child| expect_class_info(&child, child.get_parent_info()).ok());
+                push_class(state, Type::Reference, parent);
+            }
+            // Array Related
+            B::NewArray(ty) => {
+                expect_element(ty)?;
+                pop_expect(state, Type::U64)?;
+                push_class(state, Type::Reference, Some(ARRAY_CLASS.to_string()));
+            }
+            B::NewTypedArray(index) => {
+                expect_array_type(class, index)?;
+                pop_expect(state, Type::U64)?;
+                push_class(state, Type::Reference, Some(ARRAY_CLASS.to_string()));
+            }
+            B::ArrayGet(ty) => {
+                expect_element(ty)?;
+                pop_expect(state, Type::U64)?;
+                pop_expect(state, Type::Reference)?;
+                state.stack.push(Type::Reference);
+                state.stack.push(ty);
+            }
+            B::ArraySet(ty) => {
+                expect_element(ty)?;
+                pop_expect(state, Type::U64)?;
+                pop_expect(state, Type::Reference)?;
+                pop_expect(state, ty)?;
+                state.stack.push(Type::Reference);
+            }
+            // String Related
+            B::NewString(string_index) => {
+                if string_index >= class.strings().len() {
+                    return Err(VerifyErrorKind::InvalidStringIndex(string_index));
+                }
+                let index = class.get_string(string_index);
+                match pool_entry(class, index)? {
+                    PoolEntry::String(_) => {}
+                    x => return Err(VerifyErrorKind::WrongPoolEntry {
+                        index,
+                        expected: "String",
+                        found: x.kind(),
+                    }),
+                }
+                state.stack.push(Type::Reference);
+            }
+            // Misc
+            B::Breakpoint | B::Nop => {}
+        }
+        Ok(vec![pc + 1])
+    }
+
+    fn resolve_class(&self, class: &ClassHeader, index: PoolIndex) -> Result<ClassHeader, VerifyErrorKind> {
+        let name = expect_class_info(class, index)?;
+        if Some(&name) == class_name(class).as_ref() {
+            return Ok(*class);
+        }
+        self.classes.get(&name).copied().ok_or(VerifyErrorKind::UnknownClass(name))
+    }
+
+    /// The class named by a tracked class name, if the name and the class are known
+    fn known_class(&self, class: &ClassHeader, name: Option<String>) -> Option<ClassHeader> {
+        let name = name?;
+        if Some(&name) == class_name(class).as_ref() {
+            return Some(*class);
+        }
+        self.classes.get(&name).copied()
+    }
+
+    /// The class of the receiver of a virtual call, which has to be known to find the method that is called
+    fn receiver_class(&self, class: &ClassHeader, name: Option<String>, method_index: usize) -> Result<ClassHeader, VerifyErrorKind> {
+        let name = name.ok_or(VerifyErrorKind::UnknownReceiver(method_index))?;
+        if Some(&name) == class_name(class).as_ref() {
+            return Ok(*class);
+        }
+        self.classes.get(&name).copied().ok_or(VerifyErrorKind::UnknownClass(name))
+    }
+
+    /// The closest class that both classes are or extend, if their parents are known far enough to find it
+    fn common_class(&self, class: &ClassHeader, a: &str, b: &str) -> Option<String> {
+        let ancestors = self.ancestors(class, a);
+        self.ancestors(class, b).into_iter().find(|name| ancestors.contains(name))
+    }
+
+    /// A class followed by its parents, up to the root or the first class that isn't known
+    fn ancestors(&self, class: &ClassHeader, name: &str) -> Vec<String> {
+        let mut ancestors = vec![name.to_string()];
+        while let Some(header) = self.known_class(class, ancestors.last().cloned()) {
+            let Ok(parent) = expect_class_info(&header, header.get_parent_info()) else {
+                break;
+            };
+            if ancestors.contains(&parent) {
+                break;
+            }
+            ancestors.push(parent);
+        }
+        ancestors
+    }
+
+    /// Checks that a receiver of a known class is the declaring class or one of its subclasses
+    ///
+    /// Receivers of unknown classes, or with a parent that is not known, are left to the machine to check.
+    fn check_receiver(&self, class: &ClassHeader, declaring: &ClassHeader, receiver: Option<String>) -> Result<(), VerifyErrorKind> {
+        let Some(expected) = class_name(declaring) else {
+            return Ok(());
+        };
+        let Some(found) = receiver else {
+            return Ok(());
+        };
+        let mut current = found.clone();
+        let mut visited = Vec::new();
+        while current != expected {
+            let Some(header) = self.known_class(class, Some(current.clone())) else {
+                return Ok(());
+            };
+            let Ok(parent) = expect_class_info(&header, header.get_parent_info()) else {
+                return Ok(());
+            };
+            // The root class is its own parent
+            if parent == current {
+                return Err(VerifyErrorKind::ReceiverMismatch { expected, found });
+            }
+            if visited.contains(&parent) {
+                return Ok(());
+            }
+            visited.push(current);
+            current = parent;
+        }
+        Ok(())
+    }
+
+    /// Looks up a vtable slot the same way the linker lays it out
+    fn virtual_method(&self, class: &ClassHeader, index: usize) -> Result<(ClassHeader, usize), VerifyErrorKind> {
+        let mut visited = Vec::new();
+        self.vtable(class, &mut visited).get(index).map(|(class, method, _)| (*class, *method)).ok_or(VerifyErrorKind::InvalidMethodIndex(index))
+    }
+
+    /// The vtable of an unlinked class as the declaring class, method index and name and signature of each slot
+    ///
+    /// Parents that are not known are treated as having no methods, the linker reports them as missing.
+    fn vtable(&self, class: &ClassHeader, visited: &mut Vec<String>) -> Vec<(ClassHeader, usize, String)> {
+        let name = class_name(class).unwrap_or_default();
+        if visited.contains(&name) {
+            return Vec::new();
+        }
+        visited.push(name);
+
+        let mut vtable = match expect_class_info(class, class.get_parent_info()).ok().and_then(|parent| self.classes.get(&parent)) {
+            Some(parent) => self.vtable(parent, visited),
+            None => Vec::new(),
+        };
+        for (index, method_info) in class.methods().iter().enumerate() {
+            if let Some(PoolEntry::Method(Method::Foreign(_))) = class.constants().get(method_info.location) {
+                continue;
+            }
+            let Some(PoolEntry::TypeInfo(type_info)) = class.constants().get(method_info.type_info) else {
+                continue;
+            };
+            let key = format!("{} {}", string_at(class, method_info.name).unwrap_or_default(), type_info.describe(&|index| object_name(class, index)));
+            match vtable.iter().position(|(_, _, slot)| *slot == key) {
+                Some(slot) => vtable[slot] = (*class, index, key),
+                None => vtable.push((*class, index, key)),
+            }
+        }
+        vtable
+    }
+
+    fn signature<'a>(&self, target: &'a ClassHeader, method_index: usize) -> Result<(&'a [TypeInfo], &'a TypeInfo), VerifyErrorKind> {
+        if method_index >= target.methods_count() {
+            return Err(VerifyErrorKind::InvalidMethodIndex(method_index));
+        }
+        let type_info = target.get_method(method_index).type_info;
+        match pool_entry(target, type_info)? {
+            PoolEntry::TypeInfo(TypeInfo::Method { args, ret }) => Ok((args, ret)),
+            _ => Err(VerifyErrorKind::NotAMethodType),
+        }
+    }
+
+    fn apply_call(&self, state: &mut State, target: &ClassHeader, method_index: usize) -> Result<(), VerifyErrorKind> {
+        let (args, ret) = self.signature(target, method_index)?;
+        for arg in args {
+            let ty = stack_type(arg).ok_or_else(|| VerifyErrorKind::UnsupportedType(arg.clone()))?;
+            pop_expect(state, ty)?;
+        }
+        if let Some(ty) = stack_type(ret) {
+            push_class(state, ty, object_class(target, ret));
+        }
+        Ok(())
+    }
+
+    /// A tail call replaces the current frame, so the callee must return what this method returns
+    fn apply_tail_call(&self, context: &MethodContext, state: &mut State, target: &ClassHeader, method_index: usize) -> Result<Vec<usize>, VerifyErrorKind> {
+        self.apply_call(state, target, method_index)?;
+        let (_, ret) = self.signature(target, method_index)?;
+        if stack_type(ret) != stack_type(context.ret) {
+            return Err(VerifyErrorKind::ReturnTypeMismatch {
+                expected: context.ret.clone(),
+                found: stack_type(ret),
+            });
+        }
+        Ok(Vec::new())
+    }
+}
+
+fn pool_entry(class: &ClassHeader, index: PoolIndex) -> Result<&PoolEntry, VerifyErrorKind> {
+    class.constants().get(index).ok_or(VerifyErrorKind::InvalidPoolIndex(index))
+}
+
+fn string_at(class: &ClassHeader, index: PoolIndex) -> Option<String> {
+    match class.constants().get(index) {
+        Some(PoolEntry::String(string)) => Some(string.clone()),
+        _ => None,
+    }
+}
+
+fn class_name(class: &ClassHeader) -> Option<String> {
+    match class.constants().get(class.get_this_info()) {
+        Some(PoolEntry::ClassInfo(info)) => string_at(class, info.name),
+        _ => None,
+    }
+}
+
+/// The class name an object type of an unlinked class refers to
+fn object_name(class: &ClassHeader, index: PoolIndex) -> String {
+    match class.constants().get(index) {
+        Some(PoolEntry::ClassInfo(info)) => string_at(class, info.name),
+        Some(PoolEntry::String(name)) => Some(name.clone()),
+        _ => None,
+    }.unwrap_or_else(|| format!("#{}", index))
+}
+
+/// Checks that `index` is a `ClassInfo` and returns the name it points at
+fn expect_class_info(class: &ClassHeader, index: PoolIndex) -> Result<String, VerifyErrorKind> {
+    let info = match pool_entry(class, index)? {
+        PoolEntry::ClassInfo(info) => info,
+        x => return Err(VerifyErrorKind::WrongPoolEntry {
+            index,
+            expected: "ClassInfo",
+            found: x.kind(),
+        }),
+    };
+    match pool_entry(class, info.name)? {
+        PoolEntry::String(name) => Ok(name.clone()),
+        x => Err(VerifyErrorKind::WrongPoolEntry {
+            index: info.name,
+            expected: "String",
+            found: x.kind(),
+        }),
+    }
+}
+
+/// Checks that `index` is the `TypeInfo` of an array of references and that the classes it names are `ClassInfo`s
+fn expect_array_type(class: &ClassHeader, index: PoolIndex) -> Result<(), VerifyErrorKind> {
+    let element = match pool_entry(class, index)? {
+        PoolEntry::TypeInfo(TypeInfo::Array(element)) => element,
+        x => return Err(VerifyErrorKind::WrongPoolEntry {
+            index,
+            expected: "TypeInfo",
+            found: x.kind(),
+        }),
+    };
+    if stack_type(element) != Some(Type::Reference) {
+        return Err(VerifyErrorKind::UnsupportedType((**element).clone()));
+    }
+    let mut element = &**element;
+    loop {
+        match element {
+            TypeInfo::Object(index) => {
+                expect_class_info(class, *index)?;
+                return Ok(());
+            }
+            TypeInfo::Array(inner) => element = inner,
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// The class of an object type, `None` for other types or if the class info is malformed
+fn object_class(class: &ClassHeader, info: &TypeInfo) -> Option<String> {
+    match info {
+        TypeInfo::Object(index) => expect_class_info(class, *index).ok(),
+        _ => None,
+    }
+}
+
+/// The class of a field holding objects, see `object_class`
+fn field_class(class: &ClassHeader, field_index: usize) -> Option<String> {
+    match class.constants().get(class.fields().get(field_index)?.type_info) {
+        Some(PoolEntry::TypeInfo(info)) => object_class(class, info),
+        _ => None,
+    }
+}
+
+fn field_type(class: &ClassHeader, field_index: usize) -> Result<Type, VerifyErrorKind> {
+    if field_index >= class.fields_count() {
+        return Err(VerifyErrorKind::InvalidFieldIndex(field_index));
+    }
+    let index = class.get_field(field_index).type_info;
+    match pool_entry(class, index)? {
+        PoolEntry::TypeInfo(info) => stack_type(info).ok_or_else(|| VerifyErrorKind::UnsupportedType(info.clone())),
+        x => Err(VerifyErrorKind::WrongPoolEntry {
+            index,
+            expected: "TypeInfo",
+            found: x.kind(),
+        }),
+    }
+}
+
+/// The operand stack type used to hold a value of the given type, if it has one
+fn stack_type(info: &TypeInfo) -> Option<Type> {
+    match info {
+        TypeInfo::Unit | TypeInfo::Method { .. } => None,
+        TypeInfo::U8 | TypeInfo::Bool => Some(Type::U8),
+        TypeInfo::U16 => Some(Type::U16),
+        TypeInfo::U32 => Some(Type::U32),
+        TypeInfo::U64 => Some(Type::U64),
+        TypeInfo::I8 => Some(Type::I8),
+        TypeInfo::I16 => Some(Type::I16),
+        TypeInfo::I32 => Some(Type::I32),
+        TypeInfo::I64 => Some(Type::I64),
+        TypeInfo::F32 => Some(Type::F32),
+        TypeInfo::F64 => Some(Type::F64),
+        TypeInfo::Char => Some(Type::Char(4)),
+        TypeInfo::String | TypeInfo::Array(_) | TypeInfo::Object(_) => Some(Type::Reference),
+    }
+}
+
+fn type_size(ty: Type) -> usize {
+    match ty {
+        Type::U8 | Type::I8 => 1,
+        Type::U16 | Type::I16 => 2,
+        Type::U32 | Type::I32 | Type::F32 => 4,
+        Type::U64 | Type::I64 | Type::F64 | Type::Reference => 8,
+        Type::Char(size) => size as usize,
+    }
+}
+
+fn pop(state: &mut State) -> Result<Type, VerifyErrorKind> {
+    let ty = state.stack.pop().ok_or(VerifyErrorKind::StackUnderflow)?;
+    state.classes.truncate(state.stack.len());
+    Ok(ty)
+}
+
+/// Pushes a value along with its class, if it is known
+fn push_class(state: &mut State, ty: Type, class: Option<String>) {
+    state.classes.resize(state.stack.len(), None);
+    state.stack.push(ty);
+    state.classes.push(class);
+}
+
+/// The class of the value on top of the stack, if it is known
+fn top_class(state: &State) -> Option<String> {
+    state.classes.get(state.stack.len().checked_sub(1)?).cloned().flatten()
+}
+
+fn pop_expect(state: &mut State, expected: Type) -> Result<(), VerifyErrorKind> {
+    let found = pop(state)?;
+    if found != expected {
+        return Err(VerifyErrorKind::TypeMismatch { expected, found });
+    }
+    Ok(())
+}
+
+fn peek_expect(state: &mut State, expected: Type) -> Result<(), VerifyErrorKind> {
+    match state.stack.last() {
+        Some(&found) if found != expected => Err(VerifyErrorKind::TypeMismatch { expected, found }),
+        Some(_) => Ok(()),
+        None => Err(VerifyErrorKind::StackUnderflow),
+    }
+}
+
+/// Pops two values of the same type and returns that type
+fn pop_pair(state: &mut State) -> Result<Type, VerifyErrorKind> {
+    let top = pop(state)?;
+    let bottom = pop(state)?;
+    if top != bottom {
+        return Err(VerifyErrorKind::TypeMismatch { expected: top, found: bottom });
+    }
+    Ok(top)
+}
+
+fn expect_numeric(ty: Type) -> Result<(), VerifyErrorKind> {
+    match ty {
+        Type::Reference | Type::Char(_) => Err(VerifyErrorKind::ExpectedNumeric(ty)),
+        _ => Ok(()),
+    }
+}
+
+fn expect_integer(ty: Type) -> Result<(), VerifyErrorKind> {
+    match ty {
+        Type::Reference | Type::Char(_) | Type::F32 | Type::F64 => Err(VerifyErrorKind::ExpectedInteger(ty)),
+        _ => Ok(()),
+    }
+}
+
+fn expect_element(ty: Type) -> Result<(), VerifyErrorKind> {
+    match ty {
+        Type::Char(_) => Err(VerifyErrorKind::ExpectedNumeric(ty)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use definitions::{assembler::assemble, class::{ClassInfo, MethodFlags, MethodInfo}};
+
+    fn class_with_code(code: Vec<Bytecode>, ret: TypeInfo) -> ClassHeader {
+        let mut class = ClassHeader::new(8, 0, 0, 1, 0);
+
+        class.set_parent_info(1);
+        class.set_this_info(0);
+
+        class.set_constant_pool_entry(0, PoolEntry::ClassInfo(ClassInfo {
+            name: 2,
+            class_ref: None,
+        }));
+        class.set_constant_pool_entry(1, PoolEntry::ClassInfo(ClassInfo {
+            name: 3,
+            class_ref: None,
+        }));
+        class.set_constant_pool_entry(2, PoolEntry::String("Main".to_owned()));
+        class.set_constant_pool_entry(3, PoolEntry::String("cocoa.lang.Object".to_owned()));
+        class.set_constant_pool_entry(4, PoolEntry::Method(Method::Bytecode(code.into())));
+        class.set_constant_pool_entry(5, PoolEntry::TypeInfo(TypeInfo::Method { args: vec![TypeInfo::I32], ret: Box::new(ret) }));
+        class.set_constant_pool_entry(6, PoolEntry::I32(1));
+        class.set_constant_pool_entry(7, PoolEntry::U64(1));
+
+        class.set_method(0, MethodInfo {
+            flags: MethodFlags::Static,
+            name: 2,
+            type_info: 5,
+            location: 4,
+        });
+        class
+    }
+
+    fn verify(code: Vec<Bytecode>, ret: TypeInfo) -> Result<(), VerifyErrorKind> {
+        let class = class_with_code(code, ret);
+        let result = Verifier::new(&[class]).verify_class(&class).map_err(|error| error.kind);
+        let mut class = class;
+        class.deallocate();
+        result
+    }
+
+    #[test]
+    fn test_accepts_loop() {
+        let code = vec![
+            Bytecode::LoadLocal(0),
+            Bytecode::LoadConstant(6),
+            Bytecode::Add,
+            Bytecode::StoreLocal(0),
+            Bytecode::LoadLocal(0),
+            Bytecode::LoadConstant(6),
+            Bytecode::Less,
+            Bytecode::StoreLocal(1),
+            Bytecode::Pop,
+            Bytecode::Pop,
+            Bytecode::LoadLocal(1),
+            Bytecode::IfNot(-11),
+            Bytecode::LoadLocal(0),
+            Bytecode::Return,
+        ];
+        assert_eq!(verify(code, TypeInfo::I32), Ok(()));
+    }
+
+    #[test]
+    fn test_rejects_type_mismatch() {
+        let code = vec![Bytecode::LoadLocal(0), Bytecode::LoadConstant(7), Bytecode::Add, Bytecode::Return];
+        assert_eq!(verify(code, TypeInfo::I32), Err(VerifyErrorKind::TypeMismatch { expected: Type::U64, found: Type::I32 }));
+    }
+
+    #[test]
+    fn test_rejects_bad_jump() {
+        let code = vec![Bytecode::Goto(5), Bytecode::ReturnUnit];
+        assert_eq!(verify(code, TypeInfo::Unit), Err(VerifyErrorKind::JumpOutOfBounds(5)));
+    }
+
+    #[test]
+    fn test_rejects_wrong_pool_entry() {
+        let code = vec![Bytecode::New(6), Bytecode::ReturnUnit];
+        assert_eq!(verify(code, TypeInfo::Unit), Err(VerifyErrorKind::WrongPoolEntry { index: 6, expected: "ClassInfo", found: "I32" }));
+    }
+
+    #[test]
+    fn test_rejects_untyped_array_type() {
+        let code = vec![Bytecode::LoadConstant(7), Bytecode::NewTypedArray(6), Bytecode::Pop, Bytecode::ReturnUnit];
+        assert_eq!(verify(code, TypeInfo::Unit), Err(VerifyErrorKind::WrongPoolEntry { index: 6, expected: "TypeInfo", found: "I32" }));
+    }
+
+    #[test]
+    fn test_rejects_wrong_return() {
+        let code = vec![Bytecode::LoadConstant(7), Bytecode::Return];
+        assert_eq!(verify(code, TypeInfo::I32), Err(VerifyErrorKind::ReturnTypeMismatch { expected: TypeInfo::I32, found: Some(Type::U64) }));
+    }
+
+    #[test]
+    fn test_rejects_falling_off_end() {
+        let code = vec![Bytecode::Nop];
+        assert_eq!(verify(code, TypeInfo::Unit), Err(VerifyErrorKind::FallsOffEnd));
+    }
+
+    #[test]
+    fn test_rejects_undefined_local() {
+        let code = vec![Bytecode::LoadLocal(3), Bytecode::Return];
+        assert_eq!(verify(code, TypeInfo::I32), Err(VerifyErrorKind::UndefinedLocal(3)));
+    }
+
+    /// `Point` and an unrelated `Other` below a root `cocoa.lang.Object`, and a `Main` whose main method runs `code`
+    fn receiver_classes(code: &str) -> Vec<ClassHeader> {
+        let object = assemble(r#"
+            .class this this
+            .const this class name
+            .const name string "cocoa.lang.Object"
+        "#).unwrap();
+        let point = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "Point"
+            .const parent_name string "cocoa.lang.Object"
+            .const x string "x"
+            .const get string "get"
+            .const i64_type type i64
+            .const get_type type fn(object(this)) -> i64
+            .const get_code bytecode
+                LoadLocal 0
+                GetField this 0
+                Pop
+                Return
+            .end
+            .field x i64_type
+            .method get get_type get_code Public
+        "#).unwrap();
+        let other = assemble(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "Other"
+            .const parent_name string "cocoa.lang.Object"
+        "#).unwrap();
+        let main = assemble(&format!(r#"
+            .class this parent
+            .const this class name
+            .const parent class parent_name
+            .const name string "Main"
+            .const parent_name string "cocoa.lang.Object"
+            .const point_name string "Point"
+            .const point class point_name
+            .const other_name string "Other"
+            .const other class other_name
+            .const helper string "helper"
+            .const main string "Main"
+            .const flag i8 1
+            .const helper_type type fn(i32) -> unit
+            .const main_type type fn() -> i64
+            .const helper_code bytecode
+                ReturnUnit
+            .end
+            .const main_code bytecode
+                {code}
+            .end
+            .method helper helper_type helper_code Public Static
+            .method main main_type main_code Public Static
+        "#)).unwrap();
+        vec![main, point, other, object]
+    }
+
+    fn verify_main(code: &str) -> Result<(), VerifyErrorKind> {
+        let classes = receiver_classes(code);
+        Verifier::new(&classes).verify_class(&classes[0]).map_err(|error| error.kind)
+    }
+
+    #[test]
+    fn test_checks_receiver_class() {
+        // Main's own first slot is helper, which takes an i32, the call is checked against Point's get instead
+        assert_eq!(verify_main("New point\n InvokeVirtual 0\n Return"), Ok(()));
+        assert_eq!(verify_main("New point\n InvokeVirtual 1\n Return"), Err(VerifyErrorKind::InvalidMethodIndex(1)));
+
+        // A field is only accessed on instances of the class that declares it
+        assert_eq!(verify_main("New point\n GetField point 0\n Pop\n Return"), Ok(()));
+        assert_eq!(verify_main("New other\n GetField point 0\n Pop\n Return"), Err(VerifyErrorKind::ReceiverMismatch {
+            expected: String::from("Point"),
+            found: String::from("Other"),
+        }));
+    }
+
+    #[test]
+    fn test_rejects_unknown_receiver() {
+        // The call pops the receiver and pushes get's i64, the path goes on past it to the return with nothing left
+        assert_eq!(verify_main("New point\n InvokeVirtual 0\n Pop\n Return"), Err(VerifyErrorKind::ReturnTypeMismatch {
+            expected: TypeInfo::I64,
+            found: None,
+        }));
+
+        // Nothing is known about null, so there is no method to check the call against
+        assert_eq!(verify_main("PushNull\n InvokeVirtual 0\n Return"), Err(VerifyErrorKind::UnknownReceiver(0)));
+
+        // Point and Other only share Object, which has no methods
+        assert_eq!(verify_main("LoadConstant flag\n If 3\n New point\n Goto 2\n New other\n InvokeVirtual 0\n Return"), Err(VerifyErrorKind::InvalidMethodIndex(0)));
+    }
+}